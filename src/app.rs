@@ -2,12 +2,13 @@ use std::{ops::DerefMut, sync::Mutex};
 
 use crate::{
     emulator::Emulator,
-    panes::{EmulatorPane, Pane, PaneDisplay, PaneTree, RealPane},
+    panes::{EmulatorPane, Pane, PaneDisplay, PaneTree, RealPane, NEXT_ID},
     theme::{BaseThemeChoice, ThemeSettings},
 };
 use egui::Theme;
 use egui_dock::{AllowedSplits, DockArea, DockState, NodeIndex, Style, SurfaceIndex, TabViewer};
 use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
 
 lazy_static! {
     pub static ref EMULATOR: Mutex<Emulator> = Mutex::new(Emulator::new());
@@ -132,23 +133,38 @@ impl PaneManager {
 
 /// This is the core app state and pretty much everything involved with the ui comes though
 /// here as well as pane stuff like what is vcurrently put in the editor.
+///
+/// Everything not skipped here is saved by eframe on shutdown (and every so often) then loaded
+/// again in [EmulatorApp::new]. On the web eframe puts it in local storage.
+#[derive(Serialize, Deserialize)]
+#[serde(default)] // so adding a field doesn't throw away everyone's saved state
 pub struct EmulatorApp {
     /// This stores the tree of panes (pretty much the entire pane state)
     dock_state: DockState<Pane>,
     /// This struct provides interface between out pane tree and actual things like
     /// render and title. See [PaneManager]
+    #[serde(skip)]
     tree_behavior: PaneManager,
 
     #[cfg(target_arch = "wasm32")]
+    #[serde(skip)]
     /// Have we clicked ok on the fps warning? This will mean it does not spawn for the rest of the session
     has_dismissed_fps: bool,
     #[cfg(target_arch = "wasm32")]
+    #[serde(skip)]
     /// Used as a meter for how bad the fps is at the moment. Higher is worse.
     bad_fps_score: u32,
     #[cfg(target_arch = "wasm32")]
+    #[serde(skip)]
     /// Is the bad fps prompt open?
     curr_bad_fps_prompt_open: bool,
+    #[cfg(target_arch = "wasm32")]
+    #[serde(skip)]
+    /// Was the emulator running last frame, so we paint once more after it stops
+    emulator_was_running: bool,
     theme: ThemeSettings,
+    /// The breakpoints live in the emulator, this is just a copy so they get saved with everything else
+    breakpoints: Vec<usize>,
 }
 
 impl Default for EmulatorApp {
@@ -206,7 +222,10 @@ impl Default for EmulatorApp {
             bad_fps_score: 0,
             #[cfg(target_arch = "wasm32")]
             curr_bad_fps_prompt_open: false,
+            #[cfg(target_arch = "wasm32")]
+            emulator_was_running: false,
             theme,
+            breakpoints: Vec::new(),
         }
     }
 }
@@ -217,26 +236,105 @@ impl EmulatorApp {
         let span = tracing::info_span!("EmulatorApp::new");
         let _guard = span.enter();
 
-        let mut app = Self::default();
+        let restored = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<Self>(storage, eframe::APP_KEY));
+
+        let Some(mut app) = restored else {
+            tracing::info!("No saved state, starting fresh");
+            let mut app = Self::default();
+            app.theme
+                .set_global_theme(BaseThemeChoice::Dark, Some(&cc.egui_ctx));
+            app.tree_behavior.theme = app.theme.clone();
+            return app;
+        };
+        tracing::info!("Restored saved app state");
 
-        app.theme
-            .set_global_theme(BaseThemeChoice::Dark, Some(&cc.egui_ctx));
+        // Restored panes keep their ids so make sure new panes don't reuse one
+        if let Some(max_id) = app.dock_state.iter_all_tabs().map(|(_, tab)| tab.id).max() {
+            let mut next_id = NEXT_ID.lock();
+            *next_id = (*next_id).max(max_id + 1);
+        }
+
+        let mut style = (*cc.egui_ctx.style()).clone();
+        app.theme.apply_to_style(&mut style);
+        cc.egui_ctx.set_style(style);
         app.tree_behavior.theme = app.theme.clone();
+
+        EMULATOR
+            .lock()
+            .unwrap()
+            .breakpoints
+            .extend(app.breakpoints.iter().copied());
+
+        // the custom instruction lives outside the panes, programs using it need it before they assemble
+        for (_, tab) in app.dock_state.iter_all_tabs_mut() {
+            if let RealPane::EmulatorPanes(pane) = &mut tab.inner {
+                if let EmulatorPane::CustomOp(custom_op) = pane.as_mut() {
+                    custom_op.restore();
+                }
+            }
+        }
+
         app
     }
+
+    /// Put the layout back to how it is on first launch.
+    /// The program in the editor and the theme are kept, you would not want to lose those to a messed up layout.
+    fn reset_layout(&mut self) {
+        let editor = self
+            .dock_state
+            .iter_all_tabs()
+            .find_map(|(_, tab)| match &tab.inner {
+                RealPane::EmulatorPanes(pane) => match pane.as_ref() {
+                    EmulatorPane::Editor(editor) => Some(editor.clone()),
+                    _ => None,
+                },
+                _ => None,
+            });
+
+        let mut fresh = Self::default();
+        if let Some(editor) = editor {
+            for (_, tab) in fresh.dock_state.iter_all_tabs_mut() {
+                if let RealPane::EmulatorPanes(pane) = &mut tab.inner {
+                    if let EmulatorPane::Editor(fresh_editor) = pane.as_mut() {
+                        *fresh_editor = editor.clone();
+                    }
+                }
+            }
+        }
+
+        self.dock_state = fresh.dock_state;
+        self.tree_behavior.added_nodes.clear();
+        self.tree_behavior.last_added = None;
+    }
 }
 
 impl eframe::App for EmulatorApp {
     /// Called by the frame work to save state before shutdown.
-    fn save(&mut self, _storage: &mut dyn eframe::Storage) {} // TODO: implement save logic
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        // the theme editor changes the copy in the tree behavior
+        self.theme = self.tree_behavior.theme.clone();
+        self.breakpoints = EMULATOR
+            .lock()
+            .unwrap()
+            .breakpoints
+            .iter()
+            .copied()
+            .collect();
+        self.breakpoints.sort_unstable();
+
+        eframe::set_value(storage, eframe::APP_KEY, self);
+    }
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
         let update_span = tracing::info_span!("EmulatorApp::update");
         let _update_guard = update_span.enter();
 
+        // only judge fps while running, we don't paint constantly when idle
         #[cfg(target_arch = "wasm32")]
-        if !self.has_dismissed_fps {
+        if !self.has_dismissed_fps && self.emulator_was_running {
             use std::cmp::max;
             // This gets the fps as the number we know and love (1s/xdt gets the amount of x we need to reach 1s (frames per second)).
             // (This is ONLY accruate for the web becuase we use a constant framerate (we call `ctx.request_repaint()`))
@@ -284,11 +382,25 @@ impl eframe::App for EmulatorApp {
                 }
                 ui.menu_button("Windows", |ui| {
                     if ui
-                        .button("Reset Layout, REMOVES ALL PANE STATE!!!")
+                        .button("Reset Layout")
+                        .on_hover_text("Put the panes back how they were on first launch. Your program and theme are kept.")
                         .clicked()
                     {
                         tracing::info!("Resetting layout to default");
-                        *self = Self::default(); // Reset the entire app state TODO: Keep some state? Mabye we find the last used for each pane then preserve it when recreating the layout
+                        self.reset_layout();
+                        ui.close();
+                    }
+                    if ui
+                        .button("Reset Everything, REMOVES ALL SAVED STATE!!!")
+                        .clicked()
+                    {
+                        tracing::info!("Resetting app state to default");
+                        *self = Self::default();
+                        self.theme
+                            .set_global_theme(self.theme.base_theme, Some(ctx));
+                        self.tree_behavior.theme = self.theme.clone();
+                        EMULATOR.lock().unwrap().breakpoints.clear();
+                        ui.close();
                     }
                 });
 
@@ -352,7 +464,16 @@ impl eframe::App for EmulatorApp {
         {
             *LAST_PAINT_ID.lock().unwrap() = ctx.cumulative_pass_nr_for(egui::ViewportId::ROOT);
         }
+        // I could not find a way to repaint on change on the wasm backend without forking eframe.
+        // The emulator runs off its own animation frame loop, so we just keep painting while it is running
+        // (sitting idle at 60fps pegged a core)
         #[cfg(target_arch = "wasm32")]
-        ctx.request_repaint(); // I could not find a way to repaint on change on the wasm backend without forking eframe
+        {
+            let running = EMULATOR.lock().unwrap().running();
+            if running || self.emulator_was_running {
+                ctx.request_repaint();
+            }
+            self.emulator_was_running = running;
+        }
     }
 }
@@ -1,28 +1,68 @@
 #![allow(clippy::unusual_byte_groupings)] // so we can group bits by instruction parts
 #![allow(clippy::reversed_empty_ranges)] // We want to use ranges for bis like we have in class (big:small)
 
+/// Record of every memory read and write, for the timeline pane
+pub mod access_log;
+/// A set associative cache model in front of memory, counting hits and misses
+pub mod cache;
+/// Cycle, instruction, memory and trap counts for the counters pane
+pub mod counters;
 /// Run the low level ops
 pub mod executor;
+/// The textbook control FSM's numbered states, mapped onto our phases
+pub mod fsm;
+/// Catch programs using registers and memory they never set
+pub mod init_tracker;
 /// Manage the low level ops that each instruction is broken down into
 #[macro_use]
 pub mod micro_op;
+/// A control store and microsequencer that run the machine a clock cycle at a time
+pub mod microcode;
+/// Run the OS's TRAP routines in Rust instead, for speed and to skip the OS layer
+pub mod native_traps;
 /// Spec for each op so they can be executed
 pub mod ops;
 /// Convert a seris of lines of lc3 code into emulator cells reporting errors
 pub mod parse;
+/// A 5 stage pipeline timing model over the instructions that run
+pub mod pipeline;
+/// Named memory regions and per address notes declared in program comments
+pub mod regions;
+/// The registers after each instruction, for graphing them over time
+pub mod register_history;
+/// Seeded random numbers for the randomised machine state mode
+pub mod rng;
+/// Subroutine calls in progress and lcc style stack frames, for the stack pane
+pub mod stack;
 #[cfg(test)]
 /// Tests for emulation layer
 mod tests;
+/// Clock cycle counting and the memory's ready signal
+pub mod timing;
 
-use std::{collections::HashSet, ops::Range};
+use std::{
+    collections::{HashSet, VecDeque},
+    ops::Range,
+};
 
-pub use ops::{CpuState, OpCode};
+pub use ops::{CpuState, Isa, OpCode};
 use parse::ParseOutput;
 
 use crate::emulator::{
+    access_log::AccessLog,
+    cache::Cache,
+    counters::PerfCounters,
     executor::CpuPhaseState,
+    init_tracker::InitTracker,
     micro_op::{CycleState, MicroOpGenerator},
+    microcode::Microsequencer,
+    native_traps::{NativeTrapOp, NativeTraps},
     parse::CompilationArtifacts,
+    pipeline::Pipeline,
+    register_history::RegisterHistory,
+    rng::SplitMix64,
+    stack::CallStack,
+    timing::Timing,
 };
 
 /// The amount of steps to skip when os skips are enabled and we are in OS memory space
@@ -43,7 +83,14 @@ pub const PSR_ADDR: usize = 0xFFFC;
 /// Machine control register, when MCR[15] = 1 the program is running. To halt it is cleared.
 pub const MCR_ADDR: usize = 0xFFFE;
 
+/// First address user programs may use
+pub const USER_SPACE_START: usize = 0x3000;
+/// Last address user programs may use (device registers start after this)
+pub const USER_SPACE_END: usize = 0xFDFF;
+
 pub struct Emulator {
+    /// LC-3 or LC-3b, fixed for the life of the machine
+    pub isa: Isa,
     // --- not involved in the state machine ---
     /// How many cycles to run per update call
     pub speed: u32,
@@ -55,12 +102,29 @@ pub struct Emulator {
     pub skip_os_emulation: bool,
     /// The summation of all MEM[DDR] sets aka the 'output' of the emulator
     pub output: String,
+    /// Keys waiting to be typed. One goes into KBDR each time the last one is read (KBSR[15] cleared)
+    pub input_queue: VecDeque<u8>,
     /// Some associated data for the most recent set of compiled programs
     pub metadata: CompilationArtifacts,
     pub breakpoints: HashSet<usize>,
+    /// The breakpoint we last stopped on, so pressing run again gets past it instead of stopping straight away.
+    /// Cleared on the next fetch
+    pub stopped_at_breakpoint: Option<usize>,
     pub currently_executing: usize,
     /// Has the machine been halted by the OS/program
     pub halted: bool,
+    /// When set, user memory and R0-R7 are filled from this seed on reset instead of being zeroed (like lc3tools does)
+    pub random_seed: Option<u64>,
+    /// What has been written since load/reset, and what was used before it was
+    pub init_tracker: InitTracker,
+    /// Every memory read and write since load/reset
+    pub access_log: AccessLog,
+    /// How many instructions have been fetched since reset
+    pub instruction_count: u64,
+    /// R0-R7, PC and PSR after each of the last few thousand instructions
+    pub register_history: RegisterHistory,
+    /// JSRs that haven't RET'd yet
+    pub call_stack: CallStack,
     // -----------------------------------------
 
     // Why in a Box? Becuase array sits on stack and takes alot of memory.
@@ -105,12 +169,41 @@ pub struct Emulator {
 
     /// If our stste machine has reached an exeption state than this stores the particulars
     pub exception: Option<Exception>,
+    /// When set, micro steps are clock cycles of the microcoded machine instead of phases
+    pub microsequencer: Option<Microsequencer>,
+    /// When set, each instruction is also timed going through a 5 stage pipeline
+    pub pipeline: Option<Pipeline>,
+    /// When set, loads and stores go through this cache model on the way to memory
+    pub cache: Option<Cache>,
+    /// Clock cycles so far and how many memory takes
+    pub timing: Timing,
+    /// Performance counters, see [`Emulator::stats`]
+    pub counters: PerfCounters,
+    /// Which of the OS's traps run in Rust instead
+    pub native_traps: NativeTraps,
 }
 
 impl Emulator {
     pub fn new() -> Emulator {
+        Self::new_seeded(None)
+    }
+
+    /// A fresh machine. If given a seed, user memory and registers start out as seeded junk rather than zero.
+    pub fn new_seeded(random_seed: Option<u64>) -> Emulator {
+        Self::with_isa(Isa::Lc3, random_seed)
+    }
+
+    /// A fresh LC-3 or LC-3b, booting the OS written for it
+    pub fn with_isa(isa: Isa, random_seed: Option<u64>) -> Emulator {
         let mut emulator = Self {
+            isa,
             halted: false,
+            random_seed,
+            init_tracker: InitTracker::default(),
+            access_log: AccessLog::default(),
+            instruction_count: 0,
+            register_history: RegisterHistory::default(),
+            call_stack: CallStack::default(),
             speed: 1,
             ticks_between_updates: 2,
             tick: 0,
@@ -118,25 +211,31 @@ impl Emulator {
             currently_executing: 0,
             metadata: CompilationArtifacts::default(),
             breakpoints: HashSet::new(),
+            stopped_at_breakpoint: None,
             memory: Box::new([EmulatorCell::new(0); 65536]),
             r: [EmulatorCell::new(0); 8],
-            pc: EmulatorCell::new(0x200), // start of os
+            pc: EmulatorCell::new(isa.boot_address()), // start of os
             mar: EmulatorCell::new(0),
             mdr: EmulatorCell::new(0),
             ir: EmulatorCell::new(0),
             output: String::new(),
+            input_queue: VecDeque::new(),
             cpu_state: CpuState::Fetch,
             execute_state: CpuPhaseState::new(Vec::new()), // this is empty before we execute the first op
             alu: Alu::default(),
             exception: None,
             saved_ssp: EmulatorCell::new(0),
             saved_usp: EmulatorCell::new(0),
+            microsequencer: None,
+            pipeline: None,
+            cache: None,
+            timing: Timing::default(),
+            counters: PerfCounters::default(),
+            native_traps: NativeTraps::default(),
         };
 
-        let parse_output = Emulator::parse_program(
-            include_str!("../oses/simpleos.asm"),
-            Some(&mut emulator.metadata),
-        );
+        let parse_output =
+            Emulator::parse_program_for(isa, isa.os_source(), Some(&mut emulator.metadata));
 
         tracing::debug!("OS parse_output: {:?}", parse_output);
 
@@ -155,9 +254,27 @@ impl Emulator {
         emulator.memory[PSR_ADDR].set(0x0002); // Z=1, N=0, P=0 Supervisor
         emulator.memory[MCR_ADDR].set(0x0000); // machine is "stopped"
 
+        if let Some(seed) = random_seed {
+            emulator.randomize_state(seed);
+        }
+
         emulator
     }
 
+    /// Fill user memory (x3000-xFDFF) and R0-R7 with values derived from `seed`.
+    /// The OS and device registers are left alone so the machine still boots.
+    pub fn randomize_state(&mut self, seed: u64) {
+        tracing::info!("Randomising machine state with seed {}", seed);
+        let mut rng = SplitMix64::new(seed);
+
+        for reg in self.r.iter_mut() {
+            reg.set(rng.next_u16());
+        }
+        for cell in self.memory[USER_SPACE_START..=USER_SPACE_END].iter_mut() {
+            cell.set(rng.next_u16());
+        }
+    }
+
     /// Are we running in superviser or user mode?
     pub fn priv_level(&self) -> PrivilegeLevel {
         match self.memory[PSR_ADDR].index(15).get() {
@@ -167,16 +284,62 @@ impl Emulator {
         }
     }
 
-    /// Reset all registers and set PC to x200
+    /// Reset all registers and set PC to the start of the OS (x200)
     pub fn soft_reset(&self) -> Self {
-        Self {
+        let mut emulator = Self {
+            isa: self.isa,
             output: self.output.clone(),
-            pc: EmulatorCell::new(0x200),
-            speed: self.speed,
-            skip_os_emulation: self.skip_os_emulation,
+            pc: EmulatorCell::new(self.isa.boot_address()),
+            random_seed: self.random_seed,
             memory: self.memory.clone(),
             metadata: self.metadata.clone(),
+            breakpoints: self.breakpoints.clone(),
+            init_tracker: self.init_tracker.clone(),
             ..Default::default()
+        };
+        self.carry_settings(&mut emulator);
+        emulator.init_tracker.reset_registers();
+
+        // Memory is kept so only the registers get junk
+        if let Some(seed) = self.random_seed {
+            let mut rng = SplitMix64::new(seed);
+            for reg in emulator.r.iter_mut() {
+                reg.set(rng.next_u16());
+            }
+        }
+        emulator
+    }
+
+    /// Start again from scratch as an `isa` machine, memory and all. The settings a
+    /// [`Emulator::soft_reset`] keeps are kept here too
+    pub fn hard_reset(&self, isa: Isa) -> Self {
+        let mut emulator = Self::with_isa(isa, self.random_seed);
+        self.carry_settings(&mut emulator);
+        emulator
+    }
+
+    /// Copy what the user has set up (speed, timing models, cache, ...) onto a fresh machine,
+    /// everything a reset should keep goes through here
+    fn carry_settings(&self, emulator: &mut Emulator) {
+        emulator.speed = self.speed;
+        emulator.skip_os_emulation = self.skip_os_emulation;
+        emulator.access_log.enabled = self.access_log.enabled;
+        emulator.use_microsequencer(self.microsequencer.is_some());
+        emulator.pipeline = self
+            .pipeline
+            .as_ref()
+            .map(|pipeline| Pipeline::new(pipeline.forwarding));
+        emulator.cache = self.cache.as_ref().map(|cache| Cache::new(cache.config));
+        emulator.timing.memory_latency = self.timing.memory_latency;
+        emulator.native_traps.enabled = self.native_traps.enabled;
+    }
+
+    /// Where the word holding `addr` lives in [`Emulator::memory`].
+    /// LC-3b words sit at their even byte address, the odd cells go unused
+    pub fn word_address(&self, addr: u16) -> usize {
+        match self.isa {
+            Isa::Lc3 => addr as usize,
+            Isa::Lc3b => (addr & !1) as usize,
         }
     }
 
@@ -237,12 +400,37 @@ impl Emulator {
         (n, z, p)
     }
 
-    /// Input one char so that the os can read it.
-    // TODO: change this if/when we do interuption based input
+    /// Input one char so that the os can read it. Non ascii chars are ignored.
     pub fn set_in_char(&mut self, c: char) {
         if c.is_ascii() {
-            self.memory[KBDR_ADDR].set(c as u16);
-            self.memory[KBSR_ADDR].set(0x8000); // indicates new char avalible
+            self.input_queue.push_back(c as u8);
+            self.feed_keyboard();
+        }
+    }
+
+    /// Queue up some text to be typed in one char at a time (pasting, scripted input).
+    /// The keyboard can only type ascii so if there is anything else in `text` nothing gets queued.
+    pub fn queue_input(&mut self, text: &str) -> Result<(), String> {
+        if let Some((i, c)) = text.char_indices().find(|(_, c)| !c.is_ascii()) {
+            return Err(format!(
+                "'{c}' (at char {}) is not ASCII, the LC3 keyboard can only type ASCII",
+                text[..i].chars().count() + 1
+            ));
+        }
+        self.input_queue
+            .extend(text.bytes().filter(|&b| b != b'\r')); // windows line endings
+        self.feed_keyboard();
+        Ok(())
+    }
+
+    /// If the last key has been read, put the next one from the queue into KBDR.
+    // TODO: change this if/when we do interuption based input
+    fn feed_keyboard(&mut self) {
+        if self.memory[KBSR_ADDR].get() & 0x8000 == 0 {
+            if let Some(c) = self.input_queue.pop_front() {
+                self.memory[KBDR_ADDR].set(c as u16);
+                self.memory[KBSR_ADDR].set(0x8000); // indicates new char avalible
+            }
         }
     }
 
@@ -393,17 +581,23 @@ pub enum Exception {
     PrivilegeViolation,
     IllegalInstruction,
     AccessControlViolation,
+    /// LDW/STW to an odd address on the LC-3b
+    UnalignedAccess,
 }
 
 impl Exception {
-    fn get_handler_address(&self) -> usize {
-        // Base address of the Interrupt Vector Table
-        const IVT_BASE: usize = 0x0100;
+    fn get_handler_address(&self, isa: Isa) -> usize {
         // TODO: Should we make more?
-        match self {
-            Exception::PrivilegeViolation => IVT_BASE, // Vector x00 in IVT for Privilege Violation
-            Exception::IllegalInstruction => IVT_BASE + 0x01, // Vector x01 in IVT for Illegal Opcode
-            Exception::AccessControlViolation => IVT_BASE + 0x02, // Using x02 for Access Control
+        let vector = match self {
+            Exception::PrivilegeViolation => 0x00, // Vector x00 in IVT for Privilege Violation
+            Exception::IllegalInstruction => 0x01, // Vector x01 in IVT for Illegal Opcode
+            Exception::AccessControlViolation => 0x02, // Using x02 for Access Control
+            Exception::UnalignedAccess => 0x03,
+        };
+        // Base address of the Interrupt Vector Table, on the LC-3b it is after the 2 byte trap table entries
+        match isa {
+            Isa::Lc3 => 0x0100 + vector,
+            Isa::Lc3b => 0x0200 + vector * 2,
         }
     }
 }
@@ -420,6 +614,8 @@ pub enum AluOp {
     Add(EmulatorCell, EmulatorCell),
     And(EmulatorCell, EmulatorCell),
     Not(EmulatorCell),
+    Mul(EmulatorCell, EmulatorCell),
+    Xor(EmulatorCell, EmulatorCell),
 }
 
 impl AluOp {
@@ -428,6 +624,8 @@ impl AluOp {
             AluOp::Add(a, b) => a.get().wrapping_add(b.get()),
             AluOp::And(a, b) => a.get() & b.get(),
             AluOp::Not(a) => !a.get(),
+            AluOp::Mul(a, b) => a.get().wrapping_mul(b.get()),
+            AluOp::Xor(a, b) => a.get() ^ b.get(),
         })
     }
 }
@@ -456,10 +654,12 @@ impl Emulator {
                     let current_pc = self.pc.get() as usize;
 
                     if self.breakpoints.contains(&current_pc)
-                        && matches!(self.cpu_state, CpuState::Fetch)
+                        && self.between_instructions()
+                        && self.stopped_at_breakpoint != Some(current_pc)
                     // Break *before* fetching the instruction at the breakpoint
                     {
                         self.stop_running();
+                        self.stopped_at_breakpoint = Some(current_pc);
                         log::info!("Breakpoint hit at address 0x{current_pc:04X}");
                         break;
                     }
@@ -495,6 +695,11 @@ impl Emulator {
         let memory_area = area_from_address(&self.pc);
 
         self.currently_executing = pc_value as usize;
+        self.record_registers();
+        self.instruction_count += 1;
+        self.stopped_at_breakpoint = None;
+        self.track_fetch(pc_value as usize);
+        self.start_instruction_timing();
 
         // Check read permission for PC address
         if !memory_area.can_read(&self.priv_level()) {
@@ -505,24 +710,51 @@ impl Emulator {
         }
 
         // Get the micro-op generator for the instruction
-        let opcode = OpCode::from_instruction(self.memory[pc_value as usize])
-            .expect("Failed to decode instruction");
-        let micro_op_gen: &dyn MicroOpGenerator = match &opcode {
-            OpCode::Add(op) => op,
-            OpCode::And(op) => op,
-            OpCode::Br(op) => op,
-            OpCode::Jmp(op) => op,
-            OpCode::Jsr(op) => op,
-            OpCode::Ld(op) => op,
-            OpCode::Ldi(op) => op,
-            OpCode::Ldr(op) => op,
-            OpCode::Lea(op) => op,
-            OpCode::Not(op) => op,
-            OpCode::Rti(op) => op,
-            OpCode::St(op) => op,
-            OpCode::Sti(op) => op,
-            OpCode::Str(op) => op,
-            OpCode::Trap(op) => op,
+        let Some(opcode) = OpCode::decode(self.isa, self.memory[self.word_address(pc_value)])
+        else {
+            // 1101 with no custom instruction loaded, or 1010/1011 on the LC-3b
+            self.exception = Some(Exception::IllegalInstruction);
+            return Err(format!(
+                "Fetch Error: Illegal opcode at 0x{pc_value:04X}: 0x{:04X}",
+                self.memory[self.word_address(pc_value)].get()
+            ));
+        };
+        self.track_call(&opcode);
+        self.count_instruction(&opcode);
+        self.track_pipeline();
+        let native = self
+            .native_traps
+            .vector_of(&opcode)
+            .map(|vector| NativeTrapOp { vector });
+        let micro_op_gen: &dyn MicroOpGenerator = if let Some(native) = &native {
+            native
+        } else {
+            match &opcode {
+                OpCode::Add(op) => op,
+                OpCode::And(op) => op,
+                OpCode::Br(op) => op,
+                OpCode::Jmp(op) => op,
+                OpCode::Jsr(op) => op,
+                OpCode::Ld(op) => op,
+                OpCode::Ldi(op) => op,
+                OpCode::Ldr(op) => op,
+                OpCode::Lea(op) => op,
+                OpCode::Not(op) => op,
+                OpCode::Rti(op) => op,
+                OpCode::St(op) => op,
+                OpCode::Sti(op) => op,
+                OpCode::Str(op) => op,
+                OpCode::Trap(op) => op,
+                OpCode::Custom(op) => op,
+                OpCode::Ldb(op) => op,
+                OpCode::Stb(op) => op,
+                OpCode::Ldw(op) => op,
+                OpCode::Stw(op) => op,
+                OpCode::Shf(op) => op,
+                OpCode::Xor(op) => op,
+                OpCode::Lc3bRti(op) => op,
+                OpCode::Lc3bTrap(op) => op,
+            }
         };
 
         // Get the plan for the specific instruction phases
@@ -534,7 +766,7 @@ impl Emulator {
             vec![
                 micro_op!(-> Fetch),
                 micro_op!(MAR <- PC),
-                micro_op!(ALU_OUT <- PC + C(1)),
+                micro_op!(ALU_OUT <- PC + C(self.isa.word_size() as u16)),
                 micro_op!(PC <- AluOut),
             ],
             // Phase 1: Decode
@@ -587,7 +819,7 @@ impl Emulator {
 
     /// **Decode Phase:** Decode instruction in IR, determine OpCode.
     fn decode(&mut self) -> Result<OpCode, String> {
-        match OpCode::from_instruction(self.ir) {
+        match OpCode::decode(self.isa, self.ir) {
             Some(op) => Ok(op),
             None => {
                 self.exception = Some(Exception::IllegalInstruction);
@@ -602,9 +834,10 @@ impl Emulator {
     /// **Handle Exception:** Switch to supervisor mode, save state, jump to handler.
     fn handle_exception(&mut self, exception: Exception) {
         tracing::warn!("Handling Exception: {:?}", exception);
+        self.counters.exceptions += 1;
 
         // 1. Get handler address
-        let handler_addr = exception.get_handler_address();
+        let handler_addr = exception.get_handler_address(self.isa);
         let handler_addr = self.memory[handler_addr];
 
         let psr_val = self.memory[PSR_ADDR].get(); // save the curr psr before changing priv
@@ -618,14 +851,15 @@ impl Emulator {
 
         // 3. Push PSR and PC onto the Supervisor Stack (R6)
         let ssp = self.r[6].get();
-        let psr_addr = ssp.wrapping_sub(1);
-        let pc_addr = ssp.wrapping_sub(2);
+        let word = self.isa.word_size() as u16;
+        let psr_addr = ssp.wrapping_sub(word);
+        let pc_addr = ssp.wrapping_sub(2 * word);
 
         // Check stack write permissions (should be writable in Supervisor mode)
         // Basic check: Ensure stack pointer is within valid memory range
         if pc_addr > 1 && pc_addr < (self.memory.len() - 1) as u16 {
-            self.memory[psr_addr as usize].set(psr_val);
-            self.memory[pc_addr as usize].set(self.pc.get());
+            self.write_memory(psr_addr as usize, psr_val);
+            self.write_memory(pc_addr as usize, self.pc.get());
             self.r[6].set(pc_addr); // Update SSP
         } else {
             // Stack Overflow/Underflow - This is a critical error, potentially halt or double fault
@@ -652,6 +886,21 @@ impl Emulator {
         tracing::trace!(cpu_state = ?self.cpu_state, "Entering micro_step");
 
         debug_assert!(self.running(), "attermpting run but not running");
+        self.timing.cycles += 1;
+        let level = self.priv_level();
+        let result = self.cycle();
+        if self.priv_level() != level {
+            self.counters.privilege_switches += 1;
+        }
+        result
+    }
+
+    /// One cycle of whichever executor is in use
+    fn cycle(&mut self) -> Result<(), String> {
+        if self.microsequencer.is_some() {
+            // exceptions are states of the microcode there
+            return self.clock_cycle();
+        }
         // --- Check for and Handle Exceptions First ---
         if let Some(exc) = self.exception.clone() {
             tracing::info!(
@@ -664,7 +913,8 @@ impl Emulator {
             tracing::debug!("Exception handled, returning from micro_step");
         }
 
-        if matches!(self.cpu_state, CpuState::Fetch) {
+        // the fetch could already be done and waiting on memory
+        if self.between_instructions() {
             self.fetch()?;
         }
         if matches!(self.cpu_state, CpuState::Decode) {
@@ -703,7 +953,7 @@ impl Emulator {
         if matches!(self.cpu_state, CpuState::Fetch) {
             let _ = self.micro_step(); // Step over  Fetch
         }
-        while !matches!(self.cpu_state, CpuState::Fetch) && self.running() {
+        while !self.between_instructions() && self.running() {
             // Continue micro-stepping until Fetch is reached or an exception occurs
             let _ = self.micro_step(); // we ignore becuase it should already set an exeption
         }
@@ -713,7 +963,7 @@ impl Emulator {
             return;
         }
 
-        debug_assert!(matches!(self.cpu_state, CpuState::Fetch), "invalid step");
+        debug_assert!(self.between_instructions(), "invalid step");
 
         if !input_running {
             self.stop_running();
@@ -766,6 +1016,8 @@ impl Emulator {
     // pixel display
     // For now keep it simple
     fn update_devices(&mut self) {
+        self.feed_keyboard();
+
         // Check if a program is trying to write to display
         let dsr_value = self.memory[DSR_ADDR].get();
         if (dsr_value & 0x8000) != 0 {
@@ -0,0 +1,117 @@
+use std::collections::VecDeque;
+
+use crate::emulator::cache::CacheResult;
+use crate::emulator::Emulator;
+
+/// Keep this many accesses, the oldest get dropped after that
+pub const MAX_ACCESSES: usize = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One read or write of a memory cell by the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    /// How many instructions had been fetched when it happened (the timestamp)
+    pub instruction: u64,
+    /// Address of the instruction doing it
+    pub pc: u16,
+    pub address: u16,
+    /// Value before the access, the same as `new` for a read
+    pub old: u16,
+    pub new: u16,
+    pub kind: AccessKind,
+    /// Whether it hit, if there was a cache in front of this address
+    pub cache: Option<CacheResult>,
+}
+
+/// Reads and writes since load/reset, oldest first.
+///
+/// Instruction fetches aren't recorded, every instruction does one so they would drown out everything else.
+/// Devices updating their own registers aren't either, only what instructions (and exceptions) do.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    pub enabled: bool,
+    pub accesses: VecDeque<MemoryAccess>,
+    /// How many have ever been pushed, so views can tell when there is something new
+    pub pushed: u64,
+}
+
+impl Default for AccessLog {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            accesses: VecDeque::new(),
+            pushed: 0,
+        }
+    }
+}
+
+impl AccessLog {
+    pub fn push(&mut self, access: MemoryAccess) {
+        if !self.enabled {
+            return;
+        }
+        if self.accesses.len() >= MAX_ACCESSES {
+            self.accesses.pop_front();
+        }
+        self.accesses.push_back(access);
+        self.pushed += 1;
+    }
+
+    /// Accesses to one address, oldest first
+    pub fn for_address(&self, address: u16) -> impl Iterator<Item = &MemoryAccess> {
+        self.accesses
+            .iter()
+            .filter(move |access| access.address == address)
+    }
+
+    /// (instruction, value) each time the value at `address` changed, starting with what it was when we first saw it
+    pub fn values_over_time(&self, address: u16) -> Vec<(u64, u16)> {
+        let mut accesses = self.for_address(address).peekable();
+        let Some(first) = accesses.peek() else {
+            return Vec::new();
+        };
+        let mut values = vec![(first.instruction, first.old)];
+        for access in accesses.filter(|access| access.kind == AccessKind::Write) {
+            values.push((access.instruction, access.new));
+        }
+        values
+    }
+}
+
+impl Emulator {
+    /// Called after a read into the MDR
+    pub(super) fn log_memory_read(&mut self, addr: usize, cache: Option<CacheResult>) {
+        // the read at the end of the fetch phase is just getting the instruction
+        if self.execute_state.current_phase == 0 {
+            return;
+        }
+        let value = self.memory[addr].get();
+        self.access_log.push(MemoryAccess {
+            instruction: self.instruction_count,
+            pc: self.currently_executing as u16,
+            address: addr as u16,
+            old: value,
+            new: value,
+            kind: AccessKind::Read,
+            cache,
+        });
+    }
+
+    /// Called after a write, with the value that was there before
+    pub(super) fn log_memory_write(&mut self, addr: usize, old: u16, cache: Option<CacheResult>) {
+        self.access_log.push(MemoryAccess {
+            instruction: self.instruction_count,
+            pc: self.currently_executing as u16,
+            address: addr as u16,
+            old,
+            new: self.memory[addr].get(),
+            kind: AccessKind::Write,
+            cache,
+        });
+    }
+}
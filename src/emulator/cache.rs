@@ -0,0 +1,367 @@
+use serde::{Deserialize, Serialize};
+
+use crate::emulator::access_log::AccessKind;
+use crate::emulator::rng::SplitMix64;
+use crate::emulator::{Emulator, USER_SPACE_END};
+
+/// What a write that hits does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WritePolicy {
+    /// Only the cache gets it, memory is updated when the block is evicted
+    WriteBack,
+    /// Memory gets every write straight away
+    WriteThrough,
+}
+
+/// Which block in a full set makes way for a new one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Replacement {
+    /// Least recently used
+    Lru,
+    /// The one that has been there longest
+    Fifo,
+    Random,
+}
+
+/// The shape of the cache. Sizes are in addresses, so words on the LC-3 and bytes on the LC-3b
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    pub sets: usize,
+    /// Blocks per set, 1 is direct mapped
+    pub ways: usize,
+    pub block_size: usize,
+    pub write_policy: WritePolicy,
+    /// Bring the block in on a write miss
+    pub write_allocate: bool,
+    pub replacement: Replacement,
+    /// Send instruction fetches through it too, otherwise it only sees loads and stores
+    pub instructions: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            sets: 8,
+            ways: 2,
+            block_size: 4,
+            write_policy: WritePolicy::WriteBack,
+            write_allocate: true,
+            replacement: Replacement::Lru,
+            instructions: false,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// How many addresses it holds
+    pub fn size(&self) -> usize {
+        self.sets * self.ways * self.block_size
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [
+            ("Sets", self.sets),
+            ("Ways", self.ways),
+            ("Block size", self.block_size),
+        ] {
+            if !value.is_power_of_two() {
+                return Err(format!("{name} has to be a power of two, not {value}"));
+            }
+        }
+        if self.size() > 0x10000 {
+            return Err(format!(
+                "{} addresses is bigger than all of memory",
+                self.size()
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// One block's worth of slot in a set. Only the tag is kept, the data is always what is in memory
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheLine {
+    pub valid: bool,
+    pub dirty: bool,
+    pub tag: u16,
+    /// When it was brought in, for FIFO
+    pub loaded: u64,
+    /// When it was last used, for LRU
+    pub used: u64,
+}
+
+/// What happened to one access
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheResult {
+    pub hit: bool,
+    pub set: usize,
+    /// Where the block is now, none for a write miss that didn't allocate
+    pub way: Option<usize>,
+    /// Start address of the block that got kicked out to make room
+    pub evicted: Option<u16>,
+    /// The evicted block was dirty and had to be written back
+    pub wrote_back: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub read_hits: u64,
+    pub read_misses: u64,
+    pub write_hits: u64,
+    pub write_misses: u64,
+    /// Dirty blocks written back on eviction
+    pub writebacks: u64,
+    /// Writes that went straight to memory (write through, or write misses that didn't allocate)
+    pub memory_writes: u64,
+}
+
+impl CacheStats {
+    pub fn accesses(&self) -> u64 {
+        self.read_hits + self.read_misses + self.write_hits + self.write_misses
+    }
+
+    pub fn hit_rate(&self) -> f64 {
+        match self.accesses() {
+            0 => 0.0,
+            accesses => (self.read_hits + self.write_hits) as f64 / accesses as f64,
+        }
+    }
+}
+
+/// A set associative cache model sitting between the CPU and memory.
+/// It decides hits and misses, memory still holds the values so nothing the program sees changes
+#[derive(Debug, Clone)]
+pub struct Cache {
+    pub config: CacheConfig,
+    /// `sets` sets of `ways` lines
+    pub lines: Vec<Vec<CacheLine>>,
+    pub stats: CacheStats,
+    /// The most recent access and what happened, for the pane to point at
+    pub last: Option<(u16, CacheResult)>,
+    clock: u64,
+    rng: SplitMix64,
+}
+
+impl Cache {
+    /// An empty cache, `config` should have been validated
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            lines: vec![vec![CacheLine::default(); config.ways]; config.sets],
+            stats: CacheStats::default(),
+            last: None,
+            clock: 0,
+            // random replacement is the same every run so results can be compared
+            rng: SplitMix64::new(210),
+        }
+    }
+
+    /// (set, tag) of the block holding `address`
+    pub fn locate(&self, address: u16) -> (usize, u16) {
+        let block = address as usize / self.config.block_size;
+        (block % self.config.sets, (block / self.config.sets) as u16)
+    }
+
+    /// First address of the block with `tag` in `set`
+    pub fn block_address(&self, set: usize, tag: u16) -> u16 {
+        ((tag as usize * self.config.sets + set) * self.config.block_size) as u16
+    }
+
+    fn victim(&mut self, set: usize) -> usize {
+        let lines = &self.lines[set];
+        if let Some(way) = lines.iter().position(|line| !line.valid) {
+            return way;
+        }
+        let oldest = |key: fn(&CacheLine) -> u64| {
+            (0..lines.len())
+                .min_by_key(|&way| key(&lines[way]))
+                .unwrap_or(0)
+        };
+        match self.config.replacement {
+            Replacement::Lru => oldest(|line| line.used),
+            Replacement::Fifo => oldest(|line| line.loaded),
+            Replacement::Random => (self.rng.next_u64() % lines.len() as u64) as usize,
+        }
+    }
+
+    pub fn access(&mut self, address: u16, kind: AccessKind) -> CacheResult {
+        self.clock += 1;
+        let write = kind == AccessKind::Write;
+        let write_back = self.config.write_policy == WritePolicy::WriteBack;
+        let (set, tag) = self.locate(address);
+
+        let found = self.lines[set]
+            .iter()
+            .position(|line| line.valid && line.tag == tag);
+        let result = if let Some(way) = found {
+            let line = &mut self.lines[set][way];
+            line.used = self.clock;
+            if write {
+                self.stats.write_hits += 1;
+                if write_back {
+                    line.dirty = true;
+                } else {
+                    self.stats.memory_writes += 1;
+                }
+            } else {
+                self.stats.read_hits += 1;
+            }
+            CacheResult {
+                hit: true,
+                set,
+                way: Some(way),
+                evicted: None,
+                wrote_back: false,
+            }
+        } else if write && !self.config.write_allocate {
+            self.stats.write_misses += 1;
+            self.stats.memory_writes += 1;
+            CacheResult {
+                hit: false,
+                set,
+                way: None,
+                evicted: None,
+                wrote_back: false,
+            }
+        } else {
+            if write {
+                self.stats.write_misses += 1;
+            } else {
+                self.stats.read_misses += 1;
+            }
+            let way = self.victim(set);
+            let old = self.lines[set][way];
+            let evicted = old.valid.then(|| self.block_address(set, old.tag));
+            if old.valid && old.dirty {
+                self.stats.writebacks += 1;
+            }
+            if write && !write_back {
+                self.stats.memory_writes += 1;
+            }
+            self.lines[set][way] = CacheLine {
+                valid: true,
+                dirty: write && write_back,
+                tag,
+                loaded: self.clock,
+                used: self.clock,
+            };
+            CacheResult {
+                hit: false,
+                set,
+                way: Some(way),
+                evicted,
+                wrote_back: old.valid && old.dirty,
+            }
+        };
+        self.last = Some((address, result));
+        result
+    }
+}
+
+impl Emulator {
+    /// Put a cache with `config` in front of memory, or take it away with `None`
+    pub fn use_cache(&mut self, config: Option<CacheConfig>) -> Result<(), String> {
+        if let Some(config) = &config {
+            config.validate()?;
+        }
+        self.cache = config.map(Cache::new);
+        Ok(())
+    }
+
+    /// Run a CPU read or write past the cache, if there is one and it caches `addr`
+    pub(super) fn cache_access(&mut self, addr: usize, kind: AccessKind) -> Option<CacheResult> {
+        // the same test the access log uses for the instruction fetch
+        let fetch = self.execute_state.current_phase == 0;
+        let cache = self.cache.as_mut()?;
+        // device registers are never cached
+        if addr > USER_SPACE_END || (fetch && !cache.config.instructions) {
+            return None;
+        }
+        Some(cache.access(addr as u16, kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn direct_mapped() -> Cache {
+        Cache::new(CacheConfig {
+            sets: 4,
+            ways: 1,
+            block_size: 4,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn spatial_locality_and_conflicts() {
+        let mut cache = direct_mapped();
+        assert!(!cache.access(0x4000, AccessKind::Read).hit);
+        assert!(cache.access(0x4003, AccessKind::Read).hit);
+        // 16 addresses on, the same set
+        let conflict = cache.access(0x4010, AccessKind::Read);
+        assert!(!conflict.hit);
+        assert_eq!(conflict.set, 0);
+        assert_eq!(conflict.evicted, Some(0x4000));
+        assert!(!cache.access(0x4001, AccessKind::Read).hit);
+        assert_eq!(cache.stats.read_hits, 1);
+        assert_eq!(cache.stats.read_misses, 3);
+    }
+
+    #[test]
+    fn write_policies() {
+        let mut cache = direct_mapped();
+        cache.access(0x4000, AccessKind::Write);
+        assert!(cache.lines[0][0].dirty);
+        let result = cache.access(0x4010, AccessKind::Read);
+        assert!(result.wrote_back);
+        assert_eq!(cache.stats.writebacks, 1);
+
+        let mut cache = Cache::new(CacheConfig {
+            write_policy: WritePolicy::WriteThrough,
+            write_allocate: false,
+            ..cache.config
+        });
+        let result = cache.access(0x4000, AccessKind::Write);
+        assert_eq!(result.way, None);
+        assert!(!cache.access(0x4000, AccessKind::Read).hit);
+        assert!(cache.access(0x4000, AccessKind::Write).hit);
+        assert!(!cache.lines[0][0].dirty);
+        assert_eq!(cache.stats.memory_writes, 2);
+    }
+
+    #[test]
+    fn lru_and_fifo() {
+        let config = CacheConfig {
+            sets: 1,
+            ways: 2,
+            block_size: 1,
+            ..Default::default()
+        };
+        for (replacement, evicted) in [(Replacement::Lru, 1), (Replacement::Fifo, 0)] {
+            let mut cache = Cache::new(CacheConfig {
+                replacement,
+                ..config
+            });
+            cache.access(0, AccessKind::Read);
+            cache.access(1, AccessKind::Read);
+            cache.access(0, AccessKind::Read);
+            assert_eq!(
+                cache.access(2, AccessKind::Read).evicted,
+                Some(evicted),
+                "{replacement:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn sizes_must_be_powers_of_two() {
+        assert!(CacheConfig::default().validate().is_ok());
+        let config = CacheConfig {
+            ways: 3,
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::emulator::{Emulator, OpCode};
+
+/// Event counts since reset, or since they were last cleared
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PerfCounters {
+    pub instructions: u64,
+    /// Instructions run by mnemonic, BR covers every BRnzp and JMP covers RET
+    pub opcodes: BTreeMap<String, u64>,
+    /// Loads and stores, not counting instruction fetches
+    pub memory_reads: u64,
+    pub memory_writes: u64,
+    pub traps: u64,
+    /// Privilege mode, illegal opcode and access violation exceptions
+    pub exceptions: u64,
+    /// Times the PSR went from user to supervisor or back
+    pub privilege_switches: u64,
+}
+
+impl PerfCounters {
+    /// The opcodes, most run first
+    pub fn opcodes_by_count(&self) -> Vec<(&str, u64)> {
+        let mut opcodes: Vec<_> = self
+            .opcodes
+            .iter()
+            .map(|(name, &count)| (name.as_str(), count))
+            .collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        opcodes
+    }
+}
+
+impl fmt::Display for PerfCounters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} instructions", self.instructions)?;
+        writeln!(
+            f,
+            "{} memory reads, {} memory writes",
+            self.memory_reads, self.memory_writes
+        )?;
+        writeln!(
+            f,
+            "{} traps, {} exceptions, {} privilege switches",
+            self.traps, self.exceptions, self.privilege_switches
+        )?;
+        let opcodes: Vec<_> = self
+            .opcodes_by_count()
+            .into_iter()
+            .map(|(name, count)| format!("{name} {count}"))
+            .collect();
+        write!(f, "By opcode: {}", opcodes.join(", "))
+    }
+}
+
+impl Emulator {
+    /// The performance counters
+    pub fn stats(&self) -> &PerfCounters {
+        &self.counters
+    }
+
+    /// Average cycles per instruction. The cycles are [`Timing::cycles`](super::timing::Timing::cycles)
+    pub fn cpi(&self) -> f64 {
+        if self.counters.instructions == 0 {
+            0.0
+        } else {
+            self.timing.cycles as f64 / self.counters.instructions as f64
+        }
+    }
+
+    /// Start counting again from zero, the machine itself is left alone. The cycle count starts
+    /// again too, keeping the cycles the instruction in progress has had so far
+    pub fn reset_counters(&mut self) {
+        self.counters = PerfCounters::default();
+        self.timing.cycles = self.timing.instruction_so_far();
+        self.timing.instruction_start = 0;
+        self.timing.memory_wait_cycles = 0;
+    }
+
+    /// Called alongside [`Emulator::track_call`] for each instruction that decoded
+    pub(super) fn count_instruction(&mut self, opcode: &OpCode) {
+        let counters = &mut self.counters;
+        counters.instructions += 1;
+        if matches!(opcode, OpCode::Trap(_) | OpCode::Lc3bTrap(_)) {
+            counters.traps += 1;
+        }
+        match counters.opcodes.get_mut(opcode.mnemonic()) {
+            Some(count) => *count += 1,
+            None => {
+                counters.opcodes.insert(opcode.mnemonic().to_string(), 1);
+            }
+        }
+    }
+}
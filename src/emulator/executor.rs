@@ -1,9 +1,10 @@
+use crate::emulator::access_log::AccessKind;
 use crate::emulator::micro_op::{
     CycleState, DataDestination, DataSource, MAluOp, MachineFlag, MicroOp,
 };
 use crate::emulator::{
-    area_from_address, AluOp, CpuState, Emulator, EmulatorCell, Exception, OpCode, KBDR_ADDR,
-    KBSR_ADDR, MCR_ADDR, PSR_ADDR,
+    area_from_address, AluOp, CpuState, Emulator, EmulatorCell, Exception, KBDR_ADDR, KBSR_ADDR,
+    MCR_ADDR, PSR_ADDR,
 };
 use std::fmt::{self};
 
@@ -21,7 +22,8 @@ pub struct CpuPhaseState {
     pub memory_read_pending: bool,
     /// Flag indicating if a memory write is pending between phases
     pub memory_write_pending: bool,
-    temp_register: EmulatorCell,
+    /// TEMP in the micro-ops, a scratch register instructions can use between phases
+    pub temp_register: EmulatorCell,
 }
 
 impl CpuPhaseState {
@@ -104,33 +106,10 @@ impl Emulator {
             }
 
             // Update display phase
-            if let Some(MicroOp::PhaseTransition(phase)) =
+            if let Some(&MicroOp::PhaseTransition(phase)) =
                 self.execute_state.execution_plan[self.execute_state.current_phase].first()
             {
-                self.cpu_state = match phase {
-                    CycleState::Fetch => CpuState::Fetch,
-                    CycleState::Decode => CpuState::Decode,
-                    CycleState::EvaluateAddress => CpuState::EvaluateAddress(
-                        self.cpu_state
-                            .to_instruction()
-                            .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                    ),
-                    CycleState::FetchOperands => CpuState::FetchOperands(
-                        self.cpu_state
-                            .to_instruction()
-                            .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                    ),
-                    CycleState::Execute => CpuState::ExecuteOperation(
-                        self.cpu_state
-                            .to_instruction()
-                            .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                    ),
-                    CycleState::StoreResult => CpuState::StoreResult(
-                        self.cpu_state
-                            .to_instruction()
-                            .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                    ),
-                };
+                self.cpu_state = self.phase_cpu_state(phase)?;
                 tracing::trace!("transitioned to phase: {:?}", self.cpu_state);
             }
 
@@ -161,6 +140,13 @@ impl Emulator {
             self.step_micro_op()?;
         }
 
+        // the phase can't finish until memory sets R, this gets called again each cycle until it does
+        if (self.execute_state.memory_write_pending || self.execute_state.memory_read_pending)
+            && !self.memory_ready()
+        {
+            return Ok(());
+        }
+
         // Perform implicit memory operations between phases
         self.handle_implicit_memory_operations()?;
 
@@ -171,38 +157,33 @@ impl Emulator {
         if self.execute_state.current_phase >= self.execute_state.execution_plan.len() {
             self.execute_state.instruction_complete = true;
             tracing::trace!("Instruction execution complete");
-        } else if let Some(MicroOp::PhaseTransition(phase)) =
+        } else if let Some(&MicroOp::PhaseTransition(phase)) =
             self.execute_state.execution_plan[self.execute_state.current_phase].first()
         {
-            self.cpu_state = match phase {
-                CycleState::Fetch => CpuState::Fetch,
-                CycleState::Decode => CpuState::Decode,
-                CycleState::EvaluateAddress => CpuState::EvaluateAddress(
-                    self.cpu_state
-                        .to_instruction()
-                        .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                ),
-                CycleState::FetchOperands => CpuState::FetchOperands(
-                    self.cpu_state
-                        .to_instruction()
-                        .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                ),
-                CycleState::Execute => CpuState::ExecuteOperation(
-                    self.cpu_state
-                        .to_instruction()
-                        .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                ),
-                CycleState::StoreResult => CpuState::StoreResult(
-                    self.cpu_state
-                        .to_instruction()
-                        .unwrap_or(OpCode::from_instruction(self.ir).unwrap()),
-                ),
-            };
+            self.cpu_state = self.phase_cpu_state(phase)?;
         }
 
         Ok(())
     }
 
+    /// What the CPU shows while a phase runs. The instruction comes from the current state, or
+    /// is decoded from IR once we are past Decode. That decode fails for 1101 if the custom
+    /// instruction was unloaded part way through, which is an illegal opcode like any other
+    fn phase_cpu_state(&mut self, phase: CycleState) -> Result<CpuState, String> {
+        let mut instruction = || match self.cpu_state.to_instruction() {
+            Some(op) => Ok(op),
+            None => self.decode(),
+        };
+        Ok(match phase {
+            CycleState::Fetch => CpuState::Fetch,
+            CycleState::Decode => CpuState::Decode,
+            CycleState::EvaluateAddress => CpuState::EvaluateAddress(instruction()?),
+            CycleState::FetchOperands => CpuState::FetchOperands(instruction()?),
+            CycleState::Execute => CpuState::ExecuteOperation(instruction()?),
+            CycleState::StoreResult => CpuState::StoreResult(instruction()?),
+        })
+    }
+
     /// Handle implicit memory operations that occur between phases
     fn handle_implicit_memory_operations(&mut self) -> Result<(), String> {
         let span = tracing::trace_span!("implicit_memory_ops");
@@ -210,7 +191,7 @@ impl Emulator {
 
         // Check if there's a pending memory write
         if self.execute_state.memory_write_pending {
-            let addr = self.mar.get() as usize;
+            let addr = self.word_address(self.mar.get());
             let value = self.mdr.get();
 
             // Check write permissions
@@ -222,11 +203,8 @@ impl Emulator {
             }
 
             if addr < self.memory.len() {
-                self.memory[addr].set(value);
+                self.write_memory(addr, value);
                 tracing::trace!("Implicit memory write: [0x{:04X}] <- 0x{:04X}", addr, value);
-                if value == 0 && addr == MCR_ADDR {
-                    self.halted = true;
-                }
             } else {
                 return Err(format!("Memory write address out of bounds: 0x{addr:04X}"));
             }
@@ -238,7 +216,7 @@ impl Emulator {
 
         // Check if there's a pending memory read (MAR was set in previous phase)
         if self.execute_state.memory_read_pending {
-            let addr = self.mar.get() as usize;
+            let addr = self.word_address(self.mar.get());
 
             // Check read permissions
             let addr_cell = EmulatorCell::new(addr as u16);
@@ -249,7 +227,7 @@ impl Emulator {
             }
 
             if addr < self.memory.len() {
-                let value = self.memory[addr].get();
+                let value = self.read_memory(addr);
                 self.mdr.set(value);
                 tracing::trace!(
                     "Implicit memory read: [0x{:04X}] -> MDR = 0x{:04X}",
@@ -260,16 +238,41 @@ impl Emulator {
                 return Err(format!("Memory read address out of bounds: 0x{addr:04X}"));
             }
 
-            if addr == KBDR_ADDR {
-                self.memory[KBSR_ADDR].set(0x0000);
-            }
-
             self.execute_state.memory_read_pending = false;
         }
 
         Ok(())
     }
 
+    /// Read a word for the CPU, with everything that watches reads (the keyboard, the trackers)
+    pub(super) fn read_memory(&mut self, addr: usize) -> u16 {
+        let value = self.memory[addr].get();
+        let cache = self.cache_access(addr, AccessKind::Read);
+        // the fetch is phase 0, it's counted as an instruction
+        if self.execute_state.current_phase != 0 {
+            self.counters.memory_reads += 1;
+        }
+        self.track_memory_read(addr);
+        self.log_memory_read(addr, cache);
+        if addr == KBDR_ADDR {
+            self.memory[KBSR_ADDR].set(0x0000);
+        }
+        value
+    }
+
+    /// Write a word for the CPU, with everything that watches writes
+    pub(super) fn write_memory(&mut self, addr: usize, value: u16) {
+        let old = self.memory[addr].get();
+        self.memory[addr].set(value);
+        let cache = self.cache_access(addr, AccessKind::Write);
+        self.counters.memory_writes += 1;
+        self.track_memory_write(addr);
+        self.log_memory_write(addr, old, cache);
+        if value == 0 && addr == MCR_ADDR {
+            self.halted = true;
+        }
+    }
+
     /// Execute the entire instruction
     pub fn step_instruction(&mut self) -> Result<(), String> {
         let span = tracing::trace_span!("step_instruction");
@@ -295,14 +298,16 @@ impl Emulator {
                 source,
                 destination,
             } => {
-                let value = self.get_source_value(source)?;
+                let (source, destination) = (source.clone(), destination.clone());
+                self.track_transfer(&source, &destination);
+                let value = self.get_source_value(&source)?;
                 tracing::trace!(
                     "Transfer: {} -> {} (value: 0x{:04X})",
                     source,
                     destination,
                     value.get()
                 );
-                self.set_destination_value(&destination.clone(), value.get())?;
+                self.set_destination_value(&destination, value.get())?;
             }
 
             MicroOp::Alu {
@@ -310,12 +315,17 @@ impl Emulator {
                 operand1,
                 operand2,
             } => {
-                let val1 = self.get_source_value(operand1)?;
-                let val2 = self.get_source_value(operand2)?;
+                let (operation, operand1, operand2) =
+                    (*operation, operand1.clone(), operand2.clone());
+                self.track_alu(&operation, &operand1, &operand2);
+                let val1 = self.get_source_value(&operand1)?;
+                let val2 = self.get_source_value(&operand2)?;
                 self.alu.op = Some(match operation {
                     MAluOp::Add => AluOp::Add(val1, val2),
                     MAluOp::And => AluOp::And(val1, val2),
                     MAluOp::Not => AluOp::Not(val1),
+                    MAluOp::Mul => AluOp::Mul(val1, val2),
+                    MAluOp::Xor => AluOp::Xor(val1, val2),
                 });
                 if let Some(alu_op) = self.alu.op.take() {
                     self.alu.alu_out = alu_op.execute();
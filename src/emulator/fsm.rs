@@ -0,0 +1,238 @@
+//! The numbered states of the textbook LC-3 control FSM (Patt & Patel appendix C, 2nd edition numbering),
+//! and which of our phases does the work of each one
+
+use crate::emulator::{CpuState, Emulator, PrivilegeLevel, PSR_ADDR};
+
+/// One state of the control FSM
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FsmState {
+    pub number: u8,
+    /// What it does, as written in the book
+    pub rtl: &'static str,
+    /// The last of our phases (0-5) that does part of its work, it's over once that phase has run
+    pub phase: usize,
+}
+
+const fn state(number: u8, rtl: &'static str, phase: usize) -> FsmState {
+    FsmState { number, rtl, phase }
+}
+
+/// What every instruction goes through first
+const FETCH_DECODE: [FsmState; 4] = [
+    state(18, "MAR<-PC, PC<-PC+1, [INT]", 0),
+    state(33, "MDR<-M", 0),
+    state(35, "IR<-MDR", 1),
+    state(32, "BEN<-IR[11]&N + IR[10]&Z + IR[9]&P, [IR[15:12]]", 1),
+];
+
+const OPCODE_NAMES: [&str; 16] = [
+    "BR", "ADD", "LD", "ST", "JSR", "AND", "LDR", "STR", "RTI", "NOT", "LDI", "STI", "JMP",
+    "reserved", "LEA", "TRAP",
+];
+
+/// Everything the FSM needs to pick a path for an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Inputs {
+    pub instruction: u16,
+    /// The condition codes as NZP in the low 3 bits
+    pub nzp: u16,
+    pub user: bool,
+    /// The PSR an RTI would pop is a user mode one
+    pub returning_to_user: bool,
+    /// A custom instruction is loaded for 1101
+    pub custom: bool,
+}
+
+/// Whether a BR is taken
+fn ben(inputs: &Inputs) -> bool {
+    (inputs.instruction >> 9) & 0b111 & inputs.nzp != 0
+}
+
+/// The states an instruction goes through, from fetch back to 18
+pub fn path(inputs: &Inputs) -> Vec<FsmState> {
+    let mut states = FETCH_DECODE.to_vec();
+    let ir11 = inputs.instruction & 0x0800 != 0;
+    states.extend_from_slice(&match inputs.instruction >> 12 {
+        0b0000 if ben(inputs) => vec![state(0, "[BEN]", 2), state(22, "PC<-PC+off9", 5)],
+        0b0000 => vec![state(0, "[BEN]", 5)],
+        0b0001 => vec![state(1, "DR<-SR1+OP2, set CC", 5)],
+        0b0101 => vec![state(5, "DR<-SR1&OP2, set CC", 5)],
+        0b1001 => vec![state(9, "DR<-NOT(SR), set CC", 5)],
+        0b1100 => vec![state(12, "PC<-BaseR", 5)],
+        0b0100 if ir11 => vec![
+            state(4, "[IR[11]]", 2),
+            state(21, "R7<-PC, PC<-PC+off11", 5),
+        ],
+        0b0100 => vec![state(4, "[IR[11]]", 2), state(20, "R7<-PC, PC<-BaseR", 5)],
+        0b0010 => vec![
+            state(2, "MAR<-PC+off9", 3),
+            state(25, "MDR<-M", 3),
+            state(27, "DR<-MDR, set CC", 5),
+        ],
+        0b0110 => vec![
+            state(6, "MAR<-B+off6", 3),
+            state(25, "MDR<-M", 3),
+            state(27, "DR<-MDR, set CC", 5),
+        ],
+        0b1010 => vec![
+            state(10, "MAR<-PC+off9", 3),
+            state(24, "MDR<-M", 3),
+            state(26, "MAR<-MDR", 4),
+            state(25, "MDR<-M", 4),
+            state(27, "DR<-MDR, set CC", 5),
+        ],
+        0b1110 => vec![state(14, "DR<-PC+off9", 5)],
+        0b0011 => vec![
+            state(3, "MAR<-PC+off9", 5),
+            state(23, "MDR<-SR", 5),
+            state(16, "M[MAR]<-MDR", 5),
+        ],
+        0b0111 => vec![
+            state(7, "MAR<-B+off6", 5),
+            state(23, "MDR<-SR", 5),
+            state(16, "M[MAR]<-MDR", 5),
+        ],
+        0b1011 => vec![
+            state(11, "MAR<-PC+off9", 3),
+            state(29, "MDR<-M", 3),
+            state(31, "MAR<-MDR", 5),
+            state(23, "MDR<-SR", 5),
+            state(16, "M[MAR]<-MDR", 5),
+        ],
+        // we push PSR and PC to the supervisor stack instead of using R7, like the 3rd edition
+        0b1111 => {
+            let mut states = vec![state(
+                15,
+                "Table'Vector<-x00'IR[7:0], MDR<-PSR, PSR[15]<-0, [PSR[15]]",
+                4,
+            )];
+            if inputs.user {
+                states.push(state(45, "Saved.USP<-R6, R6<-Saved.SSP", 4));
+            }
+            states.extend_from_slice(&[
+                state(37, "MAR<-R6-1, R6<-R6-1", 4),
+                state(41, "M[MAR]<-MDR", 4),
+                state(43, "MDR<-PC", 4),
+                state(47, "MAR<-R6-1, R6<-R6-1", 4),
+                state(48, "M[MAR]<-MDR", 4),
+                state(50, "MAR<-Table'Vector", 4),
+                state(52, "MDR<-M", 4),
+                state(54, "PC<-MDR", 4),
+            ]);
+            states
+        }
+        0b1000 if inputs.user => vec![
+            state(8, "MAR<-R6, [PSR[15]]", 3),
+            state(44, "privilege mode exception", 3),
+        ],
+        0b1000 => vec![
+            state(8, "MAR<-R6, [PSR[15]]", 3),
+            state(36, "MDR<-M", 3),
+            state(38, "PC<-MDR", 4),
+            state(39, "MAR<-R6+1, R6<-R6+1", 4),
+            state(40, "MDR<-M", 4),
+            state(42, "PSR<-MDR", 5),
+            state(34, "R6<-R6+1, [PSR[15]]", 5),
+            if inputs.returning_to_user {
+                state(59, "Saved.SSP<-R6, R6<-Saved.USP", 5)
+            } else {
+                state(51, "nothing, staying in supervisor mode", 5)
+            },
+        ],
+        _ if inputs.custom => vec![state(13, "user defined instruction", 5)],
+        _ => vec![state(13, "illegal opcode exception", 1)],
+    });
+    states
+}
+
+/// Why the FSM goes from `path[index]` to the state after it (or back to 18)
+pub fn transition(path: &[FsmState], index: usize, inputs: &Inputs) -> String {
+    let Some(from) = path.get(index) else {
+        return String::new();
+    };
+    let next = path.get(index + 1).map_or(18, |s| s.number);
+    let opcode = (inputs.instruction >> 12) as usize;
+    let why = match from.number {
+        32 => format!("IR[15:12] = {opcode:04b} ({})", OPCODE_NAMES[opcode]),
+        0 => format!("BEN = {}", ben(inputs) as u8),
+        4 => format!("IR[11] = {}", (inputs.instruction >> 11) & 1),
+        8 | 15 => format!("PSR[15] = {}", inputs.user as u8),
+        34 => format!("PSR[15] = {}", inputs.returning_to_user as u8),
+        33 | 24 | 25 | 29 | 36 | 40 | 41 | 48 | 52 => "R, memory is ready".to_owned(),
+        _ if index + 1 == path.len() => "next instruction".to_owned(),
+        _ => "always".to_owned(),
+    };
+    format!("{} -> {next}: {why}", from.number)
+}
+
+impl Emulator {
+    /// The phase that runs on the next micro step
+    pub fn next_phase(&self) -> usize {
+        match self.cpu_state {
+            CpuState::Fetch => 0,
+            _ => self.execute_state.current_phase,
+        }
+    }
+
+    /// What the FSM is deciding on right now. Before the fetch that's the instruction at PC
+    pub fn fsm_inputs(&self) -> Inputs {
+        let address = match self.cpu_state {
+            CpuState::Fetch => self.pc.get() as usize,
+            _ => self.currently_executing,
+        };
+        Inputs {
+            instruction: self.memory[address].get(),
+            nzp: self.memory[PSR_ADDR].get() & 0b111,
+            user: matches!(self.priv_level(), PrivilegeLevel::User),
+            returning_to_user: self.memory[self.r[6].get().wrapping_add(1) as usize].get() & 0x8000
+                != 0,
+            custom: super::ops::custom::loaded().is_some(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn numbers(inputs: &Inputs) -> Vec<u8> {
+        path(inputs).iter().map(|s| s.number).collect()
+    }
+
+    #[test]
+    fn paths_follow_the_book() {
+        let inputs = |instruction| Inputs {
+            instruction,
+            nzp: 0b010,
+            user: true,
+            returning_to_user: false,
+            custom: false,
+        };
+        // LDI R1, #3
+        assert_eq!(
+            numbers(&inputs(0b1010_001_000000011)),
+            [18, 33, 35, 32, 10, 24, 26, 25, 27]
+        );
+        // BRz is taken with Z set, BRn isn't
+        assert_eq!(
+            numbers(&inputs(0b0000_010_000000011)),
+            [18, 33, 35, 32, 0, 22]
+        );
+        assert_eq!(numbers(&inputs(0b0000_100_000000011)), [18, 33, 35, 32, 0]);
+        // HALT from user mode, through the same states as an exception
+        assert_eq!(
+            numbers(&inputs(0xF025)),
+            [18, 33, 35, 32, 15, 45, 37, 41, 43, 47, 48, 50, 52, 54]
+        );
+        // RTI in user mode
+        assert_eq!(numbers(&inputs(0x8000)), [18, 33, 35, 32, 8, 44]);
+
+        let add = inputs(0x1261);
+        let path = path(&add);
+        assert_eq!(
+            transition(&path, 3, &add),
+            "32 -> 1: IR[15:12] = 0001 (ADD)"
+        );
+        assert_eq!(transition(&path, 4, &add), "1 -> 18: next instruction");
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+
+use crate::emulator::micro_op::{DataDestination, DataSource, MAluOp};
+use crate::emulator::{Emulator, USER_SPACE_END, USER_SPACE_START};
+
+/// Something that got read before anything was written to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UninitLocation {
+    Register(u16),
+    Memory(u16),
+}
+
+impl fmt::Display for UninitLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UninitLocation::Register(n) => write!(f, "R{n}"),
+            UninitLocation::Memory(addr) => write!(f, "MEM[x{addr:04X}]"),
+        }
+    }
+}
+
+/// One use of an uninitialised value by a user program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UninitRead {
+    pub location: UninitLocation,
+    /// Address of the instruction that used the value
+    pub instruction_address: usize,
+    /// Source line of that instruction (1 based), if it is in the last compiled program
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for UninitRead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} used before it was initialised by the instruction at x{:04X}",
+            self.location, self.instruction_address
+        )?;
+        if let Some(line) = self.line {
+            write!(f, " (line {line})")?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps a shadow "has been written" bit for the registers, MDR and memory since load/reset.
+///
+/// The bit gets copied around with the value (a register stored to memory and loaded back stays uninitialised)
+/// and we only complain when an uninitialised value actually gets *used*: as an ALU operand, an address or a jump target.
+/// Saving a register you never set (like `ST R1, SAVE1` at the start of a subroutine) is fine.
+///
+/// Only instructions in user space get reported, the OS shuffles plenty of registers around on our behalf.
+/// Each location is reported once, after that it counts as initialised (the junk has been "used" already).
+#[derive(Debug, Clone)]
+pub struct InitTracker {
+    registers: [bool; 8],
+    mdr: bool,
+    memory: Box<[bool; 65536]>,
+    /// Stop running when an uninitialised value is used
+    pub break_on_read: bool,
+    /// Everything found so far, oldest first
+    pub reads: Vec<UninitRead>,
+}
+
+impl Default for InitTracker {
+    fn default() -> Self {
+        Self {
+            registers: [false; 8],
+            mdr: false,
+            memory: Box::new([false; 65536]),
+            break_on_read: false,
+            reads: Vec::new(),
+        }
+    }
+}
+
+impl InitTracker {
+    pub fn register_initialised(&self, reg: u16) -> bool {
+        self.registers[reg as usize & 0b111]
+    }
+
+    pub fn memory_initialised(&self, addr: usize) -> bool {
+        // device registers are always "initialised", the hardware owns them
+        addr > USER_SPACE_END || self.memory[addr]
+    }
+
+    pub fn mark_register(&mut self, reg: u16, initialised: bool) {
+        self.registers[reg as usize & 0b111] = initialised;
+    }
+
+    pub fn mark_memory(&mut self, addr: usize, initialised: bool) {
+        self.memory[addr] = initialised;
+    }
+
+    /// Forget about the registers (on a soft reset memory stays so we keep its bits)
+    pub fn reset_registers(&mut self) {
+        self.registers = [false; 8];
+        self.mdr = false;
+        self.reads.clear();
+    }
+}
+
+impl Emulator {
+    fn in_user_code(&self) -> bool {
+        (USER_SPACE_START..=USER_SPACE_END).contains(&self.currently_executing)
+    }
+
+    fn report_uninit(&mut self, location: UninitLocation) {
+        // one report per location, afterwards treat it as written
+        match location {
+            UninitLocation::Register(reg) => self.init_tracker.mark_register(reg, true),
+            UninitLocation::Memory(addr) => self.init_tracker.mark_memory(addr as usize, true),
+        }
+
+        let read = UninitRead {
+            location,
+            instruction_address: self.currently_executing,
+            line: self
+                .metadata
+                .address_to_line
+                .get(&self.currently_executing)
+                .copied(),
+        };
+        tracing::warn!("{}", read);
+        self.init_tracker.reads.push(read);
+
+        if self.init_tracker.break_on_read {
+            self.stop_running();
+        }
+    }
+
+    /// A value was used for something other than being copied around
+    fn check_use(&mut self, source: &DataSource) {
+        if !self.in_user_code() {
+            return;
+        }
+        match source {
+            DataSource::Register(reg) if !self.init_tracker.register_initialised(*reg) => {
+                self.report_uninit(UninitLocation::Register(*reg));
+            }
+            DataSource::MDR if !self.init_tracker.mdr => {
+                self.init_tracker.mdr = true;
+                self.report_uninit(UninitLocation::Memory(self.mar.get()));
+            }
+            _ => {}
+        }
+    }
+
+    pub(super) fn track_transfer(&mut self, source: &DataSource, destination: &DataDestination) {
+        match (source, destination) {
+            // just moving the value, carry the bit along with it
+            (DataSource::Register(reg), DataDestination::MDR) => {
+                self.init_tracker.mdr = self.init_tracker.register_initialised(*reg);
+            }
+            (DataSource::MDR, DataDestination::Register(reg)) => {
+                let initialised = self.init_tracker.mdr;
+                self.init_tracker.mark_register(*reg, initialised);
+            }
+            (DataSource::Register(from), DataDestination::Register(to)) => {
+                let initialised = self.init_tracker.register_initialised(*from);
+                self.init_tracker.mark_register(*to, initialised);
+            }
+            _ => {
+                self.check_use(source);
+                match destination {
+                    DataDestination::Register(reg) => self.init_tracker.mark_register(*reg, true),
+                    DataDestination::MDR => self.init_tracker.mdr = true,
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    pub(super) fn track_alu(
+        &mut self,
+        operation: &MAluOp,
+        operand1: &DataSource,
+        operand2: &DataSource,
+    ) {
+        // AND Rx, Rx, #0 is how you clear a register, the old value doesn't matter
+        if matches!(operation, MAluOp::And) && matches!(operand2, DataSource::Immediate(0)) {
+            return;
+        }
+        self.check_use(operand1);
+        if !matches!(operation, MAluOp::Not) {
+            self.check_use(operand2);
+        }
+    }
+
+    pub(super) fn track_memory_read(&mut self, addr: usize) {
+        self.init_tracker.mdr = self.init_tracker.memory_initialised(addr);
+    }
+
+    pub(super) fn track_memory_write(&mut self, addr: usize) {
+        let initialised = self.init_tracker.mdr;
+        self.init_tracker.mark_memory(addr, initialised);
+    }
+
+    /// Running off the end of the program (or jumping somewhere silly) executes memory nobody wrote
+    pub(super) fn track_fetch(&mut self, addr: usize) {
+        if self.in_user_code() && !self.init_tracker.memory_initialised(addr) {
+            self.report_uninit(UninitLocation::Memory(addr as u16));
+        }
+    }
+}
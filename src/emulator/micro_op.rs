@@ -3,6 +3,7 @@ use std::{collections::HashMap, sync::Arc};
 
 use egui::text::LayoutJob;
 use egui::{RichText, Style, WidgetText};
+use serde::{Deserialize, Serialize};
 
 use crate::emulator::{Emulator, Exception};
 use crate::theme::ThemeSettings;
@@ -62,11 +63,16 @@ impl EguiDisplay for CycleState {
 }
 
 /// The operations the ALU can perform.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MAluOp {
     Add,
     And,
     Not,
+    /// Not in the real LC-3, there for user defined instructions (keeps the low 16 bits). A plan
+    /// runs its steps straight through with no branches, so a multiply can't be built from the others
+    Mul,
+    /// The LC-3b's XOR
+    Xor,
 }
 
 impl fmt::Display for MAluOp {
@@ -75,6 +81,8 @@ impl fmt::Display for MAluOp {
             MAluOp::Add => write!(f, "+"),
             MAluOp::And => write!(f, "&"),
             MAluOp::Not => write!(f, "NOT"),
+            MAluOp::Mul => write!(f, "*"),
+            MAluOp::Xor => write!(f, "^"),
         }
     }
 }
@@ -522,6 +530,23 @@ macro_rules! micro_op {
         }
     };
 
+    // ALU XOR (LC-3b)
+    (ALU_OUT <- R($src1:expr) ^ R($src2:expr)) => {
+        $crate::emulator::micro_op::MicroOp::Alu {
+            operation: $crate::emulator::micro_op::MAluOp::Xor,
+            operand1: $crate::emulator::micro_op::DataSource::Register($src1),
+            operand2: $crate::emulator::micro_op::DataSource::Register($src2),
+        }
+    };
+
+    (ALU_OUT <- R($src:expr) ^ IMM($val:expr)) => {
+        $crate::emulator::micro_op::MicroOp::Alu {
+            operation: $crate::emulator::micro_op::MAluOp::Xor,
+            operand1: $crate::emulator::micro_op::DataSource::Register($src),
+            operand2: $crate::emulator::micro_op::DataSource::Immediate($val),
+        }
+    };
+
     // ALU NOT
     (ALU_OUT <- NOT R($src:expr)) => {
         $crate::emulator::micro_op::MicroOp::Alu {
@@ -0,0 +1,1182 @@
+//! A microcoded LC-3, for checking a microcoded implementation against.
+//!
+//! The control store holds one microinstruction per FSM state, in the format of Patt & Patel appendix C:
+//! the datapath control signals (LD.*, Gate*, the muxes, ALUK, MIO.EN, R.W) and the microsequencer's J, COND and IRD fields.
+//! The microsequencer runs one of them per clock cycle on the same registers and memory the phase executor uses.
+//!
+//! State numbers are the 2nd edition's so they match the FSM pane. Where our machine differs from the book so does the microcode:
+//! - TRAP pushes PSR and PC onto the supervisor stack like the 3rd edition, reusing the interrupt states (45, 37, 41, 43, 47, 48, 50, 52, 54)
+//! - exceptions push the PC after the faulting instruction (state 43 gates PC, not PC-1)
+//! - an access control violation is caught when memory is enabled on a protected address and goes to state 60
+//! - memory takes `timing.memory_latency` cycles, the "wait for R" states repeat until it answers
+//! - 1101 always takes the illegal opcode exception, custom instructions only run on the phase executor
+//! - a TRAP set to run natively does its routine in state 15 and goes straight back to 18
+
+use std::fmt;
+
+use crate::emulator::micro_op::{DataDestination, DataSource, MAluOp};
+use crate::emulator::{
+    area_from_address, BitAddressable, CpuState, Emulator, EmulatorCell, Isa, OpCode, PSR_ADDR,
+};
+
+/// Which register drives the bus, only one at a time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Gate {
+    Pc,
+    Mdr,
+    Alu,
+    MarMux,
+    Vector,
+    Psr,
+    Sp,
+}
+
+impl fmt::Display for Gate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Gate::Pc => "GatePC",
+            Gate::Mdr => "GateMDR",
+            Gate::Alu => "GateALU",
+            Gate::MarMux => "GateMARMUX",
+            Gate::Vector => "GateVector",
+            Gate::Psr => "GatePSR",
+            Gate::Sp => "GateSP",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcMux {
+    PcPlus1,
+    Bus,
+    Adder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrMux {
+    Ir11_9,
+    R7,
+    Sp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sr1Mux {
+    Ir11_9,
+    Ir8_6,
+    Sp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Addr1Mux {
+    Pc,
+    BaseR,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Addr2Mux {
+    Zero,
+    Offset6,
+    PcOffset9,
+    PcOffset11,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpMux {
+    SpPlus1,
+    SpMinus1,
+    SavedSsp,
+    SavedUsp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarMux {
+    Zext7_0,
+    Adder,
+}
+
+/// What goes in Table'Vector. TRAP uses the trap vector table (x00), the exceptions the interrupt vector table (x01)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorMux {
+    Trap,
+    Privilege,
+    IllegalOpcode,
+    Acv,
+}
+
+/// PSR[15] from Set.Priv (always supervisor here) or the whole PSR from the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PsrMux {
+    Individual,
+    Bus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluK {
+    Add,
+    And,
+    Not,
+    PassA,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rw {
+    Read,
+    Write,
+}
+
+/// The datapath half of a microinstruction. A mux that is `None` is a don't care
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signals {
+    pub ld_mar: bool,
+    pub ld_mdr: bool,
+    pub ld_ir: bool,
+    pub ld_ben: bool,
+    pub ld_reg: bool,
+    pub ld_cc: bool,
+    pub ld_pc: bool,
+    pub ld_priv: bool,
+    pub ld_saved_ssp: bool,
+    pub ld_saved_usp: bool,
+    pub ld_vector: bool,
+    pub gate: Option<Gate>,
+    pub pcmux: Option<PcMux>,
+    pub drmux: Option<DrMux>,
+    pub sr1mux: Option<Sr1Mux>,
+    pub addr1mux: Option<Addr1Mux>,
+    pub addr2mux: Option<Addr2Mux>,
+    pub spmux: Option<SpMux>,
+    pub marmux: Option<MarMux>,
+    pub vectormux: Option<VectorMux>,
+    pub psrmux: Option<PsrMux>,
+    pub aluk: Option<AluK>,
+    /// MIO.EN and R.W
+    pub memory: Option<Rw>,
+}
+
+const NONE: Signals = Signals {
+    ld_mar: false,
+    ld_mdr: false,
+    ld_ir: false,
+    ld_ben: false,
+    ld_reg: false,
+    ld_cc: false,
+    ld_pc: false,
+    ld_priv: false,
+    ld_saved_ssp: false,
+    ld_saved_usp: false,
+    ld_vector: false,
+    gate: None,
+    pcmux: None,
+    drmux: None,
+    sr1mux: None,
+    addr1mux: None,
+    addr2mux: None,
+    spmux: None,
+    marmux: None,
+    vectormux: None,
+    psrmux: None,
+    aluk: None,
+    memory: None,
+};
+
+impl Signals {
+    /// The asserted signals and set muxes, named like the book
+    pub fn active(&self) -> Vec<String> {
+        let mut active: Vec<String> = [
+            (self.ld_mar, "LD.MAR"),
+            (self.ld_mdr, "LD.MDR"),
+            (self.ld_ir, "LD.IR"),
+            (self.ld_ben, "LD.BEN"),
+            (self.ld_reg, "LD.REG"),
+            (self.ld_cc, "LD.CC"),
+            (self.ld_pc, "LD.PC"),
+            (self.ld_priv, "LD.Priv"),
+            (self.ld_saved_ssp, "LD.SavedSSP"),
+            (self.ld_saved_usp, "LD.SavedUSP"),
+            (self.ld_vector, "LD.Vector"),
+        ]
+        .into_iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, name)| name.to_owned())
+        .collect();
+        if let Some(gate) = self.gate {
+            active.push(gate.to_string());
+        }
+        let muxes = [
+            self.pcmux.map(|m| {
+                format!(
+                    "PCMUX={}",
+                    match m {
+                        PcMux::PcPlus1 => "PC+1",
+                        PcMux::Bus => "BUS",
+                        PcMux::Adder => "ADDER",
+                    }
+                )
+            }),
+            self.drmux.map(|m| {
+                format!(
+                    "DRMUX={}",
+                    match m {
+                        DrMux::Ir11_9 => "11.9",
+                        DrMux::R7 => "R7",
+                        DrMux::Sp => "SP",
+                    }
+                )
+            }),
+            self.sr1mux.map(|m| {
+                format!(
+                    "SR1MUX={}",
+                    match m {
+                        Sr1Mux::Ir11_9 => "11.9",
+                        Sr1Mux::Ir8_6 => "8.6",
+                        Sr1Mux::Sp => "SP",
+                    }
+                )
+            }),
+            self.addr1mux.map(|m| {
+                format!(
+                    "ADDR1MUX={}",
+                    match m {
+                        Addr1Mux::Pc => "PC",
+                        Addr1Mux::BaseR => "BaseR",
+                    }
+                )
+            }),
+            self.addr2mux.map(|m| {
+                format!(
+                    "ADDR2MUX={}",
+                    match m {
+                        Addr2Mux::Zero => "ZERO",
+                        Addr2Mux::Offset6 => "offset6",
+                        Addr2Mux::PcOffset9 => "PCoffset9",
+                        Addr2Mux::PcOffset11 => "PCoffset11",
+                    }
+                )
+            }),
+            self.spmux.map(|m| {
+                format!(
+                    "SPMUX={}",
+                    match m {
+                        SpMux::SpPlus1 => "SP+1",
+                        SpMux::SpMinus1 => "SP-1",
+                        SpMux::SavedSsp => "Saved.SSP",
+                        SpMux::SavedUsp => "Saved.USP",
+                    }
+                )
+            }),
+            self.marmux.map(|m| {
+                format!(
+                    "MARMUX={}",
+                    match m {
+                        MarMux::Zext7_0 => "7.0",
+                        MarMux::Adder => "ADDER",
+                    }
+                )
+            }),
+            self.vectormux.map(|m| {
+                format!(
+                    "VectorMUX={}",
+                    match m {
+                        VectorMux::Trap => "x00'IR[7:0]",
+                        VectorMux::Privilege => "x01'x00",
+                        VectorMux::IllegalOpcode => "x01'x01",
+                        VectorMux::Acv => "x01'x02",
+                    }
+                )
+            }),
+            self.psrmux.map(|m| match m {
+                PsrMux::Individual => "PSRMUX=individual, Set.Priv=0".to_owned(),
+                PsrMux::Bus => "PSRMUX=BUS".to_owned(),
+            }),
+            self.aluk.map(|m| {
+                format!(
+                    "ALUK={}",
+                    match m {
+                        AluK::Add => "ADD",
+                        AluK::And => "AND",
+                        AluK::Not => "NOT",
+                        AluK::PassA => "PASSA",
+                    }
+                )
+            }),
+            self.memory.map(|rw| {
+                format!(
+                    "MIO.EN, R.W={}",
+                    match rw {
+                        Rw::Read => "RD",
+                        Rw::Write => "WR",
+                    }
+                )
+            }),
+        ];
+        active.extend(muxes.into_iter().flatten());
+        active
+    }
+}
+
+/// What the microsequencer ORs into J
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    /// 000
+    Unconditional,
+    /// 001, R into J[1]
+    Ready,
+    /// 010, BEN into J[2]
+    Branch,
+    /// 011, IR[11] into J[0]
+    AddrMode,
+    /// 100, PSR[15] into J[3]
+    Privilege,
+    /// 101, INT into J[4]
+    Interrupt,
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Cond::Unconditional => "000",
+            Cond::Ready => "001 (R)",
+            Cond::Branch => "010 (BEN)",
+            Cond::AddrMode => "011 (IR[11])",
+            Cond::Privilege => "100 (PSR[15])",
+            Cond::Interrupt => "101 (INT)",
+        })
+    }
+}
+
+/// One word of the control store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Microinstruction {
+    /// What it does, as written in the book
+    pub rtl: &'static str,
+    pub signals: Signals,
+    pub ird: bool,
+    pub cond: Cond,
+    pub j: u8,
+}
+
+/// Where an access control violation goes, the 2nd edition doesn't have one
+pub const ACV_STATE: u8 = 60;
+
+const fn mi(rtl: &'static str, signals: Signals, cond: Cond, j: u8) -> Microinstruction {
+    Microinstruction {
+        rtl,
+        signals,
+        ird: false,
+        cond,
+        j,
+    }
+}
+
+/// MAR<-PC+off9
+const MAR_PC_OFF9: Signals = Signals {
+    ld_mar: true,
+    gate: Some(Gate::MarMux),
+    marmux: Some(MarMux::Adder),
+    addr1mux: Some(Addr1Mux::Pc),
+    addr2mux: Some(Addr2Mux::PcOffset9),
+    ..NONE
+};
+
+/// MAR<-BaseR+off6
+const MAR_BASE_OFF6: Signals = Signals {
+    ld_mar: true,
+    gate: Some(Gate::MarMux),
+    marmux: Some(MarMux::Adder),
+    sr1mux: Some(Sr1Mux::Ir8_6),
+    addr1mux: Some(Addr1Mux::BaseR),
+    addr2mux: Some(Addr2Mux::Offset6),
+    ..NONE
+};
+
+/// MDR<-M, waiting for R
+const READ: Signals = Signals {
+    ld_mdr: true,
+    memory: Some(Rw::Read),
+    ..NONE
+};
+
+/// M[MAR]<-MDR, waiting for R
+const WRITE: Signals = Signals {
+    memory: Some(Rw::Write),
+    ..NONE
+};
+
+/// MAR<-MDR
+const MAR_MDR: Signals = Signals {
+    ld_mar: true,
+    gate: Some(Gate::Mdr),
+    ..NONE
+};
+
+/// MAR<-R6-1, R6<-R6-1 for pushing
+const PUSH: Signals = Signals {
+    ld_mar: true,
+    ld_reg: true,
+    gate: Some(Gate::Sp),
+    sr1mux: Some(Sr1Mux::Sp),
+    spmux: Some(SpMux::SpMinus1),
+    drmux: Some(DrMux::Sp),
+    ..NONE
+};
+
+/// Table'Vector <- ..., MDR<-PSR, PSR[15]<-0: the start of TRAP and every exception
+const fn enter_supervisor(vector: VectorMux) -> Signals {
+    Signals {
+        ld_mdr: true,
+        ld_priv: true,
+        ld_vector: true,
+        gate: Some(Gate::Psr),
+        psrmux: Some(PsrMux::Individual),
+        vectormux: Some(vector),
+        ..NONE
+    }
+}
+
+/// DR<-SR1 op SR2, set CC
+const fn operate(aluk: AluK) -> Signals {
+    Signals {
+        ld_reg: true,
+        ld_cc: true,
+        gate: Some(Gate::Alu),
+        aluk: Some(aluk),
+        sr1mux: Some(Sr1Mux::Ir8_6),
+        drmux: Some(DrMux::Ir11_9),
+        ..NONE
+    }
+}
+
+/// The microinstruction for a state, `None` for the numbers nothing uses
+pub fn control_store(state: u8) -> Option<Microinstruction> {
+    use Cond::*;
+    Some(match state {
+        // fetch and decode
+        18 => mi(
+            "MAR<-PC, PC<-PC+1, [INT]",
+            Signals {
+                ld_mar: true,
+                ld_pc: true,
+                gate: Some(Gate::Pc),
+                pcmux: Some(PcMux::PcPlus1),
+                ..NONE
+            },
+            Interrupt,
+            33,
+        ),
+        33 => mi("MDR<-M, [R]", READ, Ready, 33),
+        35 => mi(
+            "IR<-MDR",
+            Signals {
+                ld_ir: true,
+                gate: Some(Gate::Mdr),
+                ..NONE
+            },
+            Unconditional,
+            32,
+        ),
+        32 => Microinstruction {
+            rtl: "BEN<-IR[11]&N + IR[10]&Z + IR[9]&P, [IR[15:12]]",
+            signals: Signals {
+                ld_ben: true,
+                ..NONE
+            },
+            ird: true,
+            cond: Unconditional,
+            j: 0,
+        },
+
+        // operate
+        1 => mi("DR<-SR1+OP2, set CC", operate(AluK::Add), Unconditional, 18),
+        5 => mi("DR<-SR1&OP2, set CC", operate(AluK::And), Unconditional, 18),
+        9 => mi("DR<-NOT(SR), set CC", operate(AluK::Not), Unconditional, 18),
+        14 => mi(
+            "DR<-PC+off9",
+            Signals {
+                ld_reg: true,
+                gate: Some(Gate::MarMux),
+                marmux: Some(MarMux::Adder),
+                addr1mux: Some(Addr1Mux::Pc),
+                addr2mux: Some(Addr2Mux::PcOffset9),
+                drmux: Some(DrMux::Ir11_9),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+
+        // control
+        0 => mi("[BEN]", NONE, Branch, 18),
+        22 => mi(
+            "PC<-PC+off9",
+            Signals {
+                ld_pc: true,
+                pcmux: Some(PcMux::Adder),
+                addr1mux: Some(Addr1Mux::Pc),
+                addr2mux: Some(Addr2Mux::PcOffset9),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+        12 => mi(
+            "PC<-BaseR",
+            Signals {
+                ld_pc: true,
+                pcmux: Some(PcMux::Adder),
+                sr1mux: Some(Sr1Mux::Ir8_6),
+                addr1mux: Some(Addr1Mux::BaseR),
+                addr2mux: Some(Addr2Mux::Zero),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+        4 => mi("[IR[11]]", NONE, AddrMode, 20),
+        20 => mi(
+            "R7<-PC, PC<-BaseR",
+            Signals {
+                ld_reg: true,
+                ld_pc: true,
+                gate: Some(Gate::Pc),
+                drmux: Some(DrMux::R7),
+                pcmux: Some(PcMux::Adder),
+                sr1mux: Some(Sr1Mux::Ir8_6),
+                addr1mux: Some(Addr1Mux::BaseR),
+                addr2mux: Some(Addr2Mux::Zero),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+        21 => mi(
+            "R7<-PC, PC<-PC+off11",
+            Signals {
+                ld_reg: true,
+                ld_pc: true,
+                gate: Some(Gate::Pc),
+                drmux: Some(DrMux::R7),
+                pcmux: Some(PcMux::Adder),
+                addr1mux: Some(Addr1Mux::Pc),
+                addr2mux: Some(Addr2Mux::PcOffset11),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+
+        // loads
+        2 => mi("MAR<-PC+off9", MAR_PC_OFF9, Unconditional, 25),
+        6 => mi("MAR<-B+off6", MAR_BASE_OFF6, Unconditional, 25),
+        10 => mi("MAR<-PC+off9", MAR_PC_OFF9, Unconditional, 24),
+        24 => mi("MDR<-M, [R]", READ, Ready, 24),
+        26 => mi("MAR<-MDR", MAR_MDR, Unconditional, 25),
+        25 => mi("MDR<-M, [R]", READ, Ready, 25),
+        27 => mi(
+            "DR<-MDR, set CC",
+            Signals {
+                ld_reg: true,
+                ld_cc: true,
+                gate: Some(Gate::Mdr),
+                drmux: Some(DrMux::Ir11_9),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+
+        // stores
+        3 => mi("MAR<-PC+off9", MAR_PC_OFF9, Unconditional, 23),
+        7 => mi("MAR<-B+off6", MAR_BASE_OFF6, Unconditional, 23),
+        11 => mi("MAR<-PC+off9", MAR_PC_OFF9, Unconditional, 29),
+        29 => mi("MDR<-M, [R]", READ, Ready, 29),
+        31 => mi("MAR<-MDR", MAR_MDR, Unconditional, 23),
+        23 => mi(
+            "MDR<-SR",
+            Signals {
+                ld_mdr: true,
+                gate: Some(Gate::Alu),
+                aluk: Some(AluK::PassA),
+                sr1mux: Some(Sr1Mux::Ir11_9),
+                ..NONE
+            },
+            Unconditional,
+            16,
+        ),
+        16 => mi("M[MAR]<-MDR, [R]", WRITE, Ready, 16),
+
+        // RTI
+        8 => mi(
+            "MAR<-R6, [PSR[15]]",
+            Signals {
+                ld_mar: true,
+                gate: Some(Gate::MarMux),
+                marmux: Some(MarMux::Adder),
+                sr1mux: Some(Sr1Mux::Sp),
+                addr1mux: Some(Addr1Mux::BaseR),
+                addr2mux: Some(Addr2Mux::Zero),
+                ..NONE
+            },
+            Privilege,
+            36,
+        ),
+        36 => mi("MDR<-M, [R]", READ, Ready, 36),
+        38 => mi(
+            "PC<-MDR",
+            Signals {
+                ld_pc: true,
+                gate: Some(Gate::Mdr),
+                pcmux: Some(PcMux::Bus),
+                ..NONE
+            },
+            Unconditional,
+            39,
+        ),
+        39 => mi(
+            "MAR<-R6+1, R6<-R6+1",
+            Signals {
+                ld_mar: true,
+                ld_reg: true,
+                gate: Some(Gate::Sp),
+                sr1mux: Some(Sr1Mux::Sp),
+                spmux: Some(SpMux::SpPlus1),
+                drmux: Some(DrMux::Sp),
+                ..NONE
+            },
+            Unconditional,
+            40,
+        ),
+        40 => mi("MDR<-M, [R]", READ, Ready, 40),
+        42 => mi(
+            "PSR<-MDR",
+            Signals {
+                ld_priv: true,
+                ld_cc: true,
+                gate: Some(Gate::Mdr),
+                psrmux: Some(PsrMux::Bus),
+                ..NONE
+            },
+            Unconditional,
+            34,
+        ),
+        34 => mi(
+            "R6<-R6+1, [PSR[15]]",
+            Signals {
+                ld_reg: true,
+                gate: Some(Gate::Sp),
+                sr1mux: Some(Sr1Mux::Sp),
+                spmux: Some(SpMux::SpPlus1),
+                drmux: Some(DrMux::Sp),
+                ..NONE
+            },
+            Privilege,
+            51,
+        ),
+        51 => mi(
+            "nothing, staying in supervisor mode",
+            NONE,
+            Unconditional,
+            18,
+        ),
+        59 => mi(
+            "Saved.SSP<-R6, R6<-Saved.USP",
+            Signals {
+                ld_saved_ssp: true,
+                ld_reg: true,
+                gate: Some(Gate::Sp),
+                sr1mux: Some(Sr1Mux::Sp),
+                spmux: Some(SpMux::SavedUsp),
+                drmux: Some(DrMux::Sp),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+
+        // TRAP and the exceptions, push PSR and PC and go through Table'Vector
+        15 => mi(
+            "Table'Vector<-x00'IR[7:0], MDR<-PSR, PSR[15]<-0, [PSR[15]]",
+            enter_supervisor(VectorMux::Trap),
+            Privilege,
+            37,
+        ),
+        44 => mi(
+            "Table'Vector<-x01'x00, MDR<-PSR, PSR[15]<-0, [PSR[15]] (privilege mode exception)",
+            enter_supervisor(VectorMux::Privilege),
+            Privilege,
+            37,
+        ),
+        13 => mi(
+            "Table'Vector<-x01'x01, MDR<-PSR, PSR[15]<-0, [PSR[15]] (illegal opcode exception)",
+            enter_supervisor(VectorMux::IllegalOpcode),
+            Privilege,
+            37,
+        ),
+        ACV_STATE => mi(
+            "Table'Vector<-x01'x02, MDR<-PSR, PSR[15]<-0, [PSR[15]] (ACV exception)",
+            enter_supervisor(VectorMux::Acv),
+            Privilege,
+            37,
+        ),
+        45 => mi(
+            "Saved.USP<-R6, R6<-Saved.SSP",
+            Signals {
+                ld_saved_usp: true,
+                ld_reg: true,
+                gate: Some(Gate::Sp),
+                sr1mux: Some(Sr1Mux::Sp),
+                spmux: Some(SpMux::SavedSsp),
+                drmux: Some(DrMux::Sp),
+                ..NONE
+            },
+            Unconditional,
+            37,
+        ),
+        37 => mi("MAR<-R6-1, R6<-R6-1", PUSH, Unconditional, 41),
+        41 => mi("M[MAR]<-MDR, [R]", WRITE, Ready, 41),
+        43 => mi(
+            "MDR<-PC",
+            Signals {
+                ld_mdr: true,
+                gate: Some(Gate::Pc),
+                ..NONE
+            },
+            Unconditional,
+            47,
+        ),
+        47 => mi("MAR<-R6-1, R6<-R6-1", PUSH, Unconditional, 48),
+        48 => mi("M[MAR]<-MDR, [R]", WRITE, Ready, 48),
+        50 => mi(
+            "MAR<-Table'Vector",
+            Signals {
+                ld_mar: true,
+                gate: Some(Gate::Vector),
+                ..NONE
+            },
+            Unconditional,
+            52,
+        ),
+        52 => mi("MDR<-M, [R]", READ, Ready, 52),
+        54 => mi(
+            "PC<-MDR",
+            Signals {
+                ld_pc: true,
+                gate: Some(Gate::Mdr),
+                pcmux: Some(PcMux::Bus),
+                ..NONE
+            },
+            Unconditional,
+            18,
+        ),
+        _ => return None,
+    })
+}
+
+/// The state of the control unit that isn't one of the emulator's registers
+#[derive(Debug, Clone)]
+pub struct Microsequencer {
+    /// The state whose microinstruction runs next
+    pub state: u8,
+    pub ben: bool,
+    /// Table'Vector, the address of the handler's entry in a vector table
+    pub vector: u16,
+    /// Clock cycles run so far
+    pub cycles: u64,
+    /// What happened on the last cycle
+    pub last: Option<Cycle>,
+}
+
+impl Default for Microsequencer {
+    fn default() -> Self {
+        Self {
+            state: 18,
+            ben: false,
+            vector: 0,
+            cycles: 0,
+            last: None,
+        }
+    }
+}
+
+/// One clock cycle, for showing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cycle {
+    pub state: u8,
+    /// What was on the bus, if anything drove it
+    pub bus: Option<u16>,
+    pub next: u8,
+    /// How the microsequencer picked the next state
+    pub why: String,
+}
+
+/// A mux the microinstruction needs but left as a don't care is a bug in the control store
+fn set<T>(mux: Option<T>, state: u8, name: &str) -> Result<T, String> {
+    mux.ok_or_else(|| format!("State {state} uses {name} without setting it"))
+}
+
+impl Emulator {
+    /// Switch between the microsequencer and the phase executor. Only do it between instructions.
+    /// The control store is the LC-3's, so an LC-3b always uses the phase executor
+    pub fn use_microsequencer(&mut self, on: bool) {
+        self.microsequencer = (on && self.isa == Isa::Lc3).then(Microsequencer::default);
+    }
+
+    /// Run one microinstruction, one clock cycle
+    pub(super) fn clock_cycle(&mut self) -> Result<(), String> {
+        let Some(mut sequencer) = self.microsequencer.take() else {
+            return Err("The microsequencer isn't on".to_owned());
+        };
+        let result = self.run_microinstruction(&mut sequencer);
+        self.microsequencer = Some(sequencer);
+        result
+    }
+
+    fn run_microinstruction(&mut self, sequencer: &mut Microsequencer) -> Result<(), String> {
+        let state = sequencer.state;
+        let Some(Microinstruction {
+            signals: s,
+            ird,
+            cond,
+            j,
+            ..
+        }) = control_store(state)
+        else {
+            return Err(format!("State {state} isn't in the control store"));
+        };
+
+        if state == 18 {
+            // the same bookkeeping the phase executor does on fetch
+            self.currently_executing = self.pc.get() as usize;
+            self.record_registers();
+            self.instruction_count += 1;
+            self.stopped_at_breakpoint = None;
+            self.track_fetch(self.currently_executing);
+            self.start_instruction_timing();
+        }
+        if matches!(state, 13 | 44 | ACV_STATE) {
+            self.counters.exceptions += 1;
+        }
+        self.update_devices();
+
+        let vector = self.ir.range(7..0).get();
+        if state == 15 && self.native_traps.handles(vector) {
+            // the routine runs in Rust, nothing to push or jump to. Its reads aren't the fetch
+            self.execute_state.current_phase = 1;
+            self.run_native_trap(vector);
+            sequencer.last = Some(Cycle {
+                state,
+                bus: None,
+                next: 18,
+                why: format!("TRAP x{vector:02X} ran natively"),
+            });
+            sequencer.state = 18;
+            sequencer.cycles += 1;
+            self.cpu_state = CpuState::Fetch;
+            return Ok(());
+        }
+
+        // everything reads the registers as they were at the start of the cycle
+        let ir = self.ir;
+        let pc = self.pc.get();
+        let psr = self.memory[PSR_ADDR].get();
+        let sr1_index = s.sr1mux.map(|mux| match mux {
+            Sr1Mux::Ir11_9 => ir.range(11..9).get(),
+            Sr1Mux::Ir8_6 => ir.range(8..6).get(),
+            Sr1Mux::Sp => 6,
+        });
+        let sr1 = sr1_index.map(|n| self.r[n as usize].get());
+        let dr_index = |mux| match mux {
+            DrMux::Ir11_9 => ir.range(11..9).get(),
+            DrMux::R7 => 7,
+            DrMux::Sp => 6,
+        };
+        let adder = || -> Result<u16, String> {
+            let addr1 = match set(s.addr1mux, state, "ADDR1MUX")? {
+                Addr1Mux::Pc => pc,
+                Addr1Mux::BaseR => set(sr1, state, "SR1MUX")?,
+            };
+            let addr2 = match set(s.addr2mux, state, "ADDR2MUX")? {
+                Addr2Mux::Zero => 0,
+                Addr2Mux::Offset6 => ir.range(5..0).sext(5).get(),
+                Addr2Mux::PcOffset9 => ir.range(8..0).sext(8).get(),
+                Addr2Mux::PcOffset11 => ir.range(10..0).sext(10).get(),
+            };
+            Ok(addr1.wrapping_add(addr2))
+        };
+
+        let bus = match s.gate {
+            None => None,
+            Some(Gate::Pc) => Some(pc),
+            Some(Gate::Mdr) => Some(self.mdr.get()),
+            Some(Gate::Alu) => {
+                let a = set(sr1, state, "SR1MUX")?;
+                let b = if ir.index(5).get() == 1 {
+                    ir.range(4..0).sext(4).get()
+                } else {
+                    self.r[ir.range(2..0).get() as usize].get()
+                };
+                Some(match set(s.aluk, state, "ALUK")? {
+                    AluK::Add => a.wrapping_add(b),
+                    AluK::And => a & b,
+                    AluK::Not => !a,
+                    AluK::PassA => a,
+                })
+            }
+            Some(Gate::MarMux) => Some(match set(s.marmux, state, "MARMUX")? {
+                MarMux::Zext7_0 => ir.range(7..0).get(),
+                MarMux::Adder => adder()?,
+            }),
+            Some(Gate::Vector) => Some(sequencer.vector),
+            Some(Gate::Psr) => Some(psr),
+            Some(Gate::Sp) => Some(match set(s.spmux, state, "SPMUX")? {
+                SpMux::SpPlus1 => set(sr1, state, "SR1MUX")?.wrapping_add(1),
+                SpMux::SpMinus1 => set(sr1, state, "SR1MUX")?.wrapping_sub(1),
+                SpMux::SavedSsp => self.saved_ssp.get(),
+                SpMux::SavedUsp => self.saved_usp.get(),
+            }),
+        };
+        let bus_value = || bus.ok_or_else(|| format!("State {state} loads from an undriven bus"));
+
+        // memory, a protected address goes to the ACV exception instead
+        let mut from_memory = None;
+        let mut ready = true;
+        if let Some(rw) = s.memory {
+            let area = area_from_address(&self.mar);
+            let allowed = match rw {
+                Rw::Read => area.can_read(&self.priv_level()),
+                Rw::Write => area.can_write(&self.priv_level()),
+            };
+            if !allowed {
+                sequencer.last = Some(Cycle {
+                    state,
+                    bus,
+                    next: ACV_STATE,
+                    why: format!("ACV: x{:04X} is protected", self.mar.get()),
+                });
+                sequencer.state = ACV_STATE;
+                sequencer.cycles += 1;
+                self.cpu_state = CpuState::Decode;
+                return Ok(());
+            }
+            // nothing happens until the last cycle of the access
+            ready = self.memory_ready();
+            let addr = self.mar.get() as usize;
+            // the counters, cache and access log tell the fetch apart by the phase, state 33 is it
+            self.execute_state.current_phase = usize::from(state != 33);
+            match rw {
+                Rw::Read if ready => from_memory = Some(self.read_memory(addr)),
+                Rw::Write if ready => self.write_memory(addr, self.mdr.get()),
+                _ => {}
+            }
+        }
+
+        let (next, why) = if ird {
+            let opcode = ir.range(15..12).get() as u8;
+            (opcode, format!("IRD: IR[15:12] = {opcode:04b}"))
+        } else {
+            let (bit, name, value) = match cond {
+                Cond::Unconditional => (0, "", false),
+                Cond::Ready => (1 << 1, "R", ready),
+                Cond::Branch => (1 << 2, "BEN", sequencer.ben),
+                Cond::AddrMode => (1 << 0, "IR[11]", ir.index(11).get() == 1),
+                Cond::Privilege => (1 << 3, "PSR[15]", psr & 0x8000 != 0),
+                Cond::Interrupt => (1 << 4, "INT", false),
+            };
+            let next = if value { j | bit } else { j };
+            match cond {
+                Cond::Unconditional => (next, format!("J = {j}")),
+                _ => (next, format!("J = {j}, {name} = {}", value as u8)),
+            }
+        };
+
+        // the init tracker sees the same uses and moves the phase executor's micro-ops make
+        let base = DataSource::Register(sr1_index.unwrap_or(0));
+        let bus_source = match s.gate {
+            None => None,
+            Some(Gate::Pc) => Some(DataSource::PC),
+            Some(Gate::Mdr) => Some(DataSource::MDR),
+            Some(Gate::Alu) if s.aluk == Some(AluK::PassA) => Some(base.clone()),
+            Some(Gate::Alu) => {
+                let operation = match s.aluk {
+                    Some(AluK::And) => MAluOp::And,
+                    Some(AluK::Not) => MAluOp::Not,
+                    _ => MAluOp::Add,
+                };
+                let operand2 = if ir.index(5).get() == 1 {
+                    DataSource::Immediate(ir.range(4..0).sext(4).get() as i16)
+                } else {
+                    DataSource::Register(ir.range(2..0).get())
+                };
+                self.track_alu(&operation, &base, &operand2);
+                Some(DataSource::AluOut)
+            }
+            Some(Gate::MarMux) if s.marmux == Some(MarMux::Zext7_0) => {
+                Some(DataSource::Constant(ir.range(7..0).get()))
+            }
+            Some(Gate::MarMux) => {
+                if s.addr1mux == Some(Addr1Mux::BaseR) {
+                    self.track_alu(&MAluOp::Add, &base, &DataSource::Immediate(0));
+                }
+                Some(DataSource::AluOut)
+            }
+            Some(Gate::Vector) => Some(DataSource::Constant(sequencer.vector)),
+            Some(Gate::Psr) => Some(DataSource::PSR),
+            Some(Gate::Sp) => match s.spmux {
+                Some(SpMux::SpPlus1 | SpMux::SpMinus1) => {
+                    self.track_alu(&MAluOp::Add, &base, &DataSource::Immediate(0));
+                    Some(DataSource::AluOut)
+                }
+                // swapping stacks isn't tracked, R6 keeps its bit like on the phase executor
+                _ => None,
+            },
+        };
+        if let Some(source) = &bus_source {
+            if s.ld_mar {
+                self.track_transfer(source, &DataDestination::MAR);
+            }
+            if s.ld_mdr && from_memory.is_none() && ready {
+                self.track_transfer(source, &DataDestination::MDR);
+            }
+            if let (true, Some(mux)) = (s.ld_reg, s.drmux) {
+                self.track_transfer(source, &DataDestination::Register(dr_index(mux)));
+            }
+        }
+        // JSRR saves R7 before it reads the base register
+        if s.ld_pc && s.pcmux == Some(PcMux::Adder) && s.addr1mux == Some(Addr1Mux::BaseR) {
+            self.track_alu(&MAluOp::Add, &base, &DataSource::Immediate(0));
+        }
+        if let Some(source) = &bus_source {
+            if s.ld_pc && s.pcmux == Some(PcMux::Bus) {
+                self.track_transfer(source, &DataDestination::PC);
+            }
+            if s.ld_priv && s.psrmux == Some(PsrMux::Bus) {
+                self.track_transfer(source, &DataDestination::PSR);
+            }
+        }
+
+        // and everything loads together on the clock edge
+        if s.ld_mar {
+            self.mar.set(bus_value()?);
+        }
+        if s.ld_mdr && ready {
+            let value = match from_memory {
+                Some(value) => value,
+                None => bus_value()?,
+            };
+            self.mdr.set(value);
+        }
+        if s.ld_ir {
+            self.ir.set(bus_value()?);
+        }
+        if s.ld_ben {
+            let nzp = psr & 0b111;
+            sequencer.ben = ir.range(11..9).get() & nzp != 0;
+        }
+        if s.ld_reg {
+            let dr = dr_index(set(s.drmux, state, "DRMUX")?) as usize;
+            self.r[dr].set(bus_value()?);
+        }
+        if s.ld_pc {
+            let value = match set(s.pcmux, state, "PCMUX")? {
+                PcMux::PcPlus1 => pc.wrapping_add(1),
+                PcMux::Bus => bus_value()?,
+                PcMux::Adder => adder()?,
+            };
+            self.pc.set(value);
+        }
+        if s.ld_saved_ssp {
+            self.saved_ssp = EmulatorCell::new(set(sr1, state, "SR1MUX")?);
+        }
+        if s.ld_saved_usp {
+            self.saved_usp = EmulatorCell::new(set(sr1, state, "SR1MUX")?);
+        }
+        if s.ld_vector {
+            sequencer.vector = match set(s.vectormux, state, "VectorMUX")? {
+                VectorMux::Trap => ir.range(7..0).get(),
+                VectorMux::Privilege => 0x0100,
+                VectorMux::IllegalOpcode => 0x0101,
+                VectorMux::Acv => 0x0102,
+            };
+        }
+        let psrmux = s.psrmux;
+        if s.ld_priv {
+            match set(psrmux, state, "PSRMUX")? {
+                PsrMux::Bus => self.memory[PSR_ADDR].set(bus_value()?),
+                PsrMux::Individual => {
+                    self.memory[PSR_ADDR].set(self.memory[PSR_ADDR].get() & !0x8000)
+                }
+            }
+        }
+        // with PSRMUX=BUS the condition codes came in with the rest of the PSR
+        if s.ld_cc && psrmux != Some(PsrMux::Bus) {
+            let value = bus_value()?;
+            if value & 0x8000 != 0 {
+                self.set_n();
+            } else if value == 0 {
+                self.set_z();
+            } else {
+                self.set_p();
+            }
+        }
+
+        if state == 32 {
+            if let Some(opcode) = OpCode::from_instruction(self.ir) {
+                self.track_call(&opcode);
+                self.count_instruction(&opcode);
+            }
+            self.track_pipeline();
+        }
+
+        sequencer.last = Some(Cycle {
+            state,
+            bus,
+            next,
+            why,
+        });
+        sequencer.state = next;
+        sequencer.cycles += 1;
+        self.cpu_state = match (next, OpCode::from_instruction(self.ir)) {
+            (18, _) => CpuState::Fetch,
+            (33 | 35 | 32, _) | (_, None) => CpuState::Decode,
+            (_, Some(opcode)) => CpuState::ExecuteOperation(opcode),
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn control_store_is_closed() {
+        // every state leads somewhere in the store, whichever way the condition goes
+        for state in 0..64 {
+            let Some(mi) = control_store(state) else {
+                continue;
+            };
+            let targets: Vec<u8> = if mi.ird {
+                (0..16).collect()
+            } else {
+                let bit = match mi.cond {
+                    Cond::Unconditional => 0,
+                    Cond::Ready => 1 << 1,
+                    Cond::Branch => 1 << 2,
+                    Cond::AddrMode => 1,
+                    Cond::Privilege => 1 << 3,
+                    // there are no interrupts yet
+                    Cond::Interrupt => 0,
+                };
+                vec![mi.j, mi.j | bit]
+            };
+            for target in targets {
+                assert!(
+                    control_store(target).is_some(),
+                    "State {state} can go to {target}, which isn't in the store"
+                );
+            }
+            if mi.signals.memory.is_some() {
+                assert_eq!(mi.cond, Cond::Ready, "State {state} doesn't wait for R");
+            }
+        }
+    }
+}
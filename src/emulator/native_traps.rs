@@ -0,0 +1,205 @@
+use std::collections::HashMap;
+
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{Emulator, Isa, OpCode, KBDR_ADDR, KBSR_ADDR};
+
+/// The OS's service routines that can run in Rust instead, from x20 up
+pub const NATIVE_TRAPS: [&str; 6] = ["GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT"];
+
+/// Vector of the first one, GETC
+const FIRST_VECTOR: u16 = 0x20;
+
+/// What the OS prints, so a program can't tell which ran its trap
+const IN_PROMPT: &str = "\nInput a character> ";
+const HALT_MESSAGE: &str = "\n\n[OS] --- HALT ---\n\n";
+
+/// Which of TRAP x20-x25 skip the OS and run in Rust. Much faster, and there's no OS code to step through
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NativeTraps {
+    /// x20 (GETC) to x25 (HALT), in order
+    pub enabled: [bool; 6],
+    /// IN has printed its prompt and is waiting for a key
+    pub(super) prompted: bool,
+}
+
+impl NativeTraps {
+    pub fn all() -> Self {
+        Self {
+            enabled: [true; 6],
+            ..Default::default()
+        }
+    }
+
+    /// Parse `all`, or a comma separated list of names (PUTS) or vectors (x22)
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Self::all());
+        }
+        let mut traps = Self::default();
+        for name in s.split(',').map(str::trim) {
+            let index = NATIVE_TRAPS
+                .iter()
+                .position(|trap| trap.eq_ignore_ascii_case(name))
+                .or_else(|| {
+                    let hex = name.strip_prefix(['x', 'X'])?;
+                    let vector = u16::from_str_radix(hex, 16).ok()?;
+                    let index = vector.checked_sub(FIRST_VECTOR)? as usize;
+                    (index < NATIVE_TRAPS.len()).then_some(index)
+                })
+                .ok_or_else(|| {
+                    format!(
+                        "'{name}' isn't a trap that can run natively, pick from {} (or x20-x25)",
+                        NATIVE_TRAPS.join(", ")
+                    )
+                })?;
+            traps.enabled[index] = true;
+        }
+        Ok(traps)
+    }
+
+    /// Does TRAP `vector` run natively
+    pub fn handles(&self, vector: u16) -> bool {
+        vector
+            .checked_sub(FIRST_VECTOR)
+            .and_then(|index| self.enabled.get(index as usize))
+            .is_some_and(|&on| on)
+    }
+
+    pub fn any(&self) -> bool {
+        self.enabled.contains(&true)
+    }
+
+    /// The vector of a TRAP this runs natively
+    pub fn vector_of(&self, opcode: &OpCode) -> Option<u16> {
+        let vector = match opcode {
+            OpCode::Trap(op) => op.trap_vector.get(),
+            OpCode::Lc3bTrap(op) => op.trap_vector.get(),
+            _ => return None,
+        };
+        self.handles(vector).then_some(vector)
+    }
+}
+
+/// Stands in for a TRAP the phase executor runs natively, the whole routine is one micro-op in Execute
+#[derive(Debug, Clone)]
+pub struct NativeTrapOp {
+    pub vector: u16,
+}
+
+impl MicroOpGenerator for NativeTrapOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let vector = self.vector;
+        let name = NATIVE_TRAPS[(vector - FIRST_VECTOR) as usize];
+        let mut plan = HashMap::new();
+        plan.insert(
+            CycleState::Execute,
+            vec![MicroOp::new_custom(
+                move |emu| {
+                    emu.run_native_trap(vector);
+                    Ok(())
+                },
+                format!("{name} runs natively, the OS is skipped"),
+            )],
+        );
+        plan
+    }
+}
+
+impl Emulator {
+    /// Do what the OS's routine for `vector` would, with PC already past the TRAP.
+    /// GETC and IN without a key to read go back to the TRAP so it runs again, like the OS polling KBSR
+    pub(super) fn run_native_trap(&mut self, vector: u16) {
+        let done = match vector {
+            0x20 => self.native_getc(),
+            0x21 => {
+                self.output.push(self.r[0].get() as u8 as char);
+                true
+            }
+            0x22 => self.native_puts(false),
+            0x23 => {
+                if !self.native_traps.prompted {
+                    self.output.push_str(IN_PROMPT);
+                    self.native_traps.prompted = true;
+                }
+                let done = self.native_getc();
+                if done {
+                    self.native_traps.prompted = false;
+                    self.output.push(self.r[0].get() as u8 as char);
+                    self.output.push('\n');
+                }
+                done
+            }
+            0x24 => self.native_puts(true),
+            0x25 => {
+                self.output.push_str(HALT_MESSAGE);
+                self.halted = true;
+                self.stop_running();
+                true
+            }
+            _ => unreachable!("TRAP x{vector:02X} can't run natively"),
+        };
+        if !done {
+            // run the TRAP again next instruction
+            self.pc
+                .set(self.pc.get().wrapping_sub(self.isa.word_size() as u16));
+        } else if self.isa == Isa::Lc3b {
+            // the LC-3b TRAP is a call, R7 is left pointing after it
+            self.r[7] = self.pc;
+            self.init_tracker.mark_register(7, true);
+        }
+    }
+
+    /// Take the key waiting in KBDR into R0, if there is one. The device registers are read like
+    /// the OS would so the counters, cache and access log see the same accesses
+    fn native_getc(&mut self) -> bool {
+        if self.read_memory(KBSR_ADDR) & 0x8000 == 0 {
+            return false;
+        }
+        // reading KBDR clears KBSR
+        let key = self.read_memory(KBDR_ADDR);
+        self.r[0].set(key);
+        self.init_tracker.mark_register(0, true);
+        self.feed_keyboard();
+        true
+    }
+
+    /// Print the string at R0, a char a word or (`packed`) two a word low byte first.
+    /// On the LC-3b both are a char a byte
+    fn native_puts(&mut self, packed: bool) -> bool {
+        let mut addr = self.r[0].get();
+        // a missing null can't make it go round forever
+        for _ in 0..=u16::MAX {
+            let word = self.read_memory(self.word_address(addr));
+            let (chars, count) = match self.isa {
+                Isa::Lc3b if addr & 1 == 1 => ([word >> 8, 0], 1),
+                Isa::Lc3b => ([word & 0xFF, 0], 1),
+                Isa::Lc3 if packed => ([word & 0xFF, word >> 8], 2),
+                Isa::Lc3 => ([word, 0], 1),
+            };
+            for &c in &chars[..count] {
+                if c == 0 {
+                    return true;
+                }
+                self.output.push(c as u8 as char);
+            }
+            addr = addr.wrapping_add(1);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_names_and_vectors() {
+        assert_eq!(NativeTraps::parse("all").unwrap(), NativeTraps::all());
+        let traps = NativeTraps::parse("puts, x25").unwrap();
+        assert_eq!(traps.enabled, [false, false, true, false, false, true]);
+        assert!(traps.handles(0x22) && !traps.handles(0x20) && !traps.handles(0x26));
+        assert!(NativeTraps::parse("x26").is_err());
+        assert!(NativeTraps::parse("MUL").is_err());
+        assert!(!NativeTraps::default().any());
+    }
+}
@@ -3,6 +3,7 @@ use std::fmt::Display;
 pub use add::AddOp;
 pub use and::AndOp;
 pub use br::BrOp;
+pub use custom::CustomOp;
 pub use jmp::JmpOp;
 pub use jsr::JsrOp;
 pub use ld::LdOp;
@@ -16,13 +17,18 @@ pub use sti::StiOp;
 pub use str::StrOp;
 pub use trap::TrapOp;
 
+use serde::{Deserialize, Serialize};
+
 use super::{BitAddressable, EmulatorCell};
 
 mod add;
 mod and;
 mod br;
+pub mod custom;
 mod jmp;
 pub mod jsr;
+/// The LC-3b only instructions and the ones that work differently there
+pub mod lc3b;
 mod ld;
 mod ldi;
 mod ldr;
@@ -34,6 +40,51 @@ mod sti;
 mod str;
 mod trap;
 
+/// Which machine we are emulating, picked when the [`super::Emulator`] is made
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Isa {
+    /// The word addressed LC-3 from the textbook
+    #[default]
+    Lc3,
+    /// Patt's byte addressed LC-3b: LDB/STB, LDW/STW, SHF and XOR, offsets scaled to words
+    Lc3b,
+}
+
+impl Isa {
+    /// How many addresses one word takes up
+    pub fn word_size(self) -> usize {
+        match self {
+            Isa::Lc3 => 1,
+            Isa::Lc3b => 2,
+        }
+    }
+
+    /// Where the OS starts running after a reset
+    pub fn boot_address(self) -> u16 {
+        match self {
+            Isa::Lc3 => 0x0200,
+            Isa::Lc3b => 0x0400,
+        }
+    }
+
+    /// The OS flashed into a fresh machine
+    pub fn os_source(self) -> &'static str {
+        match self {
+            Isa::Lc3 => include_str!("../../oses/simpleos.asm"),
+            Isa::Lc3b => include_str!("../../oses/lc3bos.asm"),
+        }
+    }
+}
+
+impl Display for Isa {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Isa::Lc3 => write!(f, "LC-3"),
+            Isa::Lc3b => write!(f, "LC-3b"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 /// This encodes the key data used in the state machine to decide the next action to take
 pub enum CpuState {
@@ -76,6 +127,17 @@ pub enum OpCode {
     Sti(StiOp),
     Str(StrOp),
     Trap(TrapOp),
+    /// Whatever the user loaded for 1101
+    Custom(CustomOp),
+    // LC-3b only
+    Ldb(lc3b::LdbOp),
+    Stb(lc3b::StbOp),
+    Ldw(lc3b::LdwOp),
+    Stw(lc3b::StwOp),
+    Shf(lc3b::ShfOp), // Includes LSHF, RSHFL and RSHFA
+    Xor(lc3b::XorOp), // Includes NOT
+    Lc3bRti(lc3b::RtiOp),
+    Lc3bTrap(lc3b::TrapOp),
 }
 
 impl CpuState {
@@ -93,12 +155,19 @@ impl CpuState {
 }
 
 impl OpCode {
-    /// Decodes an instruction from the machine state (usually from IR)
+    /// Decode an instruction for the given machine
+    pub fn decode(isa: Isa, instruction: EmulatorCell) -> Option<OpCode> {
+        match isa {
+            Isa::Lc3 => Self::from_instruction(instruction),
+            Isa::Lc3b => lc3b::decode(instruction),
+        }
+    }
+
+    /// Decodes an LC-3 instruction from the machine state (usually from IR)
     /// and returns the corresponding OpCode variant containing the decoded operation details.
     pub fn from_instruction(instruction: EmulatorCell) -> Option<OpCode> {
         let opcode_val = instruction.range(15..12).get();
 
-        debug_assert!(opcode_val != 0xd, "Tried to decode non-existant op");
         match opcode_val {
             // Call the specific decode method for each opcode
             0x1 => Some(OpCode::Add(AddOp::decode(instruction))),
@@ -116,10 +185,41 @@ impl OpCode {
             0xB => Some(OpCode::Sti(StiOp::decode(instruction))),
             0x7 => Some(OpCode::Str(StrOp::decode(instruction))),
             0xF => Some(OpCode::Trap(TrapOp::decode(instruction))),
-            // Opcode 13 (0xD) is unused/reserved in standard LC-3.
+            // Opcode 13 (0xD) is reserved in standard LC-3, it's only valid if the user defined it
+            0xD => {
+                custom::loaded().map(|custom| OpCode::Custom(CustomOp::decode(custom, instruction)))
+            }
             _ => None, // Return None for invalid/unused opcode
         }
     }
+
+    /// Just the instruction's name, without its operands
+    pub fn mnemonic(&self) -> &str {
+        match self {
+            OpCode::Add(_) => "ADD",
+            OpCode::And(_) => "AND",
+            OpCode::Br(_) => "BR",
+            OpCode::Jmp(_) => "JMP",
+            OpCode::Jsr(_) => "JSR",
+            OpCode::Ld(_) => "LD",
+            OpCode::Ldi(_) => "LDI",
+            OpCode::Ldr(_) => "LDR",
+            OpCode::Lea(_) => "LEA",
+            OpCode::Not(_) => "NOT",
+            OpCode::Rti(_) | OpCode::Lc3bRti(_) => "RTI",
+            OpCode::St(_) => "ST",
+            OpCode::Sti(_) => "STI",
+            OpCode::Str(_) => "STR",
+            OpCode::Trap(_) | OpCode::Lc3bTrap(_) => "TRAP",
+            OpCode::Custom(op) => &op.instruction.mnemonic,
+            OpCode::Ldb(_) => "LDB",
+            OpCode::Stb(_) => "STB",
+            OpCode::Ldw(_) => "LDW",
+            OpCode::Stw(_) => "STW",
+            OpCode::Shf(_) => "SHF",
+            OpCode::Xor(_) => "XOR",
+        }
+    }
 }
 
 impl Display for OpCode {
@@ -140,6 +240,15 @@ impl Display for OpCode {
             OpCode::Sti(op) => write!(f, "{op}"),
             OpCode::Str(op) => write!(f, "{op}"),
             OpCode::Trap(op) => write!(f, "{op}"),
+            OpCode::Custom(op) => write!(f, "{op}"),
+            OpCode::Ldb(op) => write!(f, "{op}"),
+            OpCode::Stb(op) => write!(f, "{op}"),
+            OpCode::Ldw(op) => write!(f, "{op}"),
+            OpCode::Stw(op) => write!(f, "{op}"),
+            OpCode::Shf(op) => write!(f, "{op}"),
+            OpCode::Xor(op) => write!(f, "{op}"),
+            OpCode::Lc3bRti(op) => write!(f, "{op}"),
+            OpCode::Lc3bTrap(op) => write!(f, "{op}"),
         }
     }
 }
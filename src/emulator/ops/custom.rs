@@ -0,0 +1,487 @@
+//! A user defined instruction for the reserved opcode 1101, described in RON.
+//!
+//! ```norust
+//! (
+//!     mnemonic: "MUL",
+//!     fields: [
+//!         (name: "DR", kind: Register, bits: (11, 9)),
+//!         (name: "SR1", kind: Register, bits: (8, 6)),
+//!         (name: "SR2", kind: Register, bits: (2, 0)),
+//!     ],
+//!     plan: (
+//!         execute: [Alu(op: Mul, a: Reg("SR1"), b: Reg("SR2"))],
+//!         store_result: [Transfer(to: Reg("DR"), from: AluOut), SetCc(Reg("DR"))],
+//!     ),
+//! )
+//! ```
+
+use crate::emulator::micro_op::{
+    CycleState, DataDestination, DataSource, MAluOp, MachineFlag, MicroOp, MicroOpGenerator,
+};
+use crate::emulator::parse::OpToken;
+use crate::emulator::{BitAddressable, EmulatorCell};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// The opcode the LC-3 leaves free
+pub const CUSTOM_OPCODE: u16 = 0b1101;
+
+/// The instruction currently loaded. It's global because the assembler and decoder don't get an emulator to look at
+static LOADED: RwLock<Option<Arc<CustomInstruction>>> = RwLock::new(None);
+/// Goes up every time it's replaced, so anything caching decoded instructions knows to redo them
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// The loaded custom instruction, if there is one
+pub fn loaded() -> Option<Arc<CustomInstruction>> {
+    LOADED.read().ok()?.clone()
+}
+
+/// Replace the custom instruction, `None` makes 1101 illegal again
+pub fn set_loaded(instruction: Option<CustomInstruction>) {
+    if let Ok(mut loaded) = LOADED.write() {
+        *loaded = instruction.map(Arc::new);
+        GENERATION.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// How many times the custom instruction has been replaced
+pub fn generation() -> usize {
+    GENERATION.load(Ordering::Relaxed)
+}
+
+/// Tests that load an instruction, or need 1101 to be illegal, hold this so they don't run
+/// into each other through the global
+#[cfg(test)]
+pub fn test_lock() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// What an operand is and how it's written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldKind {
+    /// R0-R7, has to be 3 bits
+    Register,
+    /// A signed number (#-3, x1F)
+    Immediate,
+    /// A label or number, stored relative to the incremented PC like LD's
+    PcOffset,
+}
+
+/// One operand, in the order they are written after the mnemonic
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    pub kind: FieldKind,
+    /// Highest and lowest bit, like the book's [11:9]
+    pub bits: (u8, u8),
+}
+
+impl Field {
+    pub fn width(&self) -> u8 {
+        self.bits.0 - self.bits.1 + 1
+    }
+
+    fn mask(&self) -> u16 {
+        (((1u32 << self.width()) - 1) << self.bits.1) as u16
+    }
+}
+
+/// Where a value comes from, fields are referred to by name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Source {
+    /// The register a field names
+    Reg(String),
+    /// Always this register
+    R(u16),
+    PC,
+    IR,
+    MAR,
+    MDR,
+    PSR,
+    AluOut,
+    Temp,
+    /// A field's value, sign extended
+    Imm(String),
+    /// A field's value as an offset from the PC
+    PcOffset(String),
+    Constant(u16),
+}
+
+/// Where a value goes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Destination {
+    Reg(String),
+    R(u16),
+    PC,
+    IR,
+    MAR,
+    MDR,
+    PSR,
+    AluOut,
+    Temp,
+}
+
+/// The same steps `micro_op!` builds, with fields instead of numbers
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Step {
+    Transfer {
+        to: Destination,
+        from: Source,
+    },
+    Alu {
+        op: MAluOp,
+        a: Source,
+        /// Not used by `Not`
+        #[serde(default = "no_operand")]
+        b: Source,
+    },
+    /// Set NZP from a register (`Reg` or `R`)
+    SetCc(Source),
+    /// Write MDR to memory at MAR after the phase
+    WriteMemory,
+    Message(String),
+}
+
+fn no_operand() -> Source {
+    Source::Constant(0)
+}
+
+/// The phases an instruction gets to fill in, fetch and decode are the same for everything
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Plan {
+    #[serde(default)]
+    pub evaluate_address: Vec<Step>,
+    #[serde(default)]
+    pub fetch_operands: Vec<Step>,
+    #[serde(default)]
+    pub execute: Vec<Step>,
+    #[serde(default)]
+    pub store_result: Vec<Step>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CustomInstruction {
+    pub mnemonic: String,
+    pub fields: Vec<Field>,
+    /// Bits in [11:0] that are always set
+    #[serde(default)]
+    pub fixed: u16,
+    pub plan: Plan,
+}
+
+impl CustomInstruction {
+    /// Read and check a definition
+    pub fn from_ron(source: &str) -> Result<Self, String> {
+        let instruction: CustomInstruction =
+            ron::from_str(source).map_err(|e| format!("Invalid definition: {e}"))?;
+        instruction.validate()?;
+        Ok(instruction)
+    }
+
+    fn field(&self, name: &str) -> Result<(usize, &Field), String> {
+        self.fields
+            .iter()
+            .enumerate()
+            .find(|(_, field)| field.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("No field called {name}"))
+    }
+
+    fn check_source(&self, source: &Source) -> Result<(), String> {
+        let wanted = match source {
+            Source::Reg(name) => Some((name, FieldKind::Register)),
+            Source::Imm(name) => Some((name, FieldKind::Immediate)),
+            Source::PcOffset(name) => Some((name, FieldKind::PcOffset)),
+            Source::R(n) if *n > 7 => return Err(format!("There is no R{n}")),
+            _ => None,
+        };
+        if let Some((name, kind)) = wanted {
+            let (_, field) = self.field(name)?;
+            if field.kind != kind {
+                return Err(format!(
+                    "{name} is a {:?} field, it can't be used as a {kind:?}",
+                    field.kind
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        let mnemonic = &self.mnemonic;
+        if mnemonic.is_empty() || !mnemonic.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!(
+                "The mnemonic '{mnemonic}' has to be letters and numbers"
+            ));
+        }
+        if !mnemonic.starts_with(|c: char| c.is_ascii_alphabetic())
+            || (mnemonic.len() >= 2
+                && mnemonic[..1].eq_ignore_ascii_case("R")
+                && mnemonic[1..].parse::<u16>().is_ok())
+        {
+            return Err(format!("'{mnemonic}' would read as a number or register"));
+        }
+        if OpToken::from_str(mnemonic).is_ok_and(|op| op != OpToken::Custom && !op.lc3b_only()) {
+            return Err(format!("{mnemonic} is already an LC-3 instruction"));
+        }
+        if self.fixed & 0xF000 != 0 {
+            return Err("fixed can only set bits [11:0], [15:12] are the opcode".to_owned());
+        }
+
+        let mut used = self.fixed;
+        for (i, field) in self.fields.iter().enumerate() {
+            let (high, low) = field.bits;
+            if high > 11 || low > high {
+                return Err(format!(
+                    "{} has bits [{high}:{low}], fields have to fit in [11:0] with the high bit first",
+                    field.name
+                ));
+            }
+            if field.kind == FieldKind::Register && field.width() != 3 {
+                return Err(format!("Register field {} has to be 3 bits", field.name));
+            }
+            if field.kind != FieldKind::Register && field.width() < 2 {
+                return Err(format!(
+                    "{} needs at least 2 bits to hold a sign",
+                    field.name
+                ));
+            }
+            if used & field.mask() != 0 {
+                return Err(format!(
+                    "{} overlaps another field or the fixed bits",
+                    field.name
+                ));
+            }
+            used |= field.mask();
+            if self.fields[..i]
+                .iter()
+                .any(|other| other.name.eq_ignore_ascii_case(&field.name))
+            {
+                return Err(format!("Two fields are called {}", field.name));
+            }
+        }
+
+        let plan = &self.plan;
+        for step in plan
+            .evaluate_address
+            .iter()
+            .chain(&plan.fetch_operands)
+            .chain(&plan.execute)
+            .chain(&plan.store_result)
+        {
+            match step {
+                Step::Transfer { to, from } => {
+                    match to {
+                        Destination::Reg(name) => self.check_source(&Source::Reg(name.clone()))?,
+                        Destination::R(n) => self.check_source(&Source::R(*n))?,
+                        _ => {}
+                    }
+                    self.check_source(from)?;
+                }
+                Step::Alu { a, b, .. } => {
+                    self.check_source(a)?;
+                    self.check_source(b)?;
+                }
+                Step::SetCc(register @ (Source::Reg(_) | Source::R(_))) => {
+                    self.check_source(register)?
+                }
+                Step::SetCc(_) => return Err("SetCc needs a register".to_owned()),
+                Step::WriteMemory | Step::Message(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// How it's written, for the editor and help
+    pub fn signature(&self) -> String {
+        let operands: Vec<&str> = self.fields.iter().map(|f| f.name.as_str()).collect();
+        format!("{} {}", self.mnemonic, operands.join(", "))
+    }
+
+    /// The encoding, like `1101 DR SR1 0 0 0 SR2`
+    pub fn layout(&self) -> String {
+        let mut parts = vec!["1101".to_owned()];
+        let mut bit = 11i8;
+        while bit >= 0 {
+            if let Some(field) = self.fields.iter().find(|f| f.bits.0 as i8 == bit) {
+                parts.push(format!("{}[{}:{}]", field.name, field.bits.0, field.bits.1));
+                bit = field.bits.1 as i8 - 1;
+            } else {
+                parts.push(((self.fixed >> bit) & 1).to_string());
+                bit -= 1;
+            }
+        }
+        parts.join(" ")
+    }
+
+    /// Put operand values (already range checked and in two's complement) into an instruction word
+    pub fn encode(&self, values: &[u16]) -> u16 {
+        self.fields.iter().zip(values).fold(
+            (CUSTOM_OPCODE << 12) | self.fixed,
+            |word, (field, value)| word | ((value << field.bits.1) & field.mask()),
+        )
+    }
+}
+
+/// A decoded custom instruction
+#[derive(Debug, Clone)]
+pub struct CustomOp {
+    pub instruction: Arc<CustomInstruction>,
+    /// Each field's value, sign extended unless it's a register
+    pub values: Vec<EmulatorCell>,
+}
+
+impl CustomOp {
+    pub fn decode(instruction: Arc<CustomInstruction>, ir: EmulatorCell) -> Self {
+        let values = instruction
+            .fields
+            .iter()
+            .map(|field| {
+                let value = ir.range(field.bits.0..field.bits.1);
+                match field.kind {
+                    FieldKind::Register => value,
+                    _ => value.sext(field.width() - 1),
+                }
+            })
+            .collect();
+        Self {
+            instruction,
+            values,
+        }
+    }
+
+    fn value(&self, name: &str) -> u16 {
+        self.instruction
+            .field(name)
+            .map_or(0, |(i, _)| self.values[i].get())
+    }
+
+    fn source(&self, source: &Source) -> DataSource {
+        match source {
+            Source::Reg(name) => DataSource::Register(self.value(name)),
+            Source::R(n) => DataSource::Register(*n),
+            Source::PC => DataSource::PC,
+            Source::IR => DataSource::IR,
+            Source::MAR => DataSource::MAR,
+            Source::MDR => DataSource::MDR,
+            Source::PSR => DataSource::PSR,
+            Source::AluOut => DataSource::AluOut,
+            Source::Temp => DataSource::Temp,
+            Source::Imm(name) => DataSource::Immediate(self.value(name) as i16),
+            Source::PcOffset(name) => DataSource::PCOffset(self.value(name) as i16),
+            Source::Constant(value) => DataSource::Constant(*value),
+        }
+    }
+
+    fn destination(&self, destination: &Destination) -> DataDestination {
+        match destination {
+            Destination::Reg(name) => DataDestination::Register(self.value(name)),
+            Destination::R(n) => DataDestination::Register(*n),
+            Destination::PC => DataDestination::PC,
+            Destination::IR => DataDestination::IR,
+            Destination::MAR => DataDestination::MAR,
+            Destination::MDR => DataDestination::MDR,
+            Destination::PSR => DataDestination::PSR,
+            Destination::AluOut => DataDestination::AluOut,
+            Destination::Temp => DataDestination::Temp,
+        }
+    }
+
+    fn micro_op(&self, step: &Step) -> MicroOp {
+        match step {
+            Step::Transfer { to, from } => MicroOp::Transfer {
+                source: self.source(from),
+                destination: self.destination(to),
+            },
+            Step::Alu { op, a, b } => MicroOp::Alu {
+                operation: *op,
+                operand1: self.source(a),
+                operand2: self.source(b),
+            },
+            Step::SetCc(register) => {
+                let register = match self.source(register) {
+                    DataSource::Register(n) => n,
+                    _ => 0,
+                };
+                MicroOp::SetFlag(MachineFlag::UpdateCondCodes(register))
+            }
+            Step::WriteMemory => MicroOp::SetFlag(MachineFlag::WriteMemory),
+            Step::Message(message) => MicroOp::Message(message.clone()),
+        }
+    }
+}
+
+impl MicroOpGenerator for CustomOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let plan = &self.instruction.plan;
+        [
+            (CycleState::EvaluateAddress, &plan.evaluate_address),
+            (CycleState::FetchOperands, &plan.fetch_operands),
+            (CycleState::Execute, &plan.execute),
+            (CycleState::StoreResult, &plan.store_result),
+        ]
+        .into_iter()
+        .map(|(phase, steps)| (phase, steps.iter().map(|s| self.micro_op(s)).collect()))
+        .collect()
+    }
+}
+
+impl fmt::Display for CustomOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.instruction.mnemonic)?;
+        for (i, (field, value)) in self.instruction.fields.iter().zip(&self.values).enumerate() {
+            write!(f, "{}", if i == 0 { " " } else { ", " })?;
+            match field.kind {
+                FieldKind::Register => write!(f, "R{}", value.get())?,
+                _ => write!(f, "#{}", value.get() as i16)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MUL: &str = include_str!("../../../assets/custom_ops/mul.ron");
+
+    #[test]
+    fn mul_definition_loads() {
+        let mul = CustomInstruction::from_ron(MUL).unwrap();
+        assert_eq!(mul.mnemonic, "MUL");
+        assert_eq!(mul.layout(), "1101 DR[11:9] SR1[8:6] 0 0 0 SR2[2:0]");
+        assert_eq!(mul.encode(&[1, 2, 3]), 0b1101_001_010_000_011);
+
+        let op = CustomOp::decode(Arc::new(mul), EmulatorCell::new(0b1101_001_010_000_011));
+        assert_eq!(op.to_string(), "MUL R1, R2, R3");
+        let plan = op.generate_plan();
+        assert_eq!(
+            plan[&CycleState::Execute][0].to_string(),
+            "ALU_OUT <- R2 * R3"
+        );
+    }
+
+    #[test]
+    fn bad_definitions_are_rejected() {
+        let with = |change: &dyn Fn(&mut CustomInstruction)| {
+            let mut mul = CustomInstruction::from_ron(MUL).unwrap();
+            change(&mut mul);
+            mul.validate()
+        };
+        assert!(with(&|m| m.mnemonic = "ADD".to_owned()).is_err());
+        assert!(with(&|m| m.fields[2].bits = (6, 4)).is_err());
+        assert!(with(&|m| m.fields[1].bits = (9, 7)).is_err());
+        assert!(with(&|m| m.fixed = 0b100).is_err());
+        assert!(with(&|m| m
+            .plan
+            .execute
+            .push(Step::SetCc(Source::Reg("X".to_owned()))))
+        .is_err());
+        assert!(with(&|m| m.fixed = 0b11000).is_ok());
+    }
+}
@@ -0,0 +1,66 @@
+//! The LC-3b is the LC-3 with byte addressed memory, from Patt's 460N.
+//! Words live at even addresses so the PC moves by 2 and PC relative offsets count words
+//! (shifted left once to get bytes). LD, LDI, ST and STI are gone to make room for LDB/STB,
+//! LDW/STW take the place of LDR/STR, 1001 is XOR (NOT is XOR with -1), 1101 is SHF and
+//! TRAP is just a call through the vector table.
+use super::{jsr::JsrMode, AddOp, AndOp, BrOp, JmpOp, JsrOp, LeaOp, Op, OpCode};
+use crate::emulator::{BitAddressable, EmulatorCell};
+
+pub use ldb::LdbOp;
+pub use ldw::LdwOp;
+pub use rti::RtiOp;
+pub use shf::ShfOp;
+pub use stb::StbOp;
+pub use stw::StwOp;
+pub use trap::TrapOp;
+pub use xor::XorOp;
+
+mod ldb;
+mod ldw;
+mod rti;
+mod shf;
+mod stb;
+mod stw;
+mod trap;
+mod xor;
+
+/// A word offset as bytes
+fn lshf(offset: EmulatorCell) -> EmulatorCell {
+    EmulatorCell::new(offset.get() << 1)
+}
+
+/// The LC-3b version of [`OpCode::from_instruction`]
+pub fn decode(instruction: EmulatorCell) -> Option<OpCode> {
+    match instruction.range(15..12).get() {
+        0x0 => {
+            let mut op = BrOp::decode(instruction);
+            op.pc_offset = lshf(op.pc_offset);
+            Some(OpCode::Br(op))
+        }
+        0x1 => Some(OpCode::Add(AddOp::decode(instruction))),
+        0x2 => Some(OpCode::Ldb(LdbOp::decode(instruction))),
+        0x3 => Some(OpCode::Stb(StbOp::decode(instruction))),
+        0x4 => {
+            let mut op = JsrOp::decode(instruction);
+            if let JsrMode::Relative { pc_offset } = &mut op.mode {
+                *pc_offset = lshf(*pc_offset);
+            }
+            Some(OpCode::Jsr(op))
+        }
+        0x5 => Some(OpCode::And(AndOp::decode(instruction))),
+        0x6 => Some(OpCode::Ldw(LdwOp::decode(instruction))),
+        0x7 => Some(OpCode::Stw(StwOp::decode(instruction))),
+        0x8 => Some(OpCode::Lc3bRti(RtiOp::decode(instruction))),
+        0x9 => Some(OpCode::Xor(XorOp::decode(instruction))),
+        0xC => Some(OpCode::Jmp(JmpOp::decode(instruction))),
+        0xD => Some(OpCode::Shf(ShfOp::decode(instruction))),
+        0xE => {
+            let mut op = LeaOp::decode(instruction);
+            op.pc_offset = lshf(op.pc_offset);
+            Some(OpCode::Lea(op))
+        }
+        0xF => Some(OpCode::Lc3bTrap(TrapOp::decode(instruction))),
+        // 1010 and 1011 are reserved
+        _ => None,
+    }
+}
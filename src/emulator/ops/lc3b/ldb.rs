@@ -0,0 +1,81 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+#[derive(Debug, Clone)]
+/// Load a byte and sign extend it, DR <- SEXT(MEM[BaseR + SEXT(boffset6)])
+pub struct LdbOp {
+    pub dr: EmulatorCell,
+    pub base_r: EmulatorCell,
+    /// Counts bytes, not scaled
+    pub boffset6: EmulatorCell,
+}
+
+impl MicroOpGenerator for LdbOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        plan.insert(
+            CycleState::EvaluateAddress,
+            vec![micro_op!(ALU_OUT <- R(self.base_r.get()) + IMM(self.boffset6.get() as i16))],
+        );
+
+        // The word holding the byte is read, MAR[0] picks which half
+        plan.insert(CycleState::FetchOperands, vec![micro_op!(MAR <- AluOut)]);
+
+        plan.insert(
+            CycleState::Execute,
+            vec![MicroOp::new_custom(
+                |emu| {
+                    let word = emu.mdr.get();
+                    let byte = if emu.mar.get() & 1 == 1 {
+                        word >> 8
+                    } else {
+                        word & 0xFF
+                    };
+                    emu.execute_state
+                        .temp_register
+                        .set(byte as u8 as i8 as i16 as u16);
+                    Ok(())
+                },
+                "TEMP <- SEXT(MAR[0] ? MDR[15:8] : MDR[7:0])".to_owned(),
+            )],
+        );
+
+        plan.insert(
+            CycleState::StoreResult,
+            vec![
+                micro_op!(R(self.dr.get()) <- Temp),
+                micro_op!(SET_CC(self.dr.get())),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl Op for LdbOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 0010 | DR | BaseR | boffset6
+        Self {
+            dr: ir.range(11..9),
+            base_r: ir.range(8..6),
+            boffset6: ir.range(5..0).sext(5),
+        }
+    }
+}
+
+impl fmt::Display for LdbOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LDB R{}, R{}, #{}",
+            self.dr.get(),
+            self.base_r.get(),
+            self.boffset6.get() as i16
+        )
+    }
+}
@@ -0,0 +1,81 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell, Exception};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+/// Word accesses have to be to an even address
+pub(super) fn check_aligned() -> MicroOp {
+    MicroOp::new_custom(
+        |emu| {
+            if emu.mar.get() & 1 == 1 {
+                Err(Exception::UnalignedAccess)
+            } else {
+                Ok(())
+            }
+        },
+        "
+if (MAR[0] == 1)
+    ; Initiate an unaligned access exception"
+            .to_owned(),
+    )
+}
+
+#[derive(Debug, Clone)]
+/// Load a word, DR <- MEM[BaseR + LSHF(SEXT(offset6), 1)]
+pub struct LdwOp {
+    pub dr: EmulatorCell,
+    pub base_r: EmulatorCell,
+    /// Already shifted to bytes
+    pub offset6: EmulatorCell,
+}
+
+impl MicroOpGenerator for LdwOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        plan.insert(
+            CycleState::EvaluateAddress,
+            vec![micro_op!(ALU_OUT <- R(self.base_r.get()) + IMM(self.offset6.get() as i16))],
+        );
+
+        plan.insert(
+            CycleState::FetchOperands,
+            vec![micro_op!(MAR <- AluOut), check_aligned()],
+        );
+
+        plan.insert(
+            CycleState::StoreResult,
+            vec![
+                micro_op!(R(self.dr.get()) <- MDR),
+                micro_op!(SET_CC(self.dr.get())),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl Op for LdwOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 0110 | DR | BaseR | offset6
+        Self {
+            dr: ir.range(11..9),
+            base_r: ir.range(8..6),
+            offset6: EmulatorCell::new(ir.range(5..0).sext(5).get() << 1),
+        }
+    }
+}
+
+impl fmt::Display for LdwOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "LDW R{}, R{}, #{}",
+            self.dr.get(),
+            self.base_r.get(),
+            self.offset6.get() as i16 >> 1
+        )
+    }
+}
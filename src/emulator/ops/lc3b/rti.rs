@@ -0,0 +1,84 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell, Exception, PSR_ADDR};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+#[derive(Debug, Clone)]
+/// RTI with 2 byte stack slots, PC <- MEM[R6], PSR <- MEM[R6 + 2], R6 <- R6 + 4
+pub struct RtiOp;
+
+impl MicroOpGenerator for RtiOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        plan.insert(
+            CycleState::FetchOperands,
+            vec![
+                MicroOp::new_custom(
+                    |emu| {
+                        if emu.memory[PSR_ADDR].index(15).get() == 1 {
+                            Err(Exception::PrivilegeViolation)
+                        } else {
+                            Ok(())
+                        }
+                    },
+                    "
+if (PSR[15] == 1)
+    ; Initiate a privilege mode exception"
+                        .to_owned(),
+                ),
+                micro_op!(MAR <- R(6)),
+            ],
+        );
+
+        plan.insert(
+            CycleState::Execute,
+            vec![
+                micro_op!(Temp <- MDR),
+                micro_op!(ALU_OUT <- R(6) + IMM(2)),
+                micro_op!(MAR <- AluOut),
+            ],
+        );
+
+        plan.insert(
+            CycleState::StoreResult,
+            vec![
+                micro_op!(PC <- Temp),
+                micro_op!(PSR <- MDR),
+                micro_op!(ALU_OUT <- R(6) + IMM(4)),
+                micro_op!(R(6) <- AluOut),
+                MicroOp::new_custom(
+                    |emu| {
+                        if emu.memory[PSR_ADDR].index(15).get() == 1 {
+                            emu.saved_ssp = emu.r[6];
+                            emu.r[6] = emu.saved_usp;
+                        }
+                        Ok(())
+                    },
+                    "\
+if (PSR[15] == 1) {
+    Saved_SSP <- R6
+    R6 <- Saved_USP
+}"
+                    .to_owned(),
+                ),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl Op for RtiOp {
+    fn decode(_ir: EmulatorCell) -> Self {
+        Self
+    }
+}
+
+impl fmt::Display for RtiOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RTI")
+    }
+}
@@ -0,0 +1,109 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shift {
+    /// LSHF, zeros come in on the right
+    Left,
+    /// RSHFL, zeros come in on the left
+    RightLogical,
+    /// RSHFA, copies of the sign bit come in on the left
+    RightArithmetic,
+}
+
+#[derive(Debug, Clone)]
+/// DR <- SR shifted by amount4
+pub struct ShfOp {
+    pub dr: EmulatorCell,
+    pub sr: EmulatorCell,
+    pub shift: Shift,
+    pub amount: EmulatorCell,
+}
+
+impl Shift {
+    pub fn apply(self, value: u16, amount: u16) -> u16 {
+        match self {
+            Shift::Left => value << amount,
+            Shift::RightLogical => value >> amount,
+            Shift::RightArithmetic => ((value as i16) >> amount) as u16,
+        }
+    }
+}
+
+impl MicroOpGenerator for ShfOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+        let (shift, amount) = (self.shift, self.amount.get());
+
+        plan.insert(
+            CycleState::Execute,
+            vec![
+                micro_op!(Temp <- R(self.sr.get())),
+                MicroOp::new_custom(
+                    move |emu| {
+                        let value = emu.execute_state.temp_register.get();
+                        emu.execute_state
+                            .temp_register
+                            .set(shift.apply(value, amount));
+                        Ok(())
+                    },
+                    format!("TEMP <- {} TEMP, #{amount}", self.mnemonic()),
+                ),
+            ],
+        );
+
+        plan.insert(
+            CycleState::StoreResult,
+            vec![
+                micro_op!(R(self.dr.get()) <- Temp),
+                micro_op!(SET_CC(self.dr.get())),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl ShfOp {
+    fn mnemonic(&self) -> &'static str {
+        match self.shift {
+            Shift::Left => "LSHF",
+            Shift::RightLogical => "RSHFL",
+            Shift::RightArithmetic => "RSHFA",
+        }
+    }
+}
+
+impl Op for ShfOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 1101 | DR | SR | A | D | amount4
+        let shift = match (ir.index(5).get(), ir.index(4).get()) {
+            (_, 0) => Shift::Left,
+            (0, _) => Shift::RightLogical,
+            _ => Shift::RightArithmetic,
+        };
+        Self {
+            dr: ir.range(11..9),
+            sr: ir.range(8..6),
+            shift,
+            amount: ir.range(3..0),
+        }
+    }
+}
+
+impl fmt::Display for ShfOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} R{}, R{}, #{}",
+            self.mnemonic(),
+            self.dr.get(),
+            self.sr.get(),
+            self.amount.get()
+        )
+    }
+}
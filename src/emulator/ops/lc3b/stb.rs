@@ -0,0 +1,82 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+#[derive(Debug, Clone)]
+/// Store the low byte of SR, MEM[BaseR + SEXT(boffset6)] <- SR[7:0]
+///
+/// Memory here only does whole words, so the word is read first and written back with one half swapped
+pub struct StbOp {
+    pub sr: EmulatorCell,
+    pub base_r: EmulatorCell,
+    /// Counts bytes, not scaled
+    pub boffset6: EmulatorCell,
+}
+
+impl MicroOpGenerator for StbOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        // Setting MAR reads the word the byte goes into
+        plan.insert(
+            CycleState::EvaluateAddress,
+            vec![
+                micro_op!(ALU_OUT <- R(self.base_r.get()) + IMM(self.boffset6.get() as i16)),
+                micro_op!(MAR <- AluOut),
+            ],
+        );
+
+        plan.insert(
+            CycleState::FetchOperands,
+            vec![micro_op!(Temp <- R(self.sr.get()))],
+        );
+
+        plan.insert(
+            CycleState::Execute,
+            vec![
+                MicroOp::new_custom(
+                    |emu| {
+                        let byte = emu.execute_state.temp_register.get() & 0xFF;
+                        let word = emu.mdr.get();
+                        emu.mdr.set(if emu.mar.get() & 1 == 1 {
+                            (word & 0x00FF) | (byte << 8)
+                        } else {
+                            (word & 0xFF00) | byte
+                        });
+                        Ok(())
+                    },
+                    "MAR[0] ? MDR[15:8] : MDR[7:0] <- TEMP[7:0]".to_owned(),
+                ),
+                micro_op!(SET_FLAG(WriteMemory)),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl Op for StbOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 0011 | SR | BaseR | boffset6
+        Self {
+            sr: ir.range(11..9),
+            base_r: ir.range(8..6),
+            boffset6: ir.range(5..0).sext(5),
+        }
+    }
+}
+
+impl fmt::Display for StbOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "STB R{}, R{}, #{}",
+            self.sr.get(),
+            self.base_r.get(),
+            self.boffset6.get() as i16
+        )
+    }
+}
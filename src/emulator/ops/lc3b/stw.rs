@@ -0,0 +1,61 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{ldw::check_aligned, Op};
+
+#[derive(Debug, Clone)]
+/// Store a word, MEM[BaseR + LSHF(SEXT(offset6), 1)] <- SR
+pub struct StwOp {
+    pub sr: EmulatorCell,
+    pub base_r: EmulatorCell,
+    /// Already shifted to bytes
+    pub offset6: EmulatorCell,
+}
+
+impl MicroOpGenerator for StwOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        plan.insert(
+            CycleState::EvaluateAddress,
+            vec![micro_op!(ALU_OUT <- R(self.base_r.get()) + IMM(self.offset6.get() as i16))],
+        );
+
+        plan.insert(
+            CycleState::StoreResult,
+            vec![
+                micro_op!(MAR <- AluOut),
+                check_aligned(),
+                micro_op!(MDR <- R(self.sr.get())),
+                micro_op!(SET_FLAG(WriteMemory)),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl Op for StwOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 0111 | SR | BaseR | offset6
+        Self {
+            sr: ir.range(11..9),
+            base_r: ir.range(8..6),
+            offset6: EmulatorCell::new(ir.range(5..0).sext(5).get() << 1),
+        }
+    }
+}
+
+impl fmt::Display for StwOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "STW R{}, R{}, #{}",
+            self.sr.get(),
+            self.base_r.get(),
+            self.offset6.get() as i16 >> 1
+        )
+    }
+}
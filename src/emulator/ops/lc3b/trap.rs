@@ -0,0 +1,55 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+#[derive(Debug, Clone)]
+/// The LC-3b TRAP is a plain call through the vector table, R7 <- PC, PC <- MEM[LSHF(ZEXT(trapvect8), 1)].
+/// Nothing is pushed and the privilege doesn't change, the routine returns with RET
+pub struct TrapOp {
+    pub trap_vector: EmulatorCell,
+}
+
+impl MicroOpGenerator for TrapOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        // Read the vector table entry
+        plan.insert(
+            CycleState::EvaluateAddress,
+            vec![micro_op!(MAR <- C(self.trap_vector.get() << 1))],
+        );
+
+        plan.insert(
+            CycleState::Execute,
+            vec![micro_op!(R(7) <- PC), micro_op!(PC <- MDR)],
+        );
+
+        plan
+    }
+}
+
+impl Op for TrapOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 1111 | 0000 | trapvect8
+        Self {
+            trap_vector: ir.range(7..0),
+        }
+    }
+}
+
+impl fmt::Display for TrapOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.trap_vector.get() {
+            0x20 => write!(f, "GETC"),
+            0x21 => write!(f, "OUT"),
+            0x22 => write!(f, "PUTS"),
+            0x23 => write!(f, "IN"),
+            0x24 => write!(f, "PUTSP"),
+            0x25 => write!(f, "HALT"),
+            vector => write!(f, "TRAP x{vector:02X}"),
+        }
+    }
+}
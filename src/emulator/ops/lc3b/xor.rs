@@ -0,0 +1,88 @@
+use crate::emulator::micro_op::{CycleState, MicroOp, MicroOpGenerator};
+use crate::emulator::{BitAddressable, EmulatorCell};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::Op;
+
+#[derive(Debug, Clone)]
+/// XOR takes the LC-3's NOT opcode, NOT DR, SR is XOR DR, SR, #-1
+pub enum XorOp {
+    Immediate {
+        dr: EmulatorCell,
+        sr1: EmulatorCell,
+        imm5: EmulatorCell,
+    },
+    Register {
+        dr: EmulatorCell,
+        sr1: EmulatorCell,
+        sr2: EmulatorCell,
+    },
+}
+
+impl MicroOpGenerator for XorOp {
+    fn generate_plan(&self) -> HashMap<CycleState, Vec<MicroOp>> {
+        let mut plan = HashMap::new();
+
+        let (dr, alu) = match self {
+            XorOp::Immediate { dr, sr1, imm5 } => (
+                dr,
+                micro_op!(ALU_OUT <- R(sr1.get()) ^ IMM(imm5.sext(4).get() as i16)),
+            ),
+            XorOp::Register { dr, sr1, sr2 } => {
+                (dr, micro_op!(ALU_OUT <- R(sr1.get()) ^ R(sr2.get())))
+            }
+        };
+        plan.insert(CycleState::Execute, vec![alu]);
+        plan.insert(
+            CycleState::StoreResult,
+            vec![
+                micro_op!(R(dr.get()) <- AluOut),
+                micro_op!(SET_CC(dr.get())),
+            ],
+        );
+
+        plan
+    }
+}
+
+impl Op for XorOp {
+    fn decode(ir: EmulatorCell) -> Self {
+        // LAYOUT: 1001 | DR | SR1 | 0 | 00 | SR2
+        //         1001 | DR | SR1 | 1 | imm5
+        let dr = ir.range(11..9);
+        let sr1 = ir.range(8..6);
+        match ir.index(5).get() {
+            0 => XorOp::Register {
+                dr,
+                sr1,
+                sr2: ir.range(2..0),
+            },
+            _ => XorOp::Immediate {
+                dr,
+                sr1,
+                imm5: ir.range(4..0),
+            },
+        }
+    }
+}
+
+impl fmt::Display for XorOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            XorOp::Immediate { dr, sr1, imm5 } if imm5.get() == 0x1F => {
+                write!(f, "NOT R{}, R{}", dr.get(), sr1.get())
+            }
+            XorOp::Immediate { dr, sr1, imm5 } => write!(
+                f,
+                "XOR R{}, R{}, #{}",
+                dr.get(),
+                sr1.get(),
+                imm5.sext(4).get() as i16
+            ),
+            XorOp::Register { dr, sr1, sr2 } => {
+                write!(f, "XOR R{}, R{}, R{}", dr.get(), sr1.get(), sr2.get())
+            }
+        }
+    }
+}
@@ -1,8 +1,12 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use serde::{Deserialize, Serialize};
 
-use super::Emulator;
+use super::{ops::custom, regions::MemoryRegion, Emulator, Isa};
 
 // lazy_static! {
 //     /// Compilation artifacts for the emulator. This struct holds information about the last compiled source code, line-to-address mappings, labels, and more.
@@ -10,6 +14,9 @@ use super::Emulator;
 //         Mutex::new(CompilationArtifacts::default());
 // }
 
+/// Counts successful compiles across every machine, so each one gets its own generation
+static COMPILES: AtomicUsize = AtomicUsize::new(0);
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 /// Compilation artifacts for the emulator. This struct holds information about the last compiled source code, line-to-address mappings, labels, and more.
 pub struct CompilationArtifacts {
@@ -27,6 +34,19 @@ pub struct CompilationArtifacts {
     pub orig_address: usize,
     /// latest compilation error
     pub error: Option<ParseError>,
+    /// `;@region` comments in the most recent program
+    #[serde(default)]
+    pub regions: Vec<MemoryRegion>,
+    /// `;@note` comments in the most recent program by address
+    #[serde(default)]
+    pub notes: HashMap<usize, String>,
+    /// Things that didn't stop the program assembling but are probably mistakes, like
+    /// annotations we couldn't understand
+    #[serde(default)]
+    pub warnings: Vec<ParseError>,
+    /// Different after every successful compile, for anything caching what the labels were
+    #[serde(skip)]
+    pub generation: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Copy)]
@@ -56,6 +76,48 @@ pub enum OpToken {
     Sti,
     Str,
     Trap(Option<u8>), // we can use shorthand when lexing
+    /// The user defined instruction on opcode 1101, see [`custom`]
+    Custom,
+    // LC-3b only, on the LC-3 these are just labels
+    Ldb,
+    Stb,
+    Ldw,
+    Stw,
+    Xor,
+    Lshf,
+    Rshfl,
+    Rshfa,
+}
+
+impl OpToken {
+    /// Only an instruction when assembling for the LC-3b
+    pub fn lc3b_only(&self) -> bool {
+        matches!(
+            self,
+            OpToken::Ldb
+                | OpToken::Stb
+                | OpToken::Ldw
+                | OpToken::Stw
+                | OpToken::Xor
+                | OpToken::Lshf
+                | OpToken::Rshfl
+                | OpToken::Rshfa
+        )
+    }
+
+    /// Assembles for the LC-3 but not the LC-3b
+    pub fn lc3_only(&self) -> bool {
+        matches!(
+            self,
+            OpToken::Ld
+                | OpToken::Ldi
+                | OpToken::St
+                | OpToken::Sti
+                | OpToken::Ldr
+                | OpToken::Str
+                | OpToken::Custom
+        )
+    }
 }
 
 impl FromStr for OpToken {
@@ -91,6 +153,17 @@ impl FromStr for OpToken {
             "IN" => Ok(OpToken::Trap(Some(0x23))),
             "PUTSP" => Ok(OpToken::Trap(Some(0x24))),
             "HALT" => Ok(OpToken::Trap(Some(0x25))),
+            "LDB" => Ok(OpToken::Ldb),
+            "STB" => Ok(OpToken::Stb),
+            "LDW" => Ok(OpToken::Ldw),
+            "STW" => Ok(OpToken::Stw),
+            "XOR" => Ok(OpToken::Xor),
+            "LSHF" => Ok(OpToken::Lshf),
+            "RSHFL" => Ok(OpToken::Rshfl),
+            "RSHFA" => Ok(OpToken::Rshfa),
+            _ if custom::loaded().is_some_and(|op| op.mnemonic.eq_ignore_ascii_case(s)) => {
+                Ok(OpToken::Custom)
+            }
             _ => Err(()),
         }
     }
@@ -154,6 +227,9 @@ pub enum Token {
 
     // End of line
     EOL,
+
+    // Everything after a ;, kept to one side so the parser never sees it
+    Comment(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -164,31 +240,47 @@ pub struct TokenSpan {
 }
 
 pub struct Lexer<'a> {
+    isa: Isa,
     position: usize,
     line: usize,
     column: usize,
     tokens: Vec<TokenSpan>,
+    comments: Vec<TokenSpan>,
     chars: std::iter::Peekable<std::str::Chars<'a>>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
+        Self::with_isa(input, Isa::Lc3)
+    }
+
+    /// The LC-3b mnemonics are only opcodes when lexing for the LC-3b
+    pub fn with_isa(input: &'a str, isa: Isa) -> Self {
         Lexer {
+            isa,
             position: 0,
             line: 1,
             column: 0,
             tokens: Vec::new(),
+            comments: Vec::new(),
             chars: input.chars().peekable(),
         }
     }
 
     // Tokenize the entire input
-    pub fn tokenize(mut self) -> Result<Vec<TokenSpan>, (String, usize)> {
+    pub fn tokenize(self) -> Result<Vec<TokenSpan>, (String, usize)> {
+        self.tokenize_with_comments().map(|(tokens, _)| tokens)
+    }
+
+    /// Tokenize, also giving back a `Token::Comment` for every comment
+    pub fn tokenize_with_comments(
+        mut self,
+    ) -> Result<(Vec<TokenSpan>, Vec<TokenSpan>), (String, usize)> {
         while let Some(token) = self.next_token()? {
             self.tokens.push(token);
         }
 
-        Ok(self.tokens)
+        Ok((self.tokens, self.comments))
     }
 
     // Get the next token
@@ -211,7 +303,10 @@ impl<'a> Lexer<'a> {
 
                 // Comment
                 ';' => {
+                    let (line, column) = (self.line, self.column);
+                    let mut text = String::new();
                     // Skip the entire line
+                    self.advance();
                     for c in self.chars.by_ref() {
                         self.position += 1;
                         self.column += 1;
@@ -220,7 +315,13 @@ impl<'a> Lexer<'a> {
                             self.column = 0;
                             break;
                         }
+                        text.push(c);
                     }
+                    self.comments.push(TokenSpan {
+                        token: Token::Comment(text),
+                        line,
+                        column,
+                    });
                     Ok(Some(TokenSpan {
                         token: Token::EOL,
                         line: self.line,
@@ -254,10 +355,12 @@ impl<'a> Lexer<'a> {
                 // Numbers or identifiers
                 _ => {
                     if c.is_numeric() || *c == '#' || *c == 'x' || *c == 'X' || *c == '-' {
+                        let rewind = (self.chars.clone(), self.position, self.column);
                         match self.tokenize_number() {
                             Ok(num_token) => Ok(num_token),
                             Err(err) => {
-                                // Mabye it is a lable starting with x
+                                // Mabye it is a lable starting with x, like XOR
+                                (self.chars, self.position, self.column) = rewind;
                                 match self.tokenize_word() {
                                     Ok(str_token) => Ok(str_token),
                                     Err(_) => Err(err), // It makes more sense to return the origanal error
@@ -328,6 +431,7 @@ impl<'a> Lexer<'a> {
                         't' => string_content.push('\t'),
                         'r' => string_content.push('\r'),
                         '0' => string_content.push('\0'),
+                        'e' => string_content.push('\x1b'), // for terminal escape codes
                         '\\' => string_content.push('\\'),
                         '"' => string_content.push('"'),
                         _ => {
@@ -473,7 +577,10 @@ impl<'a> Lexer<'a> {
             }));
         }
 
-        if let Ok(op_token) = OpToken::from_str(word.as_str()) {
+        if let Some(op_token) = OpToken::from_str(word.as_str())
+            .ok()
+            .filter(|op| self.isa == Isa::Lc3b || !op.lc3b_only())
+        {
             tracing::trace!("Opcode token: {:?}", op_token);
             return Ok(Some(TokenSpan {
                 token: Token::Opcode(op_token),
@@ -503,7 +610,7 @@ impl<'a> Lexer<'a> {
 }
 
 /// Output structure for the `parse_program` function.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParseOutput {
     /// Vector containing the generated machine code instructions/data.
     pub machine_code: Vec<u16>,
@@ -514,22 +621,40 @@ pub struct ParseOutput {
     pub labels: HashMap<String, usize>,
     /// The starting memory address specified by the .ORIG directive.
     pub orig_address: usize,
+    /// The machine it was assembled for, addresses count bytes on the LC-3b
+    pub isa: Isa,
     address_to_line: HashMap<usize, usize>,
 }
 
 pub struct Parser {
+    isa: Isa,
     tokens: Vec<TokenSpan>,
     position: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<TokenSpan>) -> Self {
+        Self::with_isa(tokens, Isa::Lc3)
+    }
+
+    /// On the LC-3b addresses count bytes, so each word moves the address by 2
+    pub fn with_isa(tokens: Vec<TokenSpan>, isa: Isa) -> Self {
         Parser {
+            isa,
             tokens,
             position: 0,
         }
     }
 
+    /// Words a .STRINGZ takes up, the LC-3b packs a char per byte
+    fn string_words(&self, content: &str) -> usize {
+        let chars = content.chars().count() + 1; // +1 for null terminator
+        match self.isa {
+            Isa::Lc3 => chars,
+            Isa::Lc3b => chars.div_ceil(2),
+        }
+    }
+
     pub fn parse(&mut self) -> Result<ParseOutput, (String, TokenSpan)> {
         let mut machine_code = vec![];
         let mut labels = HashMap::new();
@@ -540,7 +665,13 @@ impl Parser {
         let mut orig_set = false;
 
         // First pass: collect labels and determine addresses
-        self.first_pass(&mut labels, &mut address, &mut orig_address, &mut orig_set)?;
+        self.first_pass(
+            &mut labels,
+            &mut HashMap::new(),
+            &mut address,
+            &mut orig_address,
+            &mut orig_set,
+        )?;
 
         // Reset for second pass
         self.position = 0;
@@ -561,6 +692,31 @@ impl Parser {
             address_to_line,
             labels,
             orig_address,
+            isa: self.isa,
+        })
+    }
+
+    /// Only the first pass, where the labels and lines go. It works when an operand doesn't
+    /// assemble (like a label too far away), the output just has no machine code
+    pub fn layout(&mut self) -> Result<ParseOutput, (String, TokenSpan)> {
+        let mut labels = HashMap::new();
+        let mut line_to_address = HashMap::new();
+        let mut orig_address = 0x3000;
+        let mut address = 0x3000;
+        self.first_pass(
+            &mut labels,
+            &mut line_to_address,
+            &mut address,
+            &mut orig_address,
+            &mut false,
+        )?;
+        Ok(ParseOutput {
+            machine_code: Vec::new(),
+            line_to_address,
+            address_to_line: HashMap::new(),
+            labels,
+            orig_address,
+            isa: self.isa,
         })
     }
 
@@ -568,6 +724,7 @@ impl Parser {
     fn first_pass(
         &mut self,
         labels: &mut HashMap<String, usize>,
+        line_to_address: &mut HashMap<usize, usize>,
         address: &mut usize,
         orig_address: &mut usize,
         orig_set: &mut bool,
@@ -575,6 +732,10 @@ impl Parser {
         while self.position < self.tokens.len() {
             let token_span = self.tokens[self.position].clone();
             let line = token_span.line;
+            // the same lines the second pass maps, for a layout without code
+            if !matches!(token_span.token, Token::EOL) {
+                line_to_address.entry(line).or_insert(*address);
+            }
 
             // Keep track of current line for error reporting
             match &token_span.token {
@@ -625,7 +786,7 @@ impl Parser {
                     } else {
                         // Treat as an opcode or standalone label
                         // For first pass, we just need to calculate address increments
-                        *address = address.checked_add(1).ok_or((
+                        *address = address.checked_add(self.isa.word_size()).ok_or((
                             format!("Address overflow past 0xFFFF on line {line}"),
                             token_span,
                         ))?;
@@ -683,6 +844,15 @@ impl Parser {
                                 }
                             }
 
+                            line_to_address.insert(line, *address);
+
+                            if self.isa == Isa::Lc3b && *address % 2 == 1 {
+                                return Err((
+                                    ".ORIG has to be an even address on the LC-3b".to_string(),
+                                    addr_token.clone(),
+                                ));
+                            }
+
                             // Skip past directive and address
                             self.position += 2;
                         }
@@ -701,7 +871,7 @@ impl Parser {
                             }
 
                             // .FILL takes one word
-                            *address = address.checked_add(1).ok_or((
+                            *address = address.checked_add(self.isa.word_size()).ok_or((
                                 format!("Address overflow past 0xFFFF on line {line}"),
                                 token_span,
                             ))?;
@@ -755,10 +925,12 @@ impl Parser {
                                 block_size
                             );
 
-                            *address = address.checked_add(block_size as usize).ok_or((
-                                format!("Address overflow past 0xFFFF on line {line}"),
-                                token_span,
-                            ))?;
+                            *address = address
+                                .checked_add(block_size as usize * self.isa.word_size())
+                                .ok_or((
+                                    format!("Address overflow past 0xFFFF on line {line}"),
+                                    token_span,
+                                ))?;
 
                             // Skip directive and size
                             self.position += 2;
@@ -783,7 +955,7 @@ impl Parser {
                             let string_token = &self.tokens[self.position + 1];
                             match &string_token.token {
                                 Token::StringLiteral(content) => {
-                                    let string_size = content.chars().count() + 1; // +1 for null terminator
+                                    let string_size = self.string_words(content);
                                     tracing::trace!(
                                         "Line {}: Directive .STRINGZ \"{}\" (size {})",
                                         line,
@@ -791,10 +963,12 @@ impl Parser {
                                         string_size
                                     );
 
-                                    *address = address.checked_add(string_size).ok_or((
-                                        format!("Address overflow past 0xFFFF on line {line}"),
-                                        token_span,
-                                    ))?;
+                                    *address = address
+                                        .checked_add(string_size * self.isa.word_size())
+                                        .ok_or((
+                                            format!("Address overflow past 0xFFFF on line {line}"),
+                                            token_span,
+                                        ))?;
                                 }
                                 _ => {
                                     return Err((
@@ -825,7 +999,7 @@ impl Parser {
                     }
 
                     // Instructions take one word
-                    *address = address.checked_add(1).ok_or((
+                    *address = address.checked_add(self.isa.word_size()).ok_or((
                         format!("Address overflow past 0xFFFF on line {line}"),
                         token_span,
                     ))?;
@@ -952,7 +1126,7 @@ impl Parser {
                             address_to_line.insert(*address, line);
 
                             machine_code.push(value);
-                            *address += 1;
+                            *address += self.isa.word_size();
                             self.position += 2; // Skip directive and value
                         }
                         ".BLKW" => {
@@ -979,7 +1153,7 @@ impl Parser {
                                 // Map each generated address back to the source line
                                 address_to_line.insert(*address, line);
                                 machine_code.push(0); // Fill with zeros
-                                *address += 1;
+                                *address += self.isa.word_size();
                             }
                             self.position += 2; // Skip directive and size
                         }
@@ -993,6 +1167,23 @@ impl Parser {
 
                             let string_token = &self.tokens[self.position + 1];
                             match &string_token.token {
+                                Token::StringLiteral(content) if self.isa == Isa::Lc3b => {
+                                    // Little endian, the first char of each pair is the low byte
+                                    let bytes: Vec<u16> =
+                                        content.chars().map(|c| c as u16 & 0xFF).collect();
+                                    for pair in bytes.chunks(2) {
+                                        address_to_line.insert(*address, line);
+                                        machine_code.push(pair[0] | pair.get(1).unwrap_or(&0) << 8);
+                                        *address += 2;
+                                    }
+
+                                    // Null terminator (already there when the length is odd)
+                                    if bytes.len() % 2 == 0 {
+                                        address_to_line.insert(*address, line);
+                                        machine_code.push(0);
+                                        *address += 2;
+                                    }
+                                }
                                 Token::StringLiteral(content) => {
                                     // Process each character
                                     for c in content.chars() {
@@ -1053,7 +1244,7 @@ impl Parser {
                     address_to_line.insert(current_address, line);
 
                     machine_code.push(instruction);
-                    *address += 1;
+                    *address += self.isa.word_size();
 
                     // Skip to next line
                     self.position = op_pos;
@@ -1085,6 +1276,21 @@ impl Parser {
         labels: &HashMap<String, usize>,
     ) -> Result<u16, (String, TokenSpan)> {
         let token_span = token_span.clone();
+        if self.isa == Isa::Lc3b {
+            let replaced_by = match op {
+                OpToken::Ld | OpToken::Ldi | OpToken::St | OpToken::Sti => {
+                    Some("isn't on the LC-3b, LEA the address then use LDW/STW")
+                }
+                OpToken::Ldr | OpToken::Str => {
+                    Some("isn't on the LC-3b, use LDW/STW (or LDB/STB for a byte)")
+                }
+                OpToken::Custom => Some("is a custom instruction, 1101 is SHF on the LC-3b"),
+                _ => None,
+            };
+            if let Some(replaced_by) = replaced_by {
+                return Err((format!("{op:?} {replaced_by}").to_uppercase(), token_span));
+            }
+        }
         match op {
             OpToken::Add => {
                 if operands.len() < 3 {
@@ -1374,6 +1580,112 @@ impl Parser {
                 let instruction = (0b1111 << 12) | trapvect8;
                 Ok(instruction)
             }
+
+            OpToken::Custom => {
+                let Some(custom) = custom::loaded() else {
+                    return Err((
+                        "No custom instruction is loaded for opcode 1101".to_string(),
+                        token_span,
+                    ));
+                };
+                if operands.len() < custom.fields.len() {
+                    return Err((
+                        format!(
+                            "Invalid {} format: expected {}",
+                            custom.mnemonic,
+                            custom.signature()
+                        ),
+                        token_span,
+                    ));
+                }
+
+                let mut values = Vec::with_capacity(custom.fields.len());
+                for (field, operand) in custom.fields.iter().zip(operands) {
+                    values.push(match field.kind {
+                        custom::FieldKind::Register => self.parse_register(operand)?,
+                        custom::FieldKind::Immediate => {
+                            self.parse_immediate(operand, field.width())?
+                        }
+                        custom::FieldKind::PcOffset => {
+                            self.parse_offset(operand, current_address, labels, field.width())?
+                        }
+                    });
+                }
+                Ok(custom.encode(&values))
+            }
+
+            // The lexer only makes these on the LC-3b
+            OpToken::Ldb | OpToken::Stb | OpToken::Ldw | OpToken::Stw => {
+                let name = format!("{op:?}").to_uppercase();
+                if operands.len() < 3 {
+                    return Err((
+                        format!("Invalid {name} format: not enough operands"),
+                        token_span,
+                    ));
+                }
+
+                let opcode: u16 = match op {
+                    OpToken::Ldb => 0b0010,
+                    OpToken::Stb => 0b0011,
+                    OpToken::Ldw => 0b0110,
+                    _ => 0b0111,
+                };
+                let reg = self.parse_register(&operands[0])?;
+                let base_r = self.parse_register(&operands[1])?;
+                // bytes for LDB/STB, words for LDW/STW
+                let offset = self.parse_immediate(&operands[2], 6)?;
+
+                Ok((opcode << 12) | (reg << 9) | (base_r << 6) | (offset & 0x3F))
+            }
+
+            OpToken::Xor => {
+                if operands.len() < 3 {
+                    return Err((
+                        "Invalid XOR format: not enough operands".to_string(),
+                        token_span,
+                    ));
+                }
+
+                let dr = self.parse_register(&operands[0])?;
+                let sr1 = self.parse_register(&operands[1])?;
+                match &operands[2].token {
+                    Token::Register(sr2) => Ok((0b1001 << 12) | (dr << 9) | (sr1 << 6) | *sr2),
+                    Token::Immediate(_) | Token::HexValue(_) => {
+                        let imm5 = self.parse_immediate(&operands[2], 5)?;
+                        Ok((0b1001 << 12) | (dr << 9) | (sr1 << 6) | (1 << 5) | (imm5 & 0x1F))
+                    }
+                    _ => Err(("Invalid XOR operand".to_string(), operands[2].clone())),
+                }
+            }
+
+            OpToken::Lshf | OpToken::Rshfl | OpToken::Rshfa => {
+                let name = format!("{op:?}").to_uppercase();
+                if operands.len() < 3 {
+                    return Err((
+                        format!("Invalid {name} format: not enough operands"),
+                        token_span,
+                    ));
+                }
+
+                let dr = self.parse_register(&operands[0])?;
+                let sr = self.parse_register(&operands[1])?;
+                let amount = match &operands[2].token {
+                    Token::Immediate(amount) | Token::HexValue(amount) if *amount <= 15 => *amount,
+                    _ => {
+                        return Err((
+                            "Shift amount has to be 0 to 15".to_string(),
+                            operands[2].clone(),
+                        ))
+                    }
+                };
+                let direction = match op {
+                    OpToken::Lshf => 0b00,
+                    OpToken::Rshfl => 0b01,
+                    _ => 0b11,
+                };
+
+                Ok((0b1101 << 12) | (dr << 9) | (sr << 6) | (direction << 4) | amount)
+            }
         }
     }
 
@@ -1423,8 +1735,13 @@ impl Parser {
         match &token.token {
             Token::LabelRef(label) => {
                 if let Some(&label_addr) = labels.get(label) {
-                    let offset = (label_addr as i16) - (current_address as i16 + 1);
-                    self.check_immediate_range(offset, width)
+                    let Some(distance) = pc_offset(self.isa, current_address, label_addr) else {
+                        return Err((
+                            format!("{label} is at an odd address, you can only jump to words"),
+                            token.clone(),
+                        ));
+                    };
+                    self.check_immediate_range(distance, width)
                         .map_err(|x| (x, token.clone()))
                 } else {
                     Err((format!("Unknown label: {label}"), token.clone()))
@@ -1468,6 +1785,14 @@ impl Parser {
     }
 }
 
+/// The PC offset from the instruction at `from` to `to`. Offsets count words from the
+/// incremented PC, `None` if `to` isn't on a word
+pub fn pc_offset(isa: Isa, from: usize, to: usize) -> Option<i16> {
+    let word = isa.word_size() as i16;
+    let distance = (to as i16).wrapping_sub(from as i16).wrapping_sub(word);
+    (distance % word == 0).then_some(distance / word)
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ParseError {
     TokenizeError(String, usize),
@@ -1491,19 +1816,29 @@ impl Emulator {
         );
 
         for (i, instruction) in cells.iter().enumerate() {
-            let addr = start_address + i;
+            let addr = start_address + i * self.isa.word_size();
             if addr >= self.memory.len() {
                 tracing::error!("Address {:04X} is out of memory bounds", addr);
                 break;
             }
             tracing::trace!("Setting memory[{:04X}] = {:04X}", addr, *instruction);
             self.memory[addr].set(*instruction);
+            self.init_tracker.mark_memory(addr, true);
         }
     }
 
     pub fn parse_program(
         program: &str,
         artifacts: Option<&mut CompilationArtifacts>,
+    ) -> Result<ParseOutput, ParseError> {
+        Self::parse_program_for(Isa::Lc3, program, artifacts)
+    }
+
+    /// Assemble for the LC-3 or the LC-3b
+    pub fn parse_program_for(
+        isa: Isa,
+        program: &str,
+        artifacts: Option<&mut CompilationArtifacts>,
     ) -> Result<ParseOutput, ParseError> {
         let span = tracing::info_span!("parse_program", program_length = program.len());
         let _guard = span.enter();
@@ -1511,12 +1846,12 @@ impl Emulator {
         tracing::info!("starting to parse program");
 
         // step 1: tokenize the input
-        let lexer = Lexer::new(program);
+        let lexer = Lexer::with_isa(program, isa);
         let tokens = lexer
-            .tokenize()
+            .tokenize_with_comments()
             .map_err(|(str, line)| ParseError::TokenizeError(str, line));
 
-        let tokens = match tokens {
+        let (tokens, comments) = match tokens {
             Ok(tokens) => tokens,
             Err(err) => {
                 if let Some(artifacts) = artifacts {
@@ -1530,7 +1865,7 @@ impl Emulator {
         tracing::trace!("tokens: {:?}", tokens);
 
         // step 2: parse the tokens
-        let mut parser = Parser::new(tokens);
+        let mut parser = Parser::with_isa(tokens, isa);
         let out = parser
             .parse()
             .map_err(|(str, tok)| ParseError::GenerationError(str, tok));
@@ -1554,10 +1889,20 @@ impl Emulator {
                     .extend(labels.iter().map(|(v, k)| (*k, v.clone())));
                 artifacts.orig_address = *orig_address;
                 artifacts.error = None;
+                artifacts.generation = COMPILES.fetch_add(1, Ordering::Relaxed) + 1;
                 artifacts.last_compiled_source = program
                     .split("\n")
                     .map(|st| st.to_string())
                     .collect::<Vec<String>>();
+                let annotations =
+                    super::regions::parse_annotations(&comments, labels, line_to_address);
+                artifacts.warnings = annotations
+                    .errors
+                    .into_iter()
+                    .map(|(comment, problem)| ParseError::GenerationError(problem, comment))
+                    .collect();
+                artifacts.regions = annotations.regions;
+                artifacts.notes = annotations.notes;
                 tracing::debug!("compilation artifacts updated");
                 tracing::debug!("compilation artifacts: \n{:#?}", artifacts);
             }
@@ -1570,4 +1915,11 @@ impl Emulator {
 
         out
     }
+
+    /// Where the labels and lines of `program` go, even if its operands don't assemble. `None`
+    /// if it doesn't get that far
+    pub fn layout_program(isa: Isa, program: &str) -> Option<ParseOutput> {
+        let tokens = Lexer::with_isa(program, isa).tokenize().ok()?;
+        Parser::with_isa(tokens, isa).layout().ok()
+    }
 }
@@ -0,0 +1,444 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::emulator::micro_op::{
+    DataDestination, DataSource, MachineFlag, MicroOp, MicroOpGenerator,
+};
+use crate::emulator::ops::custom::{self, CustomInstruction};
+use crate::emulator::ops::CustomOp;
+use crate::emulator::{Emulator, EmulatorCell, Isa};
+
+/// Keep the diagram rows for this many instructions, the oldest get dropped after that
+pub const MAX_RECORDS: usize = 512;
+
+/// The five stages, in order
+pub const STAGE_NAMES: [&str; 5] = ["IF", "ID", "EX", "MEM", "WB"];
+
+/// Slot in the ready table for the condition codes, BR waits on them like a register
+const CC: usize = 8;
+
+/// One instruction going down the pipeline, or one fetched down the wrong path
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PipelineRecord {
+    pub pc: u16,
+    /// The instruction, 0 for wrong path fetches since they never got decoded
+    pub word: u16,
+    /// The cycle it entered IF, ID, EX, MEM and WB
+    pub stages: [u64; 5],
+    /// Cycles it sat in ID waiting for an operand
+    pub stalls: u64,
+    /// Set for wrong path fetches, the cycle they were thrown away at the end of
+    pub flushed_at: Option<u64>,
+}
+
+impl PipelineRecord {
+    /// What stage it is in on `cycle`, and whether it is only there because it is held up
+    pub fn stage_at(&self, cycle: u64) -> Option<(usize, bool)> {
+        if cycle < self.stages[0] || self.flushed_at.is_some_and(|end| cycle > end) {
+            return None;
+        }
+        if cycle > self.stages[4] {
+            return None;
+        }
+        let stage = self.stages.iter().rposition(|&start| start <= cycle)?;
+        // anything past the first cycle in a stage is a stall (only IF and ID ever hold)
+        Some((stage, cycle > self.stages[stage]))
+    }
+
+    pub fn flushed(&self) -> bool {
+        self.flushed_at.is_some()
+    }
+}
+
+/// Totals since the pipeline was turned on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PipelineStats {
+    pub instructions: u64,
+    /// The cycle the last instruction got to WB
+    pub cycles: u64,
+    /// Bubbles from waiting on an operand
+    pub stall_cycles: u64,
+    /// Wrong path instructions thrown away after taken branches, jumps, traps and exceptions
+    pub flushed: u64,
+}
+
+impl PipelineStats {
+    pub fn cpi(&self) -> f64 {
+        if self.instructions == 0 {
+            0.0
+        } else {
+            self.cycles as f64 / self.instructions as f64
+        }
+    }
+}
+
+/// A classic 5 stage pipeline timing model (IF ID EX MEM WB) over the instructions the emulator runs.
+///
+/// The emulator still runs one instruction at a time, this works out what cycle each one would
+/// have gone through each stage. Branches are predicted not taken and resolved in EX, so a
+/// taken one throws away the two instructions behind it. Loads have their value after MEM,
+/// everything else after EX, and stores want their data by EX too. Without forwarding
+/// operands come out of the register file in ID, which is written in the first half of the cycle.
+#[derive(Debug, Clone)]
+pub struct Pipeline {
+    pub forwarding: bool,
+    pub records: VecDeque<PipelineRecord>,
+    pub stats: PipelineStats,
+    /// The last real instruction, the next one's timing depends on it
+    last: Option<PipelineRecord>,
+    /// For each register (and CC), the first cycle an instruction can be in EX and have its value
+    ready: [u64; 9],
+}
+
+impl Pipeline {
+    pub fn new(forwarding: bool) -> Self {
+        Self {
+            forwarding,
+            records: VecDeque::new(),
+            stats: PipelineStats::default(),
+            last: None,
+            ready: [0; 9],
+        }
+    }
+
+    fn push(&mut self, record: PipelineRecord) {
+        if self.records.len() >= MAX_RECORDS {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    /// Time the instruction `word` fetched from `pc`
+    pub fn issue(&mut self, isa: Isa, pc: u16, word: u16) {
+        let operands = Operands::of(isa, word);
+        let mut earliest_fetch = 1;
+        let mut earliest_decode = 0;
+        let mut earliest_execute = 0;
+        let mut earliest_memory = 0;
+
+        if let Some(last) = self.last {
+            let [_, decode, execute, memory, write_back] = last.stages;
+            // a stage frees up when the instruction ahead moves on from it
+            earliest_fetch = decode;
+            earliest_decode = execute;
+            earliest_execute = memory;
+            earliest_memory = write_back;
+
+            if pc != last.pc.wrapping_add(isa.word_size() as u16) {
+                // it went somewhere else, which is only known once it got through EX
+                let wrong_path = last.pc.wrapping_add(isa.word_size() as u16);
+                self.push(PipelineRecord {
+                    pc: wrong_path,
+                    word: 0,
+                    stages: [decode, execute, u64::MAX, u64::MAX, u64::MAX],
+                    stalls: 0,
+                    flushed_at: Some(execute),
+                });
+                self.push(PipelineRecord {
+                    pc: wrong_path.wrapping_add(isa.word_size() as u16),
+                    word: 0,
+                    stages: [execute, u64::MAX, u64::MAX, u64::MAX, u64::MAX],
+                    stalls: 0,
+                    flushed_at: Some(execute),
+                });
+                self.stats.flushed += 2;
+                earliest_fetch = execute + 1;
+            }
+        }
+
+        let fetch = earliest_fetch;
+        let decode = earliest_decode.max(fetch + 1);
+        let operands_ready = if operands.serialising {
+            self.ready.iter().copied().max().unwrap_or(0)
+        } else {
+            operands
+                .sources()
+                .map(|source| self.ready[source])
+                .max()
+                .unwrap_or(0)
+        };
+        let execute = earliest_execute.max(decode + 1).max(operands_ready);
+        let memory = earliest_memory.max(execute + 1);
+        let write_back = memory + 1;
+
+        let value_ready = match (self.forwarding, operands.load) {
+            (true, true) => memory + 1,
+            (true, false) => execute + 1,
+            // read in ID on the cycle of WB at the earliest
+            (false, _) => write_back + 1,
+        };
+        for dest in operands.dests() {
+            self.ready[dest] = value_ready;
+        }
+        if operands.sets_cc {
+            self.ready[CC] = value_ready;
+        }
+        if operands.serialising {
+            self.ready = [value_ready; 9];
+        }
+
+        let stalls = execute - decode - 1;
+        let record = PipelineRecord {
+            pc,
+            word,
+            stages: [fetch, decode, execute, memory, write_back],
+            stalls,
+            flushed_at: None,
+        };
+        self.push(record);
+        self.last = Some(record);
+        self.stats.instructions += 1;
+        self.stats.stall_cycles += stalls;
+        self.stats.cycles = write_back;
+    }
+}
+
+/// Which registers an instruction reads and writes, worked out from its bits
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct Operands {
+    sources: [Option<usize>; 3],
+    dest: Option<usize>,
+    /// JSR (and TRAP on the LC-3b) also write R7, RTI and the LC-3's TRAP write R6
+    link: Option<usize>,
+    sets_cc: bool,
+    load: bool,
+    /// Waits on every register and CC, and they all wait on it
+    serialising: bool,
+}
+
+impl Operands {
+    fn of(isa: Isa, word: u16) -> Self {
+        let dr = Some((word >> 9) as usize & 0b111);
+        let sr1 = Some((word >> 6) as usize & 0b111);
+        let sr2 = (word & 0x20 == 0).then_some(word as usize & 0b111);
+        let lc3b = isa == Isa::Lc3b;
+        let mut operands = Operands::default();
+        match word >> 12 {
+            // ADD, AND, NOT (and XOR on the LC-3b)
+            0b0001 | 0b0101 | 0b1001 => {
+                operands.sources = [sr1, sr2, None];
+                operands.dest = dr;
+                operands.sets_cc = true;
+            }
+            // SHF
+            0b1101 if lc3b => {
+                operands.sources = [sr1, None, None];
+                operands.dest = dr;
+                operands.sets_cc = true;
+            }
+            // BR
+            0b0000 => operands.sources = [Some(CC), None, None],
+            // JMP/RET
+            0b1100 => operands.sources = [sr1, None, None],
+            // JSR/JSRR
+            0b0100 => {
+                if word & 0x0800 == 0 {
+                    operands.sources = [sr1, None, None];
+                }
+                operands.link = Some(7);
+            }
+            // LEA
+            0b1110 => operands.dest = dr,
+            // LD, LDI (LDB on the LC-3b)
+            0b0010 | 0b1010 => {
+                operands.sources = [if lc3b { sr1 } else { None }, None, None];
+                operands.dest = dr;
+                operands.sets_cc = true;
+                operands.load = true;
+            }
+            // LDR/LDW
+            0b0110 => {
+                operands.sources = [sr1, None, None];
+                operands.dest = dr;
+                operands.sets_cc = true;
+                operands.load = true;
+            }
+            // ST, STI (STB on the LC-3b)
+            0b0011 | 0b1011 => operands.sources = [dr, if lc3b { sr1 } else { None }, None],
+            // STR/STW
+            0b0111 => operands.sources = [dr, sr1, None],
+            // RTI pops off R6
+            0b1000 => {
+                operands.sources = [Some(6), None, None];
+                operands.link = Some(6);
+                operands.sets_cc = true;
+                operands.load = true;
+            }
+            // TRAP, the LC-3 pushes PC and PSR on the supervisor stack where the LC-3b links R7
+            0b1111 => operands.link = Some(if lc3b { 7 } else { 6 }),
+            // A custom 1101 reads and writes whatever its plan says
+            0b1101 => {
+                if let Some(instruction) = custom::loaded() {
+                    operands = Operands::of_custom(instruction, word);
+                }
+            }
+            _ => {}
+        }
+        operands
+    }
+
+    /// The registers a custom instruction's steps use. One that uses more than fit waits for
+    /// everything ahead of it and holds up everything behind it
+    fn of_custom(instruction: Arc<CustomInstruction>, word: u16) -> Self {
+        let op = CustomOp::decode(instruction, EmulatorCell::new(word));
+        let mut reads = Vec::new();
+        let mut writes = Vec::new();
+        let mut operands = Operands::default();
+        let mut read = |source: &DataSource| {
+            if let DataSource::Register(n) = *source {
+                if !reads.contains(&(n as usize)) {
+                    reads.push(n as usize);
+                }
+            }
+        };
+        for micro_op in op.generate_plan().values().flatten() {
+            match micro_op {
+                MicroOp::Transfer {
+                    source,
+                    destination,
+                } => {
+                    read(source);
+                    operands.load |= matches!(source, DataSource::MDR);
+                    if let DataDestination::Register(n) = *destination {
+                        if !writes.contains(&(n as usize)) {
+                            writes.push(n as usize);
+                        }
+                    }
+                }
+                MicroOp::Alu {
+                    operand1, operand2, ..
+                } => {
+                    read(operand1);
+                    read(operand2);
+                }
+                MicroOp::SetFlag(MachineFlag::UpdateCondCodes(_)) => operands.sets_cc = true,
+                _ => {}
+            }
+        }
+        if reads.len() > operands.sources.len() || writes.len() > 2 {
+            operands.serialising = true;
+            return operands;
+        }
+        for (slot, register) in operands.sources.iter_mut().zip(reads) {
+            *slot = Some(register);
+        }
+        operands.dest = writes.first().copied();
+        operands.link = writes.get(1).copied();
+        operands
+    }
+
+    fn sources(&self) -> impl Iterator<Item = usize> + '_ {
+        self.sources.iter().flatten().copied()
+    }
+
+    fn dests(&self) -> impl Iterator<Item = usize> + '_ {
+        self.dest.iter().chain(self.link.iter()).copied()
+    }
+}
+
+impl Emulator {
+    /// Turn the pipeline timing model on or off, it starts counting from the next instruction
+    pub fn use_pipeline(&mut self, on: bool) {
+        self.pipeline = on.then(|| Pipeline::new(true));
+    }
+
+    /// Time the instruction just fetched, called alongside [`Emulator::track_call`]
+    pub(super) fn track_pipeline(&mut self) {
+        let pc = self.currently_executing as u16;
+        let word = self.memory[self.word_address(pc)].get();
+        let isa = self.isa;
+        if let Some(pipeline) = &mut self.pipeline {
+            pipeline.issue(isa, pc, word);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(forwarding: bool, program: &[(u16, u16)]) -> Pipeline {
+        let mut pipeline = Pipeline::new(forwarding);
+        for &(pc, word) in program {
+            pipeline.issue(Isa::Lc3, pc, word);
+        }
+        pipeline
+    }
+
+    #[test]
+    fn independent_instructions_dont_stall() {
+        // ADD R1, R1, #1 / ADD R2, R2, #1 / ADD R3, R3, #1
+        let pipeline = run(
+            true,
+            &[(0x3000, 0x1261), (0x3001, 0x14A1), (0x3002, 0x16E1)],
+        );
+        assert_eq!(pipeline.stats.cycles, 7);
+        assert_eq!(pipeline.stats.stall_cycles, 0);
+        assert_eq!(pipeline.records[2].stages, [3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn forwarding_and_load_use() {
+        // LDR R1, R0, #0 / ADD R2, R1, #1 / ADD R3, R2, #1
+        let program = [(0x3000, 0x6200), (0x3001, 0x1461), (0x3002, 0x16A1)];
+        let forwarded = run(true, &program);
+        // only the load has to hold its user back a cycle
+        assert_eq!(forwarded.records[1].stalls, 1);
+        assert_eq!(forwarded.records[2].stalls, 0);
+        assert_eq!(forwarded.stats.cycles, 8);
+
+        let not_forwarded = run(false, &program);
+        // each one waits for the one before to get to WB
+        assert_eq!(not_forwarded.records[1].stalls, 2);
+        assert_eq!(not_forwarded.records[2].stalls, 2);
+        assert_eq!(not_forwarded.stats.cycles, 11);
+    }
+
+    #[test]
+    fn taken_branch_flushes_two() {
+        // BRnzp x3005 / ADD R1, R1, #1 at the target
+        let pipeline = run(true, &[(0x3000, 0x0E04), (0x3005, 0x1261)]);
+        assert_eq!(pipeline.stats.flushed, 2);
+        assert_eq!(pipeline.records.len(), 4);
+        assert!(pipeline.records[1].flushed() && pipeline.records[2].flushed());
+        assert_eq!(pipeline.records[1].pc, 0x3001);
+        // fetched the cycle after the branch was in EX
+        assert_eq!(pipeline.records[3].stages[0], 4);
+        assert_eq!(pipeline.stats.cycles, 8);
+        assert_eq!(pipeline.records[1].stage_at(3), Some((1, false)));
+        assert_eq!(pipeline.records[1].stage_at(4), None);
+    }
+
+    #[test]
+    fn trap_links_r7_only_on_the_lc3b() {
+        // TRAP x25
+        let dests = |isa| Operands::of(isa, 0xF025).dests().collect::<Vec<_>>();
+        assert_eq!(dests(Isa::Lc3), [6]);
+        assert_eq!(dests(Isa::Lc3b), [7]);
+    }
+
+    #[test]
+    fn custom_instruction_operands_come_from_its_plan() {
+        let mul = CustomInstruction::from_ron(include_str!("../../assets/custom_ops/mul.ron"))
+            .map(Arc::new)
+            .unwrap();
+        // MUL R1, R2, R3
+        let operands = Operands::of_custom(mul.clone(), 0b1101_001_010_000_011);
+        let mut sources = operands.sources().collect::<Vec<_>>();
+        sources.sort();
+        assert_eq!(sources, [2, 3]);
+        assert_eq!(operands.dests().collect::<Vec<_>>(), [1]);
+        assert!(operands.sets_cc && !operands.load && !operands.serialising);
+
+        // writing three registers doesn't fit, so it holds up everything
+        let mut wide = (*mul).clone();
+        for n in 4..6 {
+            wide.plan.store_result.push(custom::Step::Transfer {
+                to: custom::Destination::R(n),
+                from: custom::Source::AluOut,
+            });
+        }
+        let wide = Operands::of_custom(Arc::new(wide), 0b1101_001_010_000_011);
+        assert!(wide.serialising);
+    }
+}
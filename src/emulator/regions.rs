@@ -0,0 +1,256 @@
+//! Named, coloured bits of memory ("stack", "input buffer" etc) and notes on single addresses.
+//! Programs declare them in comments so the source still works with any other assembler:
+//!
+//! ```norust
+//! ;@region "input buffer" BUFFER BUFFER_END
+//! ;@region stack xFD00 xFDFF color=ff8844
+//! COUNT .FILL 0 ;@note how many chars we have read
+//! ```
+//!
+//! A note on a line without code goes to the next line that has some.
+
+use std::collections::HashMap;
+
+use egui::Color32;
+use serde::{Deserialize, Serialize};
+
+use super::parse::{Token, TokenSpan};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MemoryRegion {
+    pub name: String,
+    pub start: u16,
+    /// Inclusive
+    pub end: u16,
+    pub color: Color32,
+}
+
+impl MemoryRegion {
+    pub fn new(name: impl Into<String>, start: u16, end: u16) -> Self {
+        let name = name.into();
+        Self {
+            color: color_for(&name),
+            name,
+            start,
+            end,
+        }
+    }
+
+    pub fn contains(&self, address: usize) -> bool {
+        (self.start as usize..=self.end as usize).contains(&address)
+    }
+
+    /// The comment that would declare this region in a program
+    pub fn to_annotation(&self) -> String {
+        let name = if self.name.contains(char::is_whitespace) {
+            format!("\"{}\"", self.name)
+        } else {
+            self.name.clone()
+        };
+        let [r, g, b, _] = self.color.to_array();
+        format!(
+            ";@region {name} x{:04X} x{:04X} color={r:02x}{g:02x}{b:02x}",
+            self.start, self.end
+        )
+    }
+}
+
+/// Pick a colour from the name so regions without one still look different
+pub fn color_for(name: &str) -> Color32 {
+    let hash = name
+        .bytes()
+        .fold(0u32, |hash, b| hash.wrapping_mul(31).wrapping_add(b as u32));
+    let hue = (hash % 360) as f32 / 360.0;
+    egui::ecolor::Hsva::new(hue, 0.6, 0.8, 1.0).into()
+}
+
+/// Everything declared in the comments of a program
+#[derive(Debug, Default)]
+pub struct Annotations {
+    pub regions: Vec<MemoryRegion>,
+    pub notes: HashMap<usize, String>,
+    /// (comment, problem) for annotations we couldn't understand
+    pub errors: Vec<(TokenSpan, String)>,
+}
+
+/// Split on whitespace but keep "quoted bits" together
+fn split_args(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !current.is_empty() {
+                    args.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        args.push(current);
+    }
+    args
+}
+
+/// An address written as xHEX, #DEC, DEC or a label
+pub fn parse_address(text: &str, labels: &HashMap<String, usize>) -> Result<u16, String> {
+    let number = if let Some(hex) = text.strip_prefix(['x', 'X']) {
+        u16::from_str_radix(hex, 16).ok()
+    } else {
+        text.trim_start_matches('#').parse::<u16>().ok()
+    };
+    number
+        .or_else(|| labels.get(text).map(|&address| address as u16))
+        .ok_or_else(|| format!("{text} is not an address or a label"))
+}
+
+/// Find the `;@region` and `;@note` comments among the lexer's `comments`
+pub fn parse_annotations(
+    comments: &[TokenSpan],
+    labels: &HashMap<String, usize>,
+    line_to_address: &HashMap<usize, usize>,
+) -> Annotations {
+    let mut annotations = Annotations::default();
+    let last_line = line_to_address.keys().copied().max().unwrap_or(0);
+
+    for comment in comments {
+        let line = comment.line;
+        let Token::Comment(text) = &comment.token else {
+            continue;
+        };
+        let Some(annotation) = text.strip_prefix('@') else {
+            continue;
+        };
+        let (kind, rest) = annotation
+            .split_once(char::is_whitespace)
+            .unwrap_or((annotation, ""));
+
+        match kind.to_ascii_lowercase().as_str() {
+            "region" => {
+                let args = split_args(rest);
+                if args.len() < 3 {
+                    annotations.errors.push((
+                        comment.clone(),
+                        "expected ;@region NAME START END [color=RRGGBB]".to_owned(),
+                    ));
+                    continue;
+                }
+                let region = parse_address(&args[1], labels).and_then(|start| {
+                    let end = parse_address(&args[2], labels)?;
+                    if end < start {
+                        return Err(format!("region {} ends before it starts", args[0]));
+                    }
+                    let mut region = MemoryRegion::new(&args[0], start, end);
+                    if let Some(color) = args.get(3) {
+                        let hex = color.trim_start_matches("color=");
+                        region.color = Color32::from_hex(&format!("#{hex}"))
+                            .map_err(|_| format!("{color} is not a colour, use color=RRGGBB"))?;
+                    }
+                    Ok(region)
+                });
+                match region {
+                    Ok(region) => annotations.regions.push(region),
+                    Err(e) => annotations.errors.push((comment.clone(), e)),
+                }
+            }
+            "note" => {
+                let address = (line..=last_line).find_map(|line| line_to_address.get(&line));
+                match address {
+                    Some(&address) => {
+                        annotations.notes.insert(address, rest.trim().to_owned());
+                    }
+                    None => annotations
+                        .errors
+                        .push((comment.clone(), "note is not above any code".to_owned())),
+                }
+            }
+            _ => {} // just a comment that starts with @
+        }
+    }
+    annotations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{
+        parse::{CompilationArtifacts, Lexer},
+        Emulator,
+    };
+
+    fn comments(source: &str) -> Vec<TokenSpan> {
+        Lexer::new(source).tokenize_with_comments().unwrap().1
+    }
+
+    const SOURCE: &str = r#".ORIG x3000
+;@region "input buffer" BUFFER BUFFER_END color=ff8800
+;@region stack xFD00 xFDFF
+;@note where the program starts
+LEA R0, BUFFER
+HALT
+BUFFER .BLKW 4 ;@note filled by GETC
+BUFFER_END .FILL 0
+.END"#;
+
+    #[test]
+    fn regions_and_notes_from_comments() {
+        let mut artifacts = CompilationArtifacts::default();
+        Emulator::parse_program(SOURCE, Some(&mut artifacts)).unwrap();
+
+        assert_eq!(
+            artifacts.regions,
+            vec![
+                MemoryRegion {
+                    name: "input buffer".to_owned(),
+                    start: 0x3002,
+                    end: 0x3006,
+                    color: Color32::from_rgb(0xff, 0x88, 0x00),
+                },
+                MemoryRegion::new("stack", 0xFD00, 0xFDFF),
+            ]
+        );
+        assert_eq!(artifacts.notes[&0x3000], "where the program starts");
+        assert_eq!(artifacts.notes[&0x3002], "filled by GETC");
+    }
+
+    #[test]
+    fn bad_annotations_are_reported() {
+        let source = ".ORIG x3000\n;@region oops x3010 x3000\n;@region NOPE x3000\nHALT\n.END\n;@note nothing here";
+        let assembled = Emulator::parse_program(source, None).unwrap();
+        let annotations = parse_annotations(
+            &comments(source),
+            &assembled.labels,
+            &assembled.line_to_address,
+        );
+        assert!(annotations.regions.is_empty());
+        let lines: Vec<usize> = annotations
+            .errors
+            .iter()
+            .map(|(comment, _)| comment.line)
+            .collect();
+        assert_eq!(lines, vec![2, 3, 6]);
+    }
+
+    #[test]
+    fn annotation_round_trips() {
+        let region = MemoryRegion::new("my stack", 0x4000, 0x40FF);
+        let annotations = parse_annotations(
+            &comments(&region.to_annotation()),
+            &HashMap::new(),
+            &HashMap::new(),
+        );
+        assert_eq!(annotations.regions, vec![region]);
+    }
+
+    #[test]
+    fn annotations_inside_strings_are_ignored() {
+        let source = ".ORIG x3000\nHALT\nMSG .STRINGZ \"see ;@region oops x1 x0\"\n.END";
+        let mut artifacts = CompilationArtifacts::default();
+        Emulator::parse_program(source, Some(&mut artifacts)).unwrap();
+        assert!(artifacts.regions.is_empty());
+        assert!(artifacts.warnings.is_empty());
+    }
+}
@@ -0,0 +1,61 @@
+use std::collections::VecDeque;
+
+use crate::emulator::{Emulator, PSR_ADDR};
+
+/// Keep this many instructions worth, the oldest get dropped after that
+pub const MAX_SNAPSHOTS: usize = 10_000;
+
+/// What each slot in [`RegisterSnapshot::values`] is
+pub const REGISTER_NAMES: [&str; 10] =
+    ["R0", "R1", "R2", "R3", "R4", "R5", "R6", "R7", "PC", "PSR"];
+
+/// The registers after some number of instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterSnapshot {
+    /// How many instructions had finished
+    pub instruction: u64,
+    /// R0-R7, PC then PSR
+    pub values: [u16; 10],
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RegisterHistory {
+    pub snapshots: VecDeque<RegisterSnapshot>,
+}
+
+impl RegisterHistory {
+    pub fn push(&mut self, snapshot: RegisterSnapshot) {
+        if self.snapshots.len() >= MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// (instruction, value) for the first snapshot and each time register `index` changed after that
+    pub fn series(&self, index: usize) -> Vec<(u64, u16)> {
+        let mut points: Vec<(u64, u16)> = Vec::new();
+        for snapshot in &self.snapshots {
+            let value = snapshot.values[index];
+            if points.last().map_or(true, |&(_, last)| last != value) {
+                points.push((snapshot.instruction, value));
+            }
+        }
+        points
+    }
+}
+
+impl Emulator {
+    /// Called at the start of each fetch, so it sees what the last instruction left behind
+    pub(super) fn record_registers(&mut self) {
+        let mut values = [0; 10];
+        for (value, register) in values.iter_mut().zip(&self.r) {
+            *value = register.get();
+        }
+        values[8] = self.pc.get();
+        values[9] = self.memory[PSR_ADDR].get();
+        self.register_history.push(RegisterSnapshot {
+            instruction: self.instruction_count,
+            values,
+        });
+    }
+}
@@ -0,0 +1,34 @@
+/// SplitMix64. Tiny, seedable and plenty random enough to fill memory with junk.
+/// We only need it to be deterministic for a given seed so a failing run can be reproduced.
+#[derive(Debug, Clone)]
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// One LC3 word
+    pub fn next_u16(&mut self) -> u16 {
+        (self.next_u64() >> 48) as u16
+    }
+}
+
+/// A new seed taken from the clock. Good enough for picking a seed, it gets shown to the user anyway.
+/// Kept to 32 bits so it is easy to read out and type back in.
+pub fn fresh_seed() -> u64 {
+    let nanos = web_time::SystemTime::now()
+        .duration_since(web_time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0);
+    // Mix it so seeds taken close together don't look alike
+    SplitMix64::new(nanos).next_u64() >> 32
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+
+use crate::emulator::ops::{jsr::JsrMode, OpCode};
+use crate::emulator::{Emulator, EmulatorCell, PrivilegeLevel};
+
+/// Stop tracking calls past this depth, runaway recursion shouldn't eat all the memory
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+/// A JSR/JSRR we saw that hasn't returned yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CallFrame {
+    /// Address of the JSR
+    pub call_site: u16,
+    /// The subroutine it went to
+    pub target: u16,
+    /// Where RET will go (what was put in R7)
+    pub return_address: u16,
+    /// R6 at the time of the call, everything the subroutine pushes is below this
+    pub stack_pointer: u16,
+    /// Which stack it was on
+    pub user: bool,
+}
+
+/// Subroutine calls that are in progress, innermost last
+#[derive(Debug, Clone, Default)]
+pub struct CallStack {
+    pub frames: Vec<CallFrame>,
+}
+
+impl Emulator {
+    /// Keep the call stack up to date, called when an instruction is fetched (before it runs)
+    pub(super) fn track_call(&mut self, opcode: &OpCode) {
+        let pc = self.currently_executing as u16;
+        let next = pc.wrapping_add(self.isa.word_size() as u16);
+        match opcode {
+            OpCode::Jsr(op) => {
+                if self.call_stack.frames.len() >= MAX_CALL_DEPTH {
+                    return;
+                }
+                let target = match &op.mode {
+                    JsrMode::Relative { pc_offset } => next.wrapping_add(pc_offset.get()),
+                    JsrMode::Register { base_r } => self.r[base_r.get() as usize & 0b111].get(),
+                };
+                self.call_stack.frames.push(CallFrame {
+                    call_site: pc,
+                    target,
+                    return_address: next,
+                    stack_pointer: self.r[6].get(),
+                    user: matches!(self.priv_level(), PrivilegeLevel::User),
+                });
+            }
+            // RET, go back to the frame it returns to. Anything in between didn't return properly
+            OpCode::Jmp(op) if op.base_r.get() == 7 => {
+                let r7 = self.r[7].get();
+                if let Some(i) = self
+                    .call_stack
+                    .frames
+                    .iter()
+                    .rposition(|frame| frame.return_address == r7)
+                {
+                    self.call_stack.frames.truncate(i);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A frame found by following saved R5s, the way lcc lays them out:
+///
+/// ```norust
+/// R5+4..  arguments (pushed by the caller, first one lowest)
+/// R5+3    return value
+/// R5+2    saved R7 (return address)
+/// R5+1    saved R5 (the caller's frame pointer)
+/// R5..    locals, going down
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LccFrame {
+    pub frame_pointer: u16,
+    pub saved_frame_pointer: u16,
+    pub return_address: u16,
+}
+
+/// What an lcc stack slot is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Slot {
+    Local(u16),
+    SavedFramePointer,
+    ReturnAddress,
+    ReturnValue,
+    Argument(u16),
+}
+
+/// Follow the chain of frame pointers starting at `r5` for as long as it keeps going up the stack.
+/// Innermost frame first. `top` is the highest address the stack can use
+pub fn lcc_frames(memory: &[EmulatorCell], r5: u16, sp: u16, top: u16) -> Vec<LccFrame> {
+    let mut frames = Vec::new();
+    let mut frame_pointer = r5;
+    // R5 sits one below R6 right after the callee pushes it
+    while frame_pointer >= sp.saturating_sub(1)
+        && frame_pointer.checked_add(2).is_some_and(|end| end <= top)
+        && frames.len() < MAX_CALL_DEPTH
+    {
+        let saved_frame_pointer = memory[frame_pointer as usize + 1].get();
+        frames.push(LccFrame {
+            frame_pointer,
+            saved_frame_pointer,
+            return_address: memory[frame_pointer as usize + 2].get(),
+        });
+        if saved_frame_pointer <= frame_pointer {
+            break;
+        }
+        frame_pointer = saved_frame_pointer;
+    }
+    frames
+}
+
+/// What each address in the frames holds, with the index of its frame.
+/// Arguments belong to the frame that was called, the caller only gets the locals left over
+pub fn lcc_slots(frames: &[LccFrame], sp: u16) -> BTreeMap<u16, (usize, Slot)> {
+    let mut slots = BTreeMap::new();
+    let mut bottom = sp;
+    for (i, frame) in frames.iter().enumerate() {
+        let fp = frame.frame_pointer;
+        for address in bottom..=fp {
+            slots
+                .entry(address)
+                .or_insert((i, Slot::Local(fp - address)));
+        }
+        slots.insert(fp.wrapping_add(1), (i, Slot::SavedFramePointer));
+        slots.insert(fp.wrapping_add(2), (i, Slot::ReturnAddress));
+        slots.insert(fp.wrapping_add(3), (i, Slot::ReturnValue));
+        // we can't tell the caller's temporaries apart from arguments, so everything up to its frame counts
+        let arguments_end = frames
+            .get(i + 1)
+            .map_or(fp.wrapping_add(4), |caller| caller.frame_pointer);
+        for (n, address) in (fp.wrapping_add(4)..arguments_end).enumerate() {
+            slots.insert(address, (i, Slot::Argument(n as u16)));
+        }
+        bottom = arguments_end.max(fp.wrapping_add(4));
+    }
+    slots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_saved_frame_pointers() {
+        let mut memory = vec![EmulatorCell::new(0); 0x10000];
+        // main's frame is at xFDF0 and it called f, whose frame is at xFDE8
+        memory[0xFDF1].set(0xFDFF); // main's saved R5, points above the stack so we stop
+        memory[0xFDF2].set(0x3010);
+        memory[0xFDE9].set(0xFDF0); // f's saved R5
+        memory[0xFDEA].set(0x3020);
+        let frames = lcc_frames(&memory, 0xFDE8, 0xFDE6, 0xFDF8);
+        assert_eq!(
+            frames,
+            vec![
+                LccFrame {
+                    frame_pointer: 0xFDE8,
+                    saved_frame_pointer: 0xFDF0,
+                    return_address: 0x3020,
+                },
+                LccFrame {
+                    frame_pointer: 0xFDF0,
+                    saved_frame_pointer: 0xFDFF,
+                    return_address: 0x3010,
+                },
+            ]
+        );
+
+        let slots = lcc_slots(&frames, 0xFDE6);
+        assert_eq!(slots[&0xFDE6], (0, Slot::Local(2)));
+        assert_eq!(slots[&0xFDE9], (0, Slot::SavedFramePointer));
+        assert_eq!(slots[&0xFDEA], (0, Slot::ReturnAddress));
+        assert_eq!(slots[&0xFDEB], (0, Slot::ReturnValue));
+        assert_eq!(slots[&0xFDEC], (0, Slot::Argument(0)));
+        assert_eq!(slots[&0xFDEF], (0, Slot::Argument(3)));
+        assert_eq!(slots[&0xFDF0], (1, Slot::Local(0)));
+        assert_eq!(slots[&0xFDF2], (1, Slot::ReturnAddress));
+    }
+}
@@ -1,6 +1,15 @@
 use tracing_test::traced_test;
 
-use crate::emulator::{parse::ParseOutput, BitAddressable, Emulator, EmulatorCell};
+use crate::emulator::{
+    access_log::{AccessKind, MemoryAccess},
+    cache::CacheConfig,
+    init_tracker::{UninitLocation, UninitRead},
+    native_traps::NativeTraps,
+    ops::custom::{self, CustomInstruction},
+    parse::ParseOutput,
+    stack::CallFrame,
+    BitAddressable, CpuState, Emulator, EmulatorCell, Exception, Isa, PrivilegeLevel, PSR_ADDR,
+};
 
 #[traced_test]
 #[test]
@@ -396,6 +405,37 @@ fn run_instruction_test(
     assert_fn(&machine);
 }
 
+/// Assemble `program` for `isa` and load it into a fresh machine, then let `configure` set it up
+fn load(isa: Isa, program: &str, configure: impl FnOnce(&mut Emulator)) -> Emulator {
+    let mut machine = Emulator::with_isa(isa, None);
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program_for(isa, program, Some(&mut machine.metadata)).unwrap();
+    machine.flash_memory(machine_code, orig_address);
+    configure(&mut machine);
+    machine
+}
+
+/// [`load`] a program and run it until it stops
+fn load_and_run(isa: Isa, program: &str, configure: impl FnOnce(&mut Emulator)) -> Emulator {
+    let mut machine = load(isa, program, configure);
+    assert!(machine.run(Some(100_000)).is_ok());
+    machine
+}
+
+/// Press run and let the UI loop tick until the machine stops (breakpoint, HALT or an error)
+fn run_until_stopped(machine_state: &mut Emulator) {
+    machine_state.start_running();
+    for _ in 0..10000 {
+        machine_state.update();
+        if !machine_state.running() {
+            break;
+        }
+    }
+}
+
 #[traced_test]
 #[test]
 fn test_add_register() {
@@ -715,3 +755,1108 @@ fn test_str() {
         },
     );
 }
+
+// Randomised machine state
+
+#[traced_test]
+#[test]
+fn test_seeded_state_is_reproducible() {
+    let a = Emulator::new_seeded(Some(1234));
+    let b = Emulator::new_seeded(Some(1234));
+    let c = Emulator::new_seeded(Some(4321));
+
+    for i in 0..8 {
+        assert_eq!(
+            a.r[i].get(),
+            b.r[i].get(),
+            "R{i} should match for the same seed"
+        );
+    }
+    assert!(
+        (0x3000..=0xFDFF).all(|addr| a.memory[addr].get() == b.memory[addr].get()),
+        "User memory should match for the same seed"
+    );
+    assert!(
+        (0x3000..=0xFDFF).any(|addr| a.memory[addr].get() != c.memory[addr].get()),
+        "Different seeds should give different memory"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_seeded_state_leaves_os_alone() {
+    let zeroed = Emulator::new();
+    let seeded = Emulator::new_seeded(Some(42));
+
+    assert!(
+        (0x0000..0x3000).all(|addr| zeroed.memory[addr].get() == seeded.memory[addr].get()),
+        "System space should not be randomised"
+    );
+    assert!(
+        (0xFE00..=0xFFFF).all(|addr| zeroed.memory[addr].get() == seeded.memory[addr].get()),
+        "Device registers should not be randomised"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_seeded_state_runs_program() {
+    let mut machine_state = Emulator::new_seeded(Some(7));
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(include_str!("../../asm_tests/c-println.asm"), None).unwrap();
+    machine_state.flash_memory(machine_code, orig_address);
+
+    assert!(machine_state.run(Some(10000)).is_ok());
+    assert!(!machine_state.running(), "Machine should have halted");
+    assert!(!machine_state.output.is_empty());
+
+    // soft reset keeps the seed and gives the same registers back
+    let reset = machine_state.soft_reset();
+    let fresh = Emulator::new_seeded(Some(7));
+    for i in 0..8 {
+        assert_eq!(reset.r[i].get(), fresh.r[i].get());
+    }
+}
+
+// Uninitialised read detection
+
+fn run_tracked(program: &str, break_on_read: bool) -> Emulator {
+    load_and_run(Isa::Lc3, program, |machine| {
+        machine.init_tracker.break_on_read = break_on_read
+    })
+}
+
+#[traced_test]
+#[test]
+fn test_uninit_register_reported() {
+    let machine_state = run_tracked(
+        ".ORIG x3000
+        ADD R3, R3, #1 ; forgot to clear R3
+        ADD R3, R3, #1
+        HALT
+        .END",
+        false,
+    );
+
+    assert_eq!(
+        machine_state.init_tracker.reads,
+        vec![UninitRead {
+            location: UninitLocation::Register(3),
+            instruction_address: 0x3000,
+            line: Some(2),
+        }],
+        "Only the first use of R3 should be reported"
+    );
+    assert!(
+        machine_state.halted,
+        "Reporting should not stop the program"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_uninit_not_reported_for_clear_and_save() {
+    let machine_state = run_tracked(
+        ".ORIG x3000
+        AND R3, R3, #0
+        ADD R3, R3, #1
+        ST R1, SAVE1 ; saving a register we never set is fine
+        LD R1, SAVE1
+        LD R2, DATA
+        ADD R2, R2, R3
+        HALT
+        SAVE1 .BLKW 1
+        DATA .FILL #5
+        .END",
+        false,
+    );
+
+    assert!(
+        machine_state.init_tracker.reads.is_empty(),
+        "Unexpected reports: {:?}",
+        machine_state.init_tracker.reads
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_uninit_value_through_memory() {
+    let machine_state = run_tracked(
+        ".ORIG x3000
+        ST R4, TEMP ; junk goes out
+        LD R5, TEMP ; and comes back
+        ADD R5, R5, #1
+        HALT
+        TEMP .BLKW 1
+        .END",
+        false,
+    );
+
+    assert_eq!(machine_state.init_tracker.reads.len(), 1);
+    assert_eq!(
+        machine_state.init_tracker.reads[0].location,
+        UninitLocation::Register(5)
+    );
+    assert_eq!(machine_state.init_tracker.reads[0].line, Some(4));
+}
+
+#[traced_test]
+#[test]
+fn test_uninit_break_on_read() {
+    let machine_state = run_tracked(
+        ".ORIG x3000
+        AND R0, R0, #0
+        ADD R0, R0, R2
+        HALT
+        .END",
+        true,
+    );
+
+    assert_eq!(machine_state.init_tracker.reads.len(), 1);
+    assert!(!machine_state.running(), "Should have stopped on the read");
+    assert!(!machine_state.halted, "Should have stopped before HALT");
+    assert_eq!(machine_state.currently_executing, 0x3001);
+}
+
+// Keyboard input queue
+
+#[traced_test]
+#[test]
+fn test_input_queue_feeds_getc() {
+    let mut machine_state = Emulator::new();
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(
+        ".ORIG x3000
+        GETC
+        OUT
+        GETC
+        OUT
+        GETC
+        OUT
+        HALT
+        .END",
+        None,
+    )
+    .unwrap();
+    machine_state.flash_memory(machine_code, orig_address);
+
+    // all typed before the program asks for any of it
+    assert!(machine_state.queue_input("ab\r\n").is_ok());
+    assert_eq!(
+        machine_state.input_queue.len(),
+        2,
+        "One char goes straight into KBDR, \\r is dropped"
+    );
+
+    assert!(machine_state.run(Some(10000)).is_ok());
+    assert!(machine_state.halted);
+    assert!(
+        machine_state.output.contains("ab\n"),
+        "Every queued char should be read in order, got {:?}",
+        machine_state.output
+    );
+    assert!(machine_state.input_queue.is_empty());
+}
+
+#[traced_test]
+#[test]
+fn test_input_queue_rejects_non_ascii() {
+    let mut machine_state = Emulator::new();
+
+    let result = machine_state.queue_input("héllo");
+    assert!(result.is_err(), "Non ASCII input should be rejected");
+    assert!(result.unwrap_err().contains("at char 2"));
+    assert!(
+        machine_state.input_queue.is_empty(),
+        "Nothing should be queued"
+    );
+    assert_eq!(
+        machine_state.memory[crate::emulator::KBSR_ADDR].get(),
+        0,
+        "No key should be ready"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_resume_from_breakpoint() {
+    let mut machine_state = Emulator::new();
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(
+        ".ORIG x3000
+        ADD R1, R1, #1
+        ADD R1, R1, #1
+        HALT
+        .END",
+        None,
+    )
+    .unwrap();
+    machine_state.flash_memory(machine_code, orig_address);
+    machine_state.breakpoints.insert(0x3001);
+    machine_state.ticks_between_updates = 1;
+    machine_state.speed = 100;
+
+    run_until_stopped(&mut machine_state);
+    assert_eq!(
+        machine_state.pc.get(),
+        0x3001,
+        "Should stop on the breakpoint"
+    );
+    assert_eq!(machine_state.r[1].get(), 1);
+
+    // pressing run again should carry on past it rather than stopping straight away
+    run_until_stopped(&mut machine_state);
+    assert!(machine_state.halted, "Should run to the HALT");
+    assert_eq!(machine_state.r[1].get(), 2);
+
+    let reset = machine_state.soft_reset();
+    assert!(
+        reset.breakpoints.contains(&0x3001),
+        "Breakpoints should survive a reset"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_memory_accesses_logged() {
+    let machine_state = run_tracked(
+        ".ORIG x3000
+        LD R1, COUNT
+        ADD R1, R1, #1
+        ST R1, COUNT
+        HALT
+        COUNT .FILL #5
+        .END",
+        false,
+    );
+
+    let accesses: Vec<MemoryAccess> = machine_state
+        .access_log
+        .for_address(0x3004)
+        .copied()
+        .collect();
+    assert_eq!(
+        accesses,
+        vec![
+            MemoryAccess {
+                instruction: accesses[0].instruction,
+                pc: 0x3000,
+                address: 0x3004,
+                old: 5,
+                new: 5,
+                kind: AccessKind::Read,
+                cache: None,
+            },
+            MemoryAccess {
+                instruction: accesses[0].instruction + 2,
+                pc: 0x3002,
+                address: 0x3004,
+                old: 5,
+                new: 6,
+                kind: AccessKind::Write,
+                cache: None,
+            },
+        ],
+        "Fetching the instructions should not show up, only the LD and ST"
+    );
+    assert_eq!(
+        machine_state.access_log.values_over_time(0x3004),
+        vec![(accesses[0].instruction, 5), (accesses[1].instruction, 6)]
+    );
+
+    let reset = machine_state.soft_reset();
+    assert!(reset.access_log.accesses.is_empty());
+    assert_eq!(reset.instruction_count, 0);
+}
+
+#[traced_test]
+#[test]
+fn test_register_history() {
+    let machine_state = run_tracked(
+        ".ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #3
+        ADD R1, R1, #-1
+        HALT
+        .END",
+        false,
+    );
+
+    let history = &machine_state.register_history;
+    let start = history
+        .snapshots
+        .iter()
+        .find(|snapshot| snapshot.values[8] == 0x3000)
+        .expect("Should have a snapshot before the program starts")
+        .instruction;
+    let r1: Vec<(u64, u16)> = history
+        .series(1)
+        .into_iter()
+        .filter(|&(instruction, _)| instruction > start)
+        .take(2)
+        .collect();
+    // R1 is already 0 so the AND doesn't show up as a change
+    assert_eq!(
+        r1,
+        vec![(start + 2, 3), (start + 3, 2)],
+        "R1 should change after each ADD"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_call_stack_tracking() {
+    let mut machine_state = Emulator::new();
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(
+        ".ORIG x3000
+        LD R6, STACK
+        JSR SUB
+        HALT
+        SUB ADD R6, R6, #-1
+        STR R7, R6, #0
+        LDR R7, R6, #0
+        ADD R6, R6, #1
+        RET
+        STACK .FILL xFD00
+        .END",
+        None,
+    )
+    .unwrap();
+    machine_state.flash_memory(machine_code, orig_address);
+    machine_state.breakpoints.insert(0x3005);
+    machine_state.ticks_between_updates = 1;
+    machine_state.speed = 100;
+
+    let user_calls = |machine_state: &Emulator| -> Vec<CallFrame> {
+        machine_state
+            .call_stack
+            .frames
+            .iter()
+            .filter(|frame| frame.call_site >= 0x3000)
+            .copied()
+            .collect()
+    };
+
+    run_until_stopped(&mut machine_state);
+    assert_eq!(machine_state.pc.get(), 0x3005);
+    let calls = user_calls(&machine_state);
+    assert_eq!(calls.len(), 1, "Should be inside SUB: {calls:?}");
+    assert_eq!(calls[0].call_site, 0x3001);
+    assert_eq!(calls[0].target, 0x3003);
+    assert_eq!(calls[0].return_address, 0x3002);
+    assert_eq!(calls[0].stack_pointer, 0xFD00);
+
+    run_until_stopped(&mut machine_state);
+    assert!(machine_state.halted);
+    assert!(
+        user_calls(&machine_state).is_empty(),
+        "RET should have ended the frame"
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_custom_instruction() {
+    // the loaded instruction is global, tests that want 1101 illegal wait for this one
+    let _custom = custom::test_lock();
+    custom::set_loaded(Some(
+        CustomInstruction::from_ron(include_str!("../../assets/custom_ops/mul.ron")).unwrap(),
+    ));
+    let mut machine_state = Emulator::new();
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(
+        ".ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #6
+        ADD R2, R1, #-13
+        mul R3, R1, R2
+        HALT
+        .END",
+        None,
+    )
+    .unwrap();
+    assert_eq!(machine_code[3], 0b1101_011_001_000_010);
+    machine_state.flash_memory(machine_code.clone(), orig_address);
+    machine_state.pc.set(0x3000);
+    for _ in 0..4 {
+        machine_state.step();
+    }
+    assert_eq!(machine_state.r[3].get() as i16, -42);
+    assert_eq!(machine_state.memory[PSR_ADDR].get() & 0b111, 0b100);
+
+    // unloading it part way through a MUL makes the rest of it illegal
+    let mut unloaded = Emulator::new();
+    unloaded.flash_memory(machine_code, orig_address);
+    unloaded.pc.set(0x3003);
+    unloaded.start_running();
+    while !matches!(unloaded.cpu_state, CpuState::Decode) {
+        unloaded.micro_step().unwrap();
+    }
+    custom::set_loaded(None);
+    let mut result = Ok(());
+    for _ in 0..100 {
+        result = unloaded.step_micro_op();
+        if result.is_err() || !matches!(unloaded.cpu_state, CpuState::Decode) {
+            break;
+        }
+    }
+    assert!(result.is_err(), "Should fail leaving Decode, not panic");
+    assert!(matches!(
+        unloaded.exception,
+        Some(Exception::IllegalInstruction)
+    ));
+
+    // without it 1101 is illegal again, in the assembler and the machine
+    assert!(Emulator::parse_program(".ORIG x3000\nMUL R3, R1, R2\n.END", None).is_err());
+    machine_state.pc.set(0x3003);
+    machine_state.start_running();
+    assert!(machine_state.micro_step().is_err());
+    assert!(matches!(
+        machine_state.exception,
+        Some(Exception::IllegalInstruction)
+    ));
+}
+
+// The microsequencer against the phase executor
+
+/// Run a program to the end on the phase executor and then on the microsequencer
+fn run_on_both(program: &str, input: &str) -> (Emulator, Emulator) {
+    let run = |microcoded| {
+        let machine_state = load_and_run(Isa::Lc3, program, |machine| {
+            machine.use_microsequencer(microcoded);
+            machine.queue_input(input).unwrap();
+        });
+        assert!(machine_state.halted, "Should have halted");
+        machine_state
+    };
+    (run(false), run(true))
+}
+
+fn assert_same_machine(phases: &Emulator, microcoded: &Emulator) {
+    assert_eq!(phases.output, microcoded.output);
+    for (i, (a, b)) in phases.r.iter().zip(&microcoded.r).enumerate() {
+        assert_eq!(a.get(), b.get(), "R{i} differs");
+    }
+    assert_eq!(phases.pc.get(), microcoded.pc.get(), "PC differs");
+    assert_eq!(phases.saved_ssp.get(), microcoded.saved_ssp.get());
+    assert_eq!(phases.saved_usp.get(), microcoded.saved_usp.get());
+    if let Some(addr) = (0..phases.memory.len())
+        .find(|&addr| phases.memory[addr].get() != microcoded.memory[addr].get())
+    {
+        panic!(
+            "Memory differs at x{addr:04X}: x{:04X} on the phase executor, x{:04X} microcoded",
+            phases.memory[addr].get(),
+            microcoded.memory[addr].get()
+        );
+    }
+}
+
+#[traced_test]
+#[test]
+fn test_microcode_matches_executor() {
+    let (phases, microcoded) = run_on_both(
+        ".ORIG x3000
+        LEA R0, HELLO
+        PUTS
+        AND R1, R1, #0
+        ADD R1, R1, #5
+        AND R2, R2, #0
+LOOP    ADD R2, R2, R1
+        ADD R1, R1, #-1
+        BRp LOOP
+        ST R2, SUM
+        LDI R3, SUM_PTR
+        NOT R3, R3
+        STI R3, SUM_PTR
+        LEA R4, TABLE
+        LDR R5, R4, #1
+        STR R5, R4, #2
+        AND R5, R5, #12
+        JSR DOUBLE
+        LEA R4, DOUBLE
+        JSRR R4
+        BRnz SKIP
+        ADD R5, R5, #1
+SKIP    GETC
+        OUT
+        LD R0, BANG
+        OUT
+        HALT
+DOUBLE  ADD R5, R5, R5
+        RET
+HELLO   .STRINGZ \"hi \"
+BANG    .FILL x21
+SUM     .FILL #0
+SUM_PTR .FILL SUM
+TABLE   .FILL #1
+        .FILL #-7
+        .FILL #0
+        .END",
+        "x",
+    );
+    assert!(phases.output.contains("hi x!"), "{:?}", phases.output);
+    assert_same_machine(&phases, &microcoded);
+    assert_eq!(microcoded.r[3].get() as i16, !15);
+    assert_eq!(microcoded.r[5].get(), 33);
+}
+
+#[traced_test]
+#[test]
+fn test_microcode_matches_executor_on_exceptions() {
+    // RTI in user mode
+    let (phases, microcoded) = run_on_both(
+        ".ORIG x3000
+        ADD R0, R0, #1
+        RTI
+        .END",
+        "",
+    );
+    assert!(phases.output.contains("`RTI` in user mode"));
+    assert_same_machine(&phases, &microcoded);
+
+    // reading OS memory from user mode
+    let (phases, microcoded) = run_on_both(
+        ".ORIG x3000
+        LDI R0, OS
+        HALT
+OS      .FILL x0200
+        .END",
+        "",
+    );
+    assert!(phases.output.contains("out of usermode permissions"));
+    assert_same_machine(&phases, &microcoded);
+}
+
+#[traced_test]
+#[test]
+fn test_microcode_tracks_uninitialised_reads() {
+    let (phases, microcoded) = run_on_both(
+        ".ORIG x3000
+        ADD R3, R3, #1 ; R3 was never set
+        ST R4, TEMP ; junk goes out
+        LD R5, TEMP ; and comes back
+        ADD R5, R5, #1
+        LEA R1, TEMP
+        LDR R0, R1, #0
+        STR R2, R1, #0 ; storing junk is fine
+        JSR CLEAR
+        HALT
+CLEAR   AND R2, R2, #0
+        NOT R2, R2
+        RET
+TEMP    .BLKW 1
+        .END",
+        "",
+    );
+    let reads = &microcoded.init_tracker.reads;
+    assert_eq!(
+        reads[..2]
+            .iter()
+            .map(|read| (read.location, read.instruction_address))
+            .collect::<Vec<_>>(),
+        [
+            (UninitLocation::Register(3), 0x3000),
+            (UninitLocation::Register(5), 0x3003)
+        ]
+    );
+    assert_eq!(&phases.init_tracker.reads, reads);
+}
+
+#[traced_test]
+#[test]
+fn test_microcode_states() {
+    let _custom = custom::test_lock();
+    custom::set_loaded(None);
+    let mut machine_state = Emulator::new();
+    machine_state.use_microsequencer(true);
+    machine_state.memory[0x3000].set(0x1261); // ADD R1, R1, #1
+    machine_state.memory[0x3001].set(0b1101_000_000_000_000); // nothing loaded for 1101
+    machine_state.pc.set(0x3000);
+    machine_state.set_priv_level(PrivilegeLevel::User);
+    machine_state.saved_ssp.set(0x3000);
+    machine_state.start_running();
+
+    let mut states = Vec::new();
+    while states.len() < 9 {
+        machine_state.micro_step().unwrap();
+        let cycle = machine_state
+            .microsequencer
+            .as_ref()
+            .and_then(|sequencer| sequencer.last.clone())
+            .unwrap();
+        states.push(cycle.state);
+    }
+    assert_eq!(states, [18, 33, 35, 32, 1, 18, 33, 35, 32]);
+    assert_eq!(machine_state.r[1].get(), 1);
+
+    // the illegal opcode goes through the exception states to the OS handler
+    while !matches!(machine_state.cpu_state, CpuState::Fetch) {
+        machine_state.micro_step().unwrap();
+        states.push(
+            machine_state
+                .microsequencer
+                .as_ref()
+                .unwrap()
+                .last
+                .as_ref()
+                .unwrap()
+                .state,
+        );
+    }
+    assert_eq!(&states[9..], [13, 45, 37, 41, 43, 47, 48, 50, 52, 54]);
+    assert_eq!(machine_state.pc.get(), machine_state.memory[0x0101].get());
+    assert_eq!(machine_state.r[6].get(), 0x2FFE);
+    assert_eq!(machine_state.memory[0x2FFE].get(), 0x3002);
+    assert!(matches!(
+        machine_state.priv_level(),
+        PrivilegeLevel::Supervisor
+    ));
+}
+
+/// Boot an LC-3b, load `program` and run it until it halts
+fn run_lc3b(program: &str) -> Emulator {
+    load_and_run(Isa::Lc3b, program, |_| {})
+}
+
+#[traced_test]
+#[test]
+fn test_lc3b_assembler() {
+    let assembled = Emulator::parse_program_for(
+        Isa::Lc3b,
+        ".ORIG x3000
+        LOOP ADD R1, R1, #-1
+        BRp LOOP
+        JSR LOOP
+        LEA R0, MSG
+        LSHF R1, R2, #3
+        RSHFL R1, R2, #3
+        RSHFA R1, R2, #3
+        XOR R1, R2, R3
+        NOT R1, R2
+        LDB R1, R2, #-1
+        STW R1, R2, #1
+        MSG .STRINGZ \"abc\"
+        .END",
+        None,
+    )
+    .unwrap();
+    // words are 2 bytes apart and offsets count words from PC + 2
+    assert_eq!(assembled.labels["MSG"], 0x3016);
+    assert_eq!(
+        assembled.machine_code,
+        [
+            0x127F, 0x03FE, 0x4FFD, 0xE007, 0xD283, 0xD293, 0xD2B3, 0x9283, 0x92BF, 0x22BF, 0x7281,
+            0x6261, 0x0063
+        ]
+    );
+
+    // LC-3 only instructions are errors rather than labels
+    assert!(
+        Emulator::parse_program_for(Isa::Lc3b, ".ORIG x3000\nLDR R1, R2, #0\n.END", None).is_err()
+    );
+    // and the LC-3b ones are still free to be labels on the LC-3
+    assert!(Emulator::parse_program(".ORIG x3000\nXOR ADD R0, R0, #1\nBR XOR\n.END", None).is_ok());
+}
+
+#[traced_test]
+#[test]
+fn test_lc3b_program() {
+    let machine = run_lc3b(
+        ".ORIG x3000
+        LEA R0, BYTES
+        LDB R1, R0, #0      ; x81 sign extends
+        LDB R2, R0, #1      ; x7F
+        LDW R3, R0, #0
+        LEA R4, SPACE
+        STB R1, R4, #1      ; only the high byte of SPACE
+        STW R3, R4, #1      ; the word after
+        LDW R5, R4, #0
+        RSHFA R5, R5, #4
+        XOR R2, R2, #-1
+        LEA R0, HELLO
+        PUTS
+        HALT
+BYTES   .FILL x7F81
+SPACE   .BLKW 2
+HELLO   .STRINGZ \"hi\"
+        .END",
+    );
+    assert!(!machine.running());
+    assert_eq!(machine.r[1].get(), 0xFF81);
+    assert_eq!(machine.r[2].get(), 0xFF80);
+    assert_eq!(machine.r[3].get(), 0x7F81);
+    assert_eq!(machine.r[5].get(), 0xF810);
+    let space = machine.metadata.labels["SPACE"];
+    assert_eq!(machine.memory[space].get(), 0x8100);
+    assert_eq!(machine.memory[space + 2].get(), 0x7F81);
+    assert!(machine.output.starts_with("hi"), "got {:?}", machine.output);
+    assert!(machine.output.contains("HALT"));
+}
+
+#[traced_test]
+#[test]
+fn test_lc3b_unaligned_word() {
+    let machine = run_lc3b(
+        ".ORIG x3000
+        LEA R0, DATA
+        LDW R2, R0, #0
+        ADD R0, R0, #1
+        LDW R3, R0, #0
+        HALT
+DATA    .FILL x1234
+        .END",
+    );
+    assert_eq!(machine.r[2].get(), 0x1234);
+    assert_eq!(machine.r[3].get(), 0);
+    assert!(
+        machine.output.contains("Unaligned word access"),
+        "got {:?}",
+        machine.output
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_pipeline_times_every_instruction() {
+    let mut machine = Emulator::new();
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(
+        ".ORIG x3000
+        AND R1, R1, #0
+        ADD R1, R1, #5
+LOOP    ADD R1, R1, #-1
+        BRp LOOP
+        HALT
+        .END",
+        None,
+    )
+    .unwrap();
+    machine.flash_memory(machine_code, orig_address);
+    machine.use_pipeline(true);
+    machine.run(Some(10_000)).unwrap();
+
+    let pipeline = machine.pipeline.as_ref().unwrap();
+    assert_eq!(pipeline.stats.instructions, machine.instruction_count);
+    // BRp goes back 4 times, then there's the boot JMP, the TRAP and RET
+    assert!(pipeline.stats.flushed >= 2 * 4);
+    assert!(pipeline.stats.cpi() > 1.0);
+
+    // the pipeline comes back empty after a reset
+    let reset = machine.soft_reset();
+    let pipeline = reset.pipeline.as_ref().unwrap();
+    assert_eq!(pipeline.stats.instructions, 0);
+    assert!(pipeline.forwarding);
+}
+
+#[traced_test]
+#[test]
+fn test_cache_sees_loads_and_stores() {
+    let mut machine = Emulator::new();
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program(
+        ".ORIG x3000
+        LEA R0, ARRAY
+        AND R1, R1, #0
+        ADD R2, R1, #8
+LOOP    LDR R3, R0, #0
+        ADD R1, R1, R3
+        ADD R0, R0, #1
+        ADD R2, R2, #-1
+        BRp LOOP
+        ST R1, SUM
+        HALT
+SUM     .BLKW 2
+ARRAY   .FILL 1
+        .FILL 2
+        .FILL 3
+        .FILL 4
+        .FILL 5
+        .FILL 6
+        .FILL 7
+        .FILL 8
+        .END",
+        None,
+    )
+    .unwrap();
+    machine.flash_memory(machine_code, orig_address);
+    let config = CacheConfig {
+        sets: 4,
+        ways: 1,
+        block_size: 4,
+        ..Default::default()
+    };
+    machine.use_cache(Some(config)).unwrap();
+    machine.run(Some(10_000)).unwrap();
+    assert_eq!(machine.r[1].get(), 36);
+
+    let user: Vec<_> = machine
+        .access_log
+        .accesses
+        .iter()
+        .filter(|access| access.pc >= 0x3000)
+        .collect();
+    // the LDR walking the array, one miss per block of 4
+    let reads: Vec<bool> = user
+        .iter()
+        .filter(|access| access.pc == 0x3003)
+        .map(|access| access.cache.unwrap().hit)
+        .collect();
+    assert_eq!(
+        reads,
+        [false, true, true, true, false, true, true, true],
+        "{user:?}"
+    );
+    // nothing had touched SUM's block, write allocate still brings it in
+    let store = user
+        .iter()
+        .find(|access| access.kind == AccessKind::Write)
+        .unwrap();
+    let result = store.cache.unwrap();
+    assert!(!result.hit);
+    assert_eq!(result.way, Some(0));
+    assert!(machine.cache.as_ref().unwrap().stats.write_misses >= 1);
+
+    // device registers are never cached
+    assert!(machine
+        .access_log
+        .accesses
+        .iter()
+        .filter(|access| access.address as usize > crate::emulator::USER_SPACE_END)
+        .all(|access| access.cache.is_none()));
+
+    assert!(machine
+        .use_cache(Some(CacheConfig { sets: 3, ..config }))
+        .is_err());
+}
+
+#[traced_test]
+#[test]
+fn test_hard_reset_keeps_settings() {
+    let mut machine = Emulator::new();
+    machine.use_cache(Some(CacheConfig::default())).unwrap();
+    machine.use_pipeline(true);
+    machine.use_microsequencer(true);
+    machine.memory[0x3000].set(0x1234);
+
+    let reset = machine.hard_reset(Isa::Lc3);
+    assert_eq!(reset.memory[0x3000].get(), 0, "Memory should be cleared");
+    assert_eq!(
+        reset.cache.as_ref().map(|cache| cache.config),
+        Some(CacheConfig::default())
+    );
+    assert!(reset.pipeline.is_some());
+    assert!(reset.microsequencer.is_some());
+
+    // the microcode is only written for the LC-3
+    let lc3b = machine.hard_reset(Isa::Lc3b);
+    assert!(lc3b.cache.is_some() && lc3b.pipeline.is_some());
+    assert!(lc3b.microsequencer.is_none());
+}
+
+/// Cycles each of the first `count` instructions at x3000 take, with memory taking `latency` cycles
+fn instruction_cycles(program: &str, latency: u32, microcoded: bool, count: usize) -> Vec<u64> {
+    let mut machine = load(Isa::Lc3, program, |machine| {
+        machine.pc.set(0x3000);
+        machine.use_microsequencer(microcoded);
+        machine.timing.memory_latency = latency;
+    });
+    (0..count)
+        .map(|_| {
+            machine.step();
+            // the fetch of the next one finishes the count
+            machine.timing.instruction_so_far()
+        })
+        .collect()
+}
+
+#[traced_test]
+#[test]
+fn test_memory_latency_adds_wait_cycles() {
+    let program = ".ORIG x3000
+        ADD R1, R1, #1
+        LD R2, DATA
+        ST R2, DATA
+        HALT
+DATA    .FILL #7
+        .END";
+    // a phase is a cycle, each access waits two more for R
+    assert_eq!(instruction_cycles(program, 1, false, 3), [6, 6, 6]);
+    assert_eq!(instruction_cycles(program, 3, false, 3), [8, 10, 10]);
+    // the microcode's own states: 18 33 35 32 1, then 18 33 35 32 2 25 27, then 18 33 35 32 3 23 16
+    assert_eq!(instruction_cycles(program, 1, true, 3), [5, 7, 7]);
+    assert_eq!(instruction_cycles(program, 3, true, 3), [7, 11, 11]);
+
+    let mut machine = load_and_run(Isa::Lc3, program, |machine| {
+        machine.timing.memory_latency = 4;
+    });
+    // it only got slower
+    assert_eq!(machine.r[2].get(), 7);
+    assert!(machine.output.contains("HALT"));
+    assert!(machine.timing.memory_wait_cycles >= 3 * machine.instruction_count);
+    assert_eq!(machine.soft_reset().timing.memory_latency, 4);
+}
+
+fn counters_after(program: &str, microcoded: bool) -> Emulator {
+    load_and_run(Isa::Lc3, program, |machine| {
+        machine.use_microsequencer(microcoded)
+    })
+}
+
+#[traced_test]
+#[test]
+fn test_perf_counters() {
+    let program = ".ORIG x3000
+        LD R0, CHAR
+        OUT
+        ADD R1, R1, #1
+        ADD R1, R1, #1
+        ST R1, CHAR
+        HALT
+CHAR    .FILL x41
+        .END";
+    let machine = counters_after(program, false);
+    let stats = machine.stats();
+    assert_eq!(stats.instructions, machine.instruction_count);
+    // the OS's instructions count too
+    assert!(stats.opcodes["ADD"] >= 2);
+    assert!(stats.opcodes["ST"] >= 1);
+    assert_eq!(stats.opcodes["TRAP"], stats.traps);
+    assert!(stats.traps >= 2);
+    assert_eq!(stats.exceptions, 0);
+    // OS into the program, OUT and back
+    assert!(stats.privilege_switches >= 3);
+    assert!(stats.memory_reads > 0 && stats.memory_writes > 0);
+    assert!(machine.cpi() > 1.0);
+    assert_eq!(
+        stats.opcodes_by_count()[0].1,
+        stats.opcodes.values().max().copied().unwrap()
+    );
+
+    // the microcode sees the same program, just in different cycles
+    let microcoded = counters_after(program, true);
+    let micro_stats = microcoded.stats();
+    assert_eq!(micro_stats.opcodes, stats.opcodes);
+    // the phase executor's TRAP reads the vector table twice
+    assert!(micro_stats.memory_reads > 0 && micro_stats.memory_reads < stats.memory_reads);
+    assert_eq!(micro_stats.memory_writes, stats.memory_writes);
+    assert_eq!(micro_stats.privilege_switches, stats.privilege_switches);
+
+    let mut machine = machine;
+    machine.reset_counters();
+    assert_eq!(machine.stats().instructions, 0);
+    let reset = microcoded.soft_reset();
+    assert_eq!(reset.stats().instructions, 0);
+    assert_eq!(reset.timing.cycles, 0);
+
+    // a privileged RTI from user mode, the PSR and PC pushes are writes on both
+    let machine = counters_after(".ORIG x3000\nRTI\n.END", false);
+    let microcoded = counters_after(".ORIG x3000\nRTI\n.END", true);
+    assert_eq!(machine.stats().exceptions, 1);
+    assert_eq!(microcoded.stats().exceptions, 1);
+    assert_eq!(
+        machine.stats().memory_writes,
+        microcoded.stats().memory_writes
+    );
+}
+
+fn run_with_traps(isa: Isa, program: &str, native: NativeTraps, microcoded: bool) -> Emulator {
+    load_and_run(isa, program, |machine| {
+        machine.native_traps = native;
+        machine.use_microsequencer(microcoded);
+        machine.queue_input("ab").unwrap();
+    })
+}
+
+#[traced_test]
+#[test]
+fn test_native_traps_match_the_os() {
+    let program = ".ORIG x3000
+        LEA R0, MSG
+        PUTS
+        GETC
+        OUT
+        IN
+        ADD R1, R0, #1
+        HALT
+MSG     .STRINGZ \"hi \"
+        .END";
+    for (isa, microcoded) in [(Isa::Lc3, false), (Isa::Lc3, true), (Isa::Lc3b, false)] {
+        let os = run_with_traps(isa, program, NativeTraps::default(), microcoded);
+        let native = run_with_traps(isa, program, NativeTraps::all(), microcoded);
+        assert!(os.halted && native.halted, "{isa:?}");
+        assert_eq!(native.output, os.output, "{isa:?}");
+        assert!(native.output.contains("hi a\nInput a character> b\n"));
+        assert_eq!(native.r[1].get(), b'c' as u16);
+        if isa == Isa::Lc3 {
+            // the OS's HALT clobbers R0 and never gets back to the user stack
+            let registers =
+                |machine: &Emulator| machine.r[1..6].iter().map(|r| r.get()).collect::<Vec<_>>();
+            assert_eq!(registers(&native), registers(&os), "{microcoded}");
+            // and no going into supervisor mode for them
+            assert!(native.stats().privilege_switches < os.stats().privilege_switches);
+        }
+        // none of the OS's instructions ran
+        assert!(native.stats().instructions * 5 < os.stats().instructions);
+        // but PUTS still read "hi " and its null, and GETC and IN read the keyboard
+        assert!(native.stats().memory_reads >= 8, "{isa:?} {microcoded}");
+    }
+
+    // only the ones picked skip the OS
+    let mut some = NativeTraps::default();
+    some.enabled[2] = true;
+    let machine = run_with_traps(Isa::Lc3, program, some, false);
+    assert_eq!(
+        machine.output,
+        run_with_traps(Isa::Lc3, program, NativeTraps::default(), false).output
+    );
+}
+
+#[traced_test]
+#[test]
+fn test_native_getc_waits_for_a_key() {
+    let program = ".ORIG x3000
+        GETC
+        LD R0, TEXT
+        PUTSP
+        HALT
+TEXT    .FILL x3010
+        .END";
+    let mut machine = load(Isa::Lc3, program, |machine| {
+        machine.native_traps = NativeTraps::all();
+    });
+    machine.run(Some(500)).unwrap();
+    // it went round the GETC until it gave up
+    assert!(!machine.halted);
+    assert_eq!(machine.pc.get(), 0x3000);
+
+    // PUTSP packs two chars a word, low byte first
+    machine.memory[0x3010].set(u16::from_le_bytes(*b"ok"));
+    machine.memory[0x3011].set(b'!' as u16);
+    machine.memory[0x3012].set(0);
+    machine.set_in_char('\u{10}');
+    machine.run(Some(10)).unwrap();
+    assert!(machine.halted);
+    assert!(machine.output.contains("ok!"));
+}
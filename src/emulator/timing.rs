@@ -0,0 +1,69 @@
+use crate::emulator::{CpuState, Emulator};
+
+/// Most cycles a memory access can be set to take
+pub const MAX_MEMORY_LATENCY: u32 = 100;
+
+/// Clock cycle counts, and how long memory takes to set R
+#[derive(Debug, Clone)]
+pub struct Timing {
+    /// Cycles a memory access takes, R goes to 1 on the last one. 1 answers straight away
+    pub memory_latency: u32,
+    /// Cycles since reset or the counters were cleared. A phase (or a microinstruction) is one, and so is each cycle spent waiting on R
+    pub cycles: u64,
+    /// How many of `cycles` were spent waiting on R
+    pub memory_wait_cycles: u64,
+    /// Cycles the last finished instruction took
+    pub last_instruction: u64,
+    /// `cycles` when the current instruction was fetched
+    pub(super) instruction_start: u64,
+    /// Cycles the access in progress has waited, none when nothing is waiting
+    pub waiting: Option<u32>,
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self {
+            memory_latency: 1,
+            cycles: 0,
+            memory_wait_cycles: 0,
+            last_instruction: 0,
+            instruction_start: 0,
+            waiting: None,
+        }
+    }
+}
+
+impl Timing {
+    /// Cycles into the current instruction
+    pub fn instruction_so_far(&self) -> u64 {
+        self.cycles - self.instruction_start
+    }
+}
+
+impl Emulator {
+    /// Between instructions, not part way through one (which could be waiting on the fetch's read)
+    pub fn between_instructions(&self) -> bool {
+        matches!(self.cpu_state, CpuState::Fetch) && self.timing.waiting.is_none()
+    }
+
+    /// Called when an instruction is fetched, the cycles since the last fetch were the last instruction's
+    pub(super) fn start_instruction_timing(&mut self) {
+        // the cycle doing the fetch is already counted, it's the new instruction's
+        let now = self.timing.cycles.saturating_sub(1);
+        self.timing.last_instruction = now - self.timing.instruction_start;
+        self.timing.instruction_start = now;
+    }
+
+    /// A cycle with memory enabled, is R set yet? Counts the wait if not
+    pub(super) fn memory_ready(&mut self) -> bool {
+        let waited = self.timing.waiting.unwrap_or(0);
+        if waited + 1 >= self.timing.memory_latency {
+            self.timing.waiting = None;
+            true
+        } else {
+            self.timing.waiting = Some(waited + 1);
+            self.timing.memory_wait_cycles += 1;
+            false
+        }
+    }
+}
@@ -0,0 +1,200 @@
+//! Run a program without the GUI. Meant for graders and scripts:
+//!
+//! ```norust
+//! tools_for_210 --headless program.asm [--seed N | --random-seed] [--max-steps N] [--input FILE] [--custom-op FILE.ron] [--isa lc3|lc3b] [--native-traps all|GETC,PUTS,..] [--stats]
+//! ```
+//!
+//! The program output goes to stdout, everything else (seed, uninitialised reads, the `--stats` counters) goes to stderr.
+
+use std::path::PathBuf;
+
+use crate::emulator::{
+    native_traps::NativeTraps, ops::custom, parse::ParseOutput, rng, Emulator, Isa,
+};
+
+/// Everything the headless runner needs to know, parsed from the command line
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct HeadlessOptions {
+    pub program_path: PathBuf,
+    /// Seed for the randomised machine state, `None` means zeroed memory and registers
+    pub seed: Option<u64>,
+    /// Give up after this many instructions (so infinite loops don't hang the grader)
+    pub max_steps: Option<usize>,
+    /// File whose contents get typed into the keyboard as the program asks for it
+    pub input_path: Option<PathBuf>,
+    /// RON definition of the instruction on opcode 1101
+    pub custom_op_path: Option<PathBuf>,
+    /// Which machine to assemble for and run on
+    pub isa: Isa,
+    /// Print the performance counters when it stops
+    pub stats: bool,
+    /// TRAPs to run in Rust instead of the OS, much faster for programs that print a lot
+    pub native_traps: NativeTraps,
+}
+
+pub const USAGE: &str =
+    "usage: tools_for_210 --headless <program.asm> [--seed N | --random-seed] [--max-steps N] [--input FILE] [--custom-op FILE.ron] [--isa lc3|lc3b] [--native-traps all|GETC,PUTS,..] [--stats]";
+
+/// Numbers can be given as decimal or as hex with an x/0x prefix (like the seed shown in the app)
+fn parse_number(s: &str) -> Result<u64, String> {
+    let lower = s.to_ascii_lowercase();
+    let parsed = match lower.strip_prefix("0x").or_else(|| lower.strip_prefix('x')) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => lower.parse::<u64>(),
+    };
+    parsed.map_err(|e| format!("invalid number '{s}': {e}"))
+}
+
+impl HeadlessOptions {
+    /// Parse the arguments that come after `--headless`
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut options = HeadlessOptions::default();
+        let mut program_path = None;
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--seed" => {
+                    let value = args.next().ok_or("--seed needs a value")?;
+                    options.seed = Some(parse_number(&value)?);
+                }
+                "--random-seed" => options.seed = Some(rng::fresh_seed()),
+                "--max-steps" => {
+                    let value = args.next().ok_or("--max-steps needs a value")?;
+                    options.max_steps = Some(parse_number(&value)? as usize);
+                }
+                "--input" => {
+                    let value = args.next().ok_or("--input needs a file")?;
+                    options.input_path = Some(PathBuf::from(value));
+                }
+                "--custom-op" => {
+                    let value = args.next().ok_or("--custom-op needs a file")?;
+                    options.custom_op_path = Some(PathBuf::from(value));
+                }
+                "--isa" => {
+                    let value = args.next().ok_or("--isa needs lc3 or lc3b")?;
+                    options.isa = match value.to_ascii_lowercase().replace('-', "").as_str() {
+                        "lc3" => Isa::Lc3,
+                        "lc3b" => Isa::Lc3b,
+                        _ => return Err(format!("unknown isa '{value}', expected lc3 or lc3b")),
+                    };
+                }
+                "--stats" => options.stats = true,
+                "--native-traps" => {
+                    let value = args
+                        .next()
+                        .ok_or("--native-traps needs 'all' or a list of traps")?;
+                    options.native_traps = NativeTraps::parse(&value)?;
+                }
+                flag if flag.starts_with("--") => return Err(format!("unknown option '{flag}'")),
+                path => {
+                    if program_path.is_some() {
+                        return Err(format!("unexpected argument '{path}'"));
+                    }
+                    program_path = Some(PathBuf::from(path));
+                }
+            }
+        }
+
+        options.program_path = program_path.ok_or("no program given")?;
+        Ok(options)
+    }
+}
+
+/// Assemble and run the program to completion (or `max_steps`).
+/// Returns the final machine and whether it halted on its own.
+pub fn run(options: &HeadlessOptions) -> Result<(Emulator, bool), String> {
+    let source = std::fs::read_to_string(&options.program_path)
+        .map_err(|e| format!("could not read {}: {e}", options.program_path.display()))?;
+
+    if let Some(path) = &options.custom_op_path {
+        let definition = std::fs::read_to_string(path)
+            .map_err(|e| format!("could not read {}: {e}", path.display()))?;
+        let instruction = custom::CustomInstruction::from_ron(&definition)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        custom::set_loaded(Some(instruction));
+    }
+
+    let mut emulator = Emulator::with_isa(options.isa, options.seed);
+    emulator.native_traps = options.native_traps;
+    if let Some(seed) = options.seed {
+        eprintln!("Seed: {seed}");
+    }
+
+    let ParseOutput {
+        machine_code,
+        orig_address,
+        ..
+    } = Emulator::parse_program_for(options.isa, &source, Some(&mut emulator.metadata))
+        .map_err(|e| format!("assembly failed: {e:?}"))?;
+    emulator.flash_memory(machine_code, orig_address);
+
+    if let Some(input_path) = &options.input_path {
+        let input = std::fs::read_to_string(input_path)
+            .map_err(|e| format!("could not read {}: {e}", input_path.display()))?;
+        emulator.queue_input(&input)?;
+    }
+
+    // Not using `Emulator::run` as it can't tell us if we ran out of steps or halted
+    emulator.start_running();
+    let mut steps = 0;
+    while emulator.running() {
+        if options.max_steps.is_some_and(|max| steps >= max) {
+            emulator.stop_running();
+            return Ok((emulator, false));
+        }
+        emulator.step();
+        steps += 1;
+    }
+    Ok((emulator, true))
+}
+
+/// Entry point used by `main` when given `--headless`. Returns the process exit code.
+pub fn main(args: impl IntoIterator<Item = String>) -> i32 {
+    let options = match HeadlessOptions::from_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n{USAGE}");
+            return 2;
+        }
+    };
+
+    match run(&options) {
+        Ok((emulator, halted)) => {
+            print!("{}", emulator.output);
+            for read in &emulator.init_tracker.reads {
+                let source = read.line.and_then(|line| {
+                    emulator
+                        .metadata
+                        .last_compiled_source
+                        .get(line.checked_sub(1)?)
+                });
+                match source {
+                    Some(source) => eprintln!("warning: {read}: {}", source.trim()),
+                    None => eprintln!("warning: {read}"),
+                }
+            }
+            if options.stats {
+                eprintln!(
+                    "{} cycles ({:.2} CPI)",
+                    emulator.timing.cycles,
+                    emulator.cpi()
+                );
+                eprintln!("{}", emulator.stats());
+            }
+            if halted {
+                0
+            } else {
+                eprintln!(
+                    "Program did not halt within {} steps",
+                    options.max_steps.unwrap_or_default()
+                );
+                1
+            }
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
@@ -3,6 +3,8 @@
 
 pub mod app;
 pub mod emulator;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod headless;
 pub mod panes;
 pub mod theme;
 pub mod turing;
@@ -19,6 +19,12 @@ fn main() -> eframe::Result {
         platform::pump_events::{EventLoopExtPumpEvents, PumpStatus},
     };
 
+    // Headless mode skips the gui (and the logging, so stdout is just the program output)
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("--headless") {
+        std::process::exit(tools_for_210::headless::main(args.skip(1)));
+    }
+
     let fmt_layer = tracing_subscriber::fmt::layer().with_writer(std::io::stdout); // write events to the terminal
 
     tracing_subscriber::registry()
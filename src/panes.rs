@@ -73,3 +73,43 @@ impl PaneDisplay for Pane {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_old_saved_panes_still_load() {
+        // What the editor and controls saved before tabs, the cache and the pipeline existed
+        let saved = r#"[
+            (alone: false, inner: EmulatorPanes(Editor((
+                program: ".ORIG x3000\nHALT\n.END",
+                fade: 0.0,
+                last_compilation_was_successful: true,
+            ))), id: 0),
+            (alone: false, inner: EmulatorPanes(Controls((
+                speed: 45,
+                randomize_state: false,
+                seed: 0,
+                break_on_uninit_read: true,
+            ))), id: 1),
+        ]"#;
+
+        let panes: Vec<Pane> = ron::from_str(saved).expect("Old layout should still load");
+        let editor = ron::to_string(&panes[0]).unwrap();
+        assert!(
+            editor.contains("last_compilation_was_successful:true"),
+            "got {editor}"
+        );
+        assert!(
+            editor.contains("untitled.asm"),
+            "New fields get their defaults, got {editor}"
+        );
+        let controls = ron::to_string(&panes[1]).unwrap();
+        assert!(controls.contains("speed:45"), "got {controls}");
+        assert!(
+            controls.contains("break_on_uninit_read:true"),
+            "got {controls}"
+        );
+    }
+}
@@ -1,9 +1,20 @@
+pub mod cache;
 pub mod controls;
+pub mod counters;
 pub mod cpu_state;
+pub mod custom_op;
+pub mod datapath;
 pub mod editor;
+pub mod fsm;
 pub mod help;
 pub mod io;
 pub mod memory;
+pub mod microcode;
+pub mod pipeline;
+pub mod plot;
+pub mod stack;
+pub mod terminal;
+pub mod timeline;
 
 use crate::emulator::Emulator;
 use crate::theme::ThemeSettings;
@@ -13,11 +24,20 @@ use super::PaneTree;
 use memory::MemoryPane;
 use serde::{Deserialize, Serialize};
 
+pub use cache::CachePane;
 pub use controls::ControlsPane;
+pub use counters::CountersPane;
 pub use cpu_state::CpuStatePane;
+pub use custom_op::CustomOpPane;
+pub use datapath::DatapathPane;
 pub use editor::EditorPane;
+pub use fsm::FsmPane;
 pub use help::HelpPane;
 pub use io::IoPane;
+pub use microcode::MicrocodePane;
+pub use pipeline::PipelinePane;
+pub use stack::StackPane;
+pub use timeline::TimelinePane;
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum EmulatorPane {
@@ -27,6 +47,15 @@ pub enum EmulatorPane {
     Controls(ControlsPane),
     Cpu(CpuStatePane),
     Memory(MemoryPane),
+    Timeline(TimelinePane),
+    Stack(StackPane),
+    Datapath(DatapathPane),
+    CustomOp(CustomOpPane),
+    Fsm(FsmPane),
+    Microcode(MicrocodePane),
+    Pipeline(PipelinePane),
+    Cache(CachePane),
+    Counters(CountersPane),
 }
 
 impl PaneDisplay for EmulatorPane {
@@ -38,6 +67,15 @@ impl PaneDisplay for EmulatorPane {
             EmulatorPane::Controls(pane) => pane.title(),
             EmulatorPane::Cpu(pane) => pane.title(),
             EmulatorPane::Memory(pane) => pane.title(),
+            EmulatorPane::Timeline(pane) => pane.title(),
+            EmulatorPane::Stack(pane) => pane.title(),
+            EmulatorPane::Datapath(pane) => pane.title(),
+            EmulatorPane::CustomOp(pane) => pane.title(),
+            EmulatorPane::Fsm(pane) => pane.title(),
+            EmulatorPane::Microcode(pane) => pane.title(),
+            EmulatorPane::Pipeline(pane) => pane.title(),
+            EmulatorPane::Cache(pane) => pane.title(),
+            EmulatorPane::Counters(pane) => pane.title(),
         }
     }
 
@@ -49,6 +87,15 @@ impl PaneDisplay for EmulatorPane {
             EmulatorPane::Cpu(pane) => pane.render(ui, emulator, theme),
             EmulatorPane::Memory(pane) => pane.render(ui, emulator, theme),
             EmulatorPane::Controls(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Timeline(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Stack(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Datapath(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::CustomOp(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Fsm(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Microcode(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Pipeline(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Cache(pane) => pane.render(ui, emulator, theme),
+            EmulatorPane::Counters(pane) => pane.render(ui, emulator, theme),
         }
     }
 
@@ -57,8 +104,17 @@ impl PaneDisplay for EmulatorPane {
             "Emulator".to_owned(),
             vec![
                 MemoryPane::children(),
+                TimelinePane::children(),
+                StackPane::children(),
                 EditorPane::children(),
                 CpuStatePane::children(),
+                DatapathPane::children(),
+                FsmPane::children(),
+                MicrocodePane::children(),
+                PipelinePane::children(),
+                CachePane::children(),
+                CountersPane::children(),
+                CustomOpPane::children(),
                 IoPane::children(),
                 HelpPane::children(),
                 ControlsPane::children(),
@@ -0,0 +1,198 @@
+use crate::emulator::cache::{CacheConfig, Replacement, WritePolicy};
+use crate::emulator::Emulator;
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+
+use super::EmulatorPane;
+
+/// Powers of two to pick the cache's dimensions from
+const SIZES: [usize; 9] = [1, 2, 4, 8, 16, 32, 64, 128, 256];
+
+/// The cache model: its shape, its counters and what is in each set
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CachePane {
+    /// What the next 'Apply' builds
+    config: CacheConfig,
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+fn size_picker(ui: &mut egui::Ui, name: &str, value: &mut usize) {
+    egui::ComboBox::from_label(name)
+        .selected_text(value.to_string())
+        .show_ui(ui, |ui| {
+            for size in SIZES {
+                ui.selectable_value(value, size, size.to_string());
+            }
+        });
+}
+
+impl CachePane {
+    fn settings(&mut self, ui: &mut egui::Ui) {
+        let config = &mut self.config;
+        ui.horizontal_wrapped(|ui| {
+            size_picker(ui, "sets", &mut config.sets);
+            size_picker(ui, "ways", &mut config.ways);
+            size_picker(ui, "block size", &mut config.block_size);
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.selectable_value(
+                &mut config.write_policy,
+                WritePolicy::WriteBack,
+                "Write back",
+            );
+            ui.selectable_value(
+                &mut config.write_policy,
+                WritePolicy::WriteThrough,
+                "Write through",
+            );
+            ui.checkbox(&mut config.write_allocate, "Allocate on write miss");
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Replace:");
+            ui.selectable_value(&mut config.replacement, Replacement::Lru, "LRU");
+            ui.selectable_value(&mut config.replacement, Replacement::Fifo, "FIFO");
+            ui.selectable_value(&mut config.replacement, Replacement::Random, "Random");
+            ui.checkbox(&mut config.instructions, "Cache instruction fetches");
+        });
+    }
+}
+
+impl PaneDisplay for CachePane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        self.settings(ui);
+        ui.horizontal(|ui| {
+            let label = if emulator.cache.is_some() {
+                "Apply (empties it)"
+            } else {
+                "Add the cache"
+            };
+            if ui.button(label).clicked() {
+                self.error = emulator.use_cache(Some(self.config)).err();
+            }
+            if emulator.cache.is_some() && ui.button("Remove").clicked() {
+                let _ = emulator.use_cache(None);
+            }
+            ui.label(
+                RichText::new(format!(
+                    "{} addresses",
+                    self.config.sets * self.config.ways * self.config.block_size
+                ))
+                .weak(),
+            );
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(theme.error_fg_color, error);
+        }
+        ui.separator();
+
+        let Some(cache) = &emulator.cache else {
+            ui.label(
+                RichText::new("No cache, loads and stores go straight to memory.")
+                    .color(theme.secondary_text_color),
+            );
+            return;
+        };
+
+        let stats = cache.stats;
+        egui::Grid::new("cache_stats").striped(true).show(ui, |ui| {
+            for title in ["", "Hits", "Misses"] {
+                ui.label(RichText::new(title).strong());
+            }
+            ui.end_row();
+            ui.label("Reads");
+            ui.label(stats.read_hits.to_string());
+            ui.label(stats.read_misses.to_string());
+            ui.end_row();
+            ui.label("Writes");
+            ui.label(stats.write_hits.to_string());
+            ui.label(stats.write_misses.to_string());
+            ui.end_row();
+        });
+        ui.label(format!(
+            "Hit rate {:.1}% of {} accesses. {} write backs, {} writes straight to memory",
+            stats.hit_rate() * 100.0,
+            stats.accesses(),
+            stats.writebacks,
+            stats.memory_writes
+        ));
+        if let Some((address, result)) = cache.last {
+            let (text, color) = if result.hit {
+                ("hit", theme.accent_color_positive)
+            } else {
+                ("missed", theme.accent_color_negative)
+            };
+            ui.colored_label(
+                color,
+                format!("Last: x{address:04X} {text} in set {}", result.set),
+            );
+        }
+        ui.separator();
+
+        let labels = &emulator.metadata.addr_to_label;
+        egui::ScrollArea::both().show(ui, |ui| {
+            egui::Grid::new("cache_sets")
+                .striped(true)
+                .min_col_width(90.0)
+                .show(ui, |ui| {
+                    ui.label(RichText::new("Set").strong());
+                    for way in 0..cache.config.ways {
+                        ui.label(RichText::new(format!("Way {way}")).strong());
+                    }
+                    ui.end_row();
+                    for (set, lines) in cache.lines.iter().enumerate() {
+                        ui.label(RichText::new(set.to_string()).monospace());
+                        for (way, line) in lines.iter().enumerate() {
+                            if !line.valid {
+                                ui.label(RichText::new("empty").weak());
+                                continue;
+                            }
+                            let start = cache.block_address(set, line.tag);
+                            let mut text = RichText::new(format!(
+                                "x{start:04X}{}",
+                                if line.dirty { " D" } else { "" }
+                            ))
+                            .monospace();
+                            if cache
+                                .last
+                                .is_some_and(|(_, last)| last.set == set && last.way == Some(way))
+                            {
+                                text = text.strong().color(theme.cpu_state_active_color);
+                            }
+                            let end = start as usize + cache.config.block_size - 1;
+                            let mut hover =
+                                format!("Tag x{:X}, holds x{start:04X}-x{end:04X}", line.tag);
+                            if let Some(label) =
+                                (start as usize..=end).find_map(|address| labels.get(&address))
+                            {
+                                hover.push_str(&format!(" ({label})"));
+                            }
+                            ui.label(text).on_hover_text(hover);
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+        ui.label(
+            RichText::new("Blocks are shown by their first address, D is dirty. The cache only decides hits and misses, memory always has the values.")
+                .small()
+                .weak(),
+        );
+    }
+
+    fn title(&self) -> String {
+        "Cache".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Cache".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Cache(
+                CachePane::default(),
+            )))),
+        )
+    }
+}
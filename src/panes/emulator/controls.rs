@@ -1,16 +1,33 @@
-use crate::emulator::{Emulator, MAX_OS_STEPS};
+use crate::emulator::{
+    native_traps::{NativeTraps, NATIVE_TRAPS},
+    rng,
+    timing::MAX_MEMORY_LATENCY,
+    Emulator, Isa, MAX_OS_STEPS,
+};
 use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
 use crate::theme::ThemeSettings;
+use egui::RichText;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct ControlsPane {
     speed: u32,
+    /// Start from seeded junk instead of zeroes on reset
+    randomize_state: bool,
+    seed: u64,
+    /// Pause when a user program uses a register/memory location it never set
+    break_on_uninit_read: bool,
 }
 
 impl Default for ControlsPane {
     fn default() -> Self {
-        Self { speed: 30 }
+        Self {
+            speed: 30,
+            randomize_state: false,
+            seed: rng::fresh_seed(),
+            break_on_uninit_read: false,
+        }
     }
 }
 
@@ -39,6 +56,64 @@ impl PaneDisplay for ControlsPane {
             // Skip OS emulation checkbox
             ui.checkbox(&mut emulator.skip_os_emulation, "Skip OS Routines").on_hover_text("Automatically step through OS code (PC < 0x3000) when stepping.");
 
+            ui.horizontal(|ui| {
+                ui.label("Memory latency:");
+                ui.add(egui::DragValue::new(&mut emulator.timing.memory_latency).range(1..=MAX_MEMORY_LATENCY).suffix(" cycles"))
+                    .on_hover_text("How many clock cycles a memory access takes before memory sets R. Phases that read or write memory wait on it, and so do the microcode's [R] states.");
+            });
+
+            ui.horizontal_wrapped(|ui| {
+                ui.label("Native traps:").on_hover_text("Run these TRAPs in Rust instead of the OS's routines. Programs print the same but run much faster, and there is no OS code to step through.");
+                for (enabled, name) in emulator.native_traps.enabled.iter_mut().zip(NATIVE_TRAPS) {
+                    ui.checkbox(enabled, name);
+                }
+                if emulator.native_traps.any() {
+                    if ui.small_button("None").clicked() {
+                        emulator.native_traps = NativeTraps::default();
+                    }
+                } else if ui.small_button("All").clicked() {
+                    emulator.native_traps = NativeTraps::all();
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.randomize_state, "Randomise state on reset").on_hover_text("Fill user memory and R0-R7 with random values when resetting instead of zeroes (like lc3tools does). Catches programs that forget to initialise a register.");
+                if self.randomize_state {
+                    ui.label("Seed:");
+                    ui.add(egui::DragValue::new(&mut self.seed)).on_hover_text("The same seed always gives the same starting state. Pass it to the headless runner with --seed to reproduce a run.");
+                    if ui.button("🎲").on_hover_text("Pick a new seed").clicked() {
+                        self.seed = rng::fresh_seed();
+                    }
+                }
+            });
+            emulator.random_seed = self.randomize_state.then_some(self.seed);
+
+            ui.checkbox(&mut self.break_on_uninit_read, "Break on uninitialised read").on_hover_text("Pause when your program uses a register or memory location that was never written since load/reset (like forgetting to clear R3 before a loop).");
+            emulator.init_tracker.break_on_read = self.break_on_uninit_read;
+
+            if !emulator.init_tracker.reads.is_empty() {
+                egui::CollapsingHeader::new(
+                    RichText::new(format!("⚠ Uninitialised reads ({})", emulator.init_tracker.reads.len()))
+                        .color(theme.warn_fg_color),
+                )
+                .default_open(true)
+                .show(ui, |ui| {
+                    for read in &emulator.init_tracker.reads {
+                        ui.label(RichText::new(read.to_string()).color(theme.warn_fg_color));
+                        // show the offending line so you don't have to go looking
+                        if let Some(source) = read
+                            .line
+                            .and_then(|line| emulator.metadata.last_compiled_source.get(line.checked_sub(1)?))
+                        {
+                            ui.label(RichText::new(source.trim()).monospace());
+                        }
+                    }
+                    if ui.button("Clear").clicked() {
+                        emulator.init_tracker.reads.clear();
+                    }
+                });
+            }
+
             ui.separator();
 
             ui.horizontal_wrapped(|ui| {
@@ -53,8 +128,8 @@ impl PaneDisplay for ControlsPane {
                     }
                 } else {
 
-                        if emulator.pc.get() != 0x200 && emulator.halted
-                            && ui.add(egui::Button::new("▶ Reset & Run").fill(theme.accent_color_primary)).on_hover_text("Set PC=0x200 and reset all the other registers to 0. Memory stays intact. MCR[15] is set to 1 (run state).").clicked() {
+                        if emulator.pc.get() != emulator.isa.boot_address() && emulator.halted
+                            && ui.add(egui::Button::new("▶ Reset & Run").fill(theme.accent_color_primary)).on_hover_text("Set PC to the start of the OS and reset all the other registers to 0. Memory stays intact. MCR[15] is set to 1 (run state).").clicked() {
                                                     if emulator.halted {
                                                         emulator.halted = false;
                                                         *emulator = emulator.soft_reset();
@@ -135,18 +210,21 @@ impl PaneDisplay for ControlsPane {
                 .fill(theme.accent_color_negative)
                 .min_size(egui::vec2(ui.available_width() - theme.item_spacing.x * 2.0, 0.0)); // Full width button
 
-            if ui.add(reset_button).clicked() {
-                let current_skip_os = emulator.skip_os_emulation; // Preserve this setting
-                let current_speed = emulator.speed; // Preserve speed setting
-
-                *emulator = Emulator::new(); // Reset to default state
-                emulator.skip_os_emulation = current_skip_os; // Restore
-                emulator.speed = current_speed; // Restore
-
-
+            // Switching machine is a reset too, the OS and addressing are different
+            let mut isa = emulator.isa;
+            ui.horizontal(|ui| {
+                ui.label("Machine:");
+                for choice in [Isa::Lc3, Isa::Lc3b] {
+                    ui.selectable_value(&mut isa, choice, choice.to_string());
+                }
+            })
+            .response
+            .on_hover_text("The LC-3b is byte addressed, with LDB/STB, LDW/STW, SHF and XOR. Switching resets the machine, recompile your program after.");
 
+            if ui.add(reset_button).clicked() || isa != emulator.isa {
+                *emulator = emulator.hard_reset(isa);
             }
-            ui.small("Resets CPU, memory, and devices. Execution speed and Skip OS settings are preserved.");
+            ui.small("Resets CPU, memory, and devices. Execution speed, Skip OS, the microsequencer, pipeline, cache, memory latency, native traps and randomise settings are preserved.");
         });
     }
 
@@ -0,0 +1,151 @@
+use crate::emulator::Emulator;
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+use web_time::Instant;
+
+use super::EmulatorPane;
+
+/// Work the rate out over at least this long so it doesn't jump about every frame
+const RATE_WINDOW: f64 = 0.5;
+
+/// The performance counters, and how fast the emulator is actually going
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CountersPane {
+    /// When the rate was last worked out, and the instruction and cycle counts then
+    #[serde(skip)]
+    sample: Option<(Instant, u64, u64)>,
+    /// Instructions and cycles per second over the last window
+    #[serde(skip)]
+    rate: Option<(f64, f64)>,
+}
+
+impl CountersPane {
+    fn measure(&mut self, emulator: &Emulator) {
+        if !emulator.running() {
+            self.sample = None;
+            self.rate = None;
+            return;
+        }
+        let now = Instant::now();
+        let stats = emulator.stats();
+        let cycles_now = emulator.timing.cycles;
+        match self.sample {
+            // counters that went backwards were reset, start again
+            Some((at, instructions, cycles))
+                if stats.instructions >= instructions && cycles_now >= cycles =>
+            {
+                let elapsed = now.duration_since(at).as_secs_f64();
+                if elapsed >= RATE_WINDOW {
+                    self.rate = Some((
+                        (stats.instructions - instructions) as f64 / elapsed,
+                        (cycles_now - cycles) as f64 / elapsed,
+                    ));
+                    self.sample = Some((now, stats.instructions, cycles_now));
+                }
+            }
+            _ => self.sample = Some((now, stats.instructions, cycles_now)),
+        }
+    }
+}
+
+impl PaneDisplay for CountersPane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        self.measure(emulator);
+        ui.horizontal_wrapped(|ui| {
+            if ui
+                .button("Reset counters")
+                .on_hover_text("Count from zero again without resetting the machine")
+                .clicked()
+            {
+                emulator.reset_counters();
+                self.sample = None;
+            }
+            ui.label(
+                RichText::new(format!(
+                    "Speed: {} micro steps every {} frames",
+                    emulator.speed, emulator.ticks_between_updates
+                ))
+                .color(theme.secondary_text_color),
+            );
+        });
+        match self.rate {
+            Some((instructions, cycles)) => ui.label(format!(
+                "Running at {instructions:.0} instructions/s ({cycles:.0} cycles/s)"
+            )),
+            None if emulator.running() => ui.label("Measuring..."),
+            None => ui.label(
+                RichText::new("Run the program to see how many instructions a second it gets.")
+                    .color(theme.secondary_text_color),
+            ),
+        };
+        ui.separator();
+
+        let stats = emulator.stats();
+        egui::Grid::new("perf_counters")
+            .striped(true)
+            .num_columns(2)
+            .show(ui, |ui| {
+                for (name, value) in [
+                    ("Cycles", emulator.timing.cycles.to_string()),
+                    ("Instructions", stats.instructions.to_string()),
+                    ("CPI", format!("{:.2}", emulator.cpi())),
+                    ("Memory reads", stats.memory_reads.to_string()),
+                    ("Memory writes", stats.memory_writes.to_string()),
+                    ("Traps", stats.traps.to_string()),
+                    ("Exceptions", stats.exceptions.to_string()),
+                    ("Privilege switches", stats.privilege_switches.to_string()),
+                ] {
+                    ui.label(name);
+                    ui.label(RichText::new(value).monospace());
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+
+        ui.label(RichText::new("By opcode").strong());
+        if stats.instructions == 0 {
+            ui.label(RichText::new("Nothing run yet.").weak());
+            return;
+        }
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("perf_counters_opcodes")
+                .striped(true)
+                .num_columns(3)
+                .show(ui, |ui| {
+                    for (name, count) in stats.opcodes_by_count() {
+                        ui.label(RichText::new(name).monospace());
+                        ui.label(count.to_string());
+                        ui.label(
+                            RichText::new(format!(
+                                "{:.1}%",
+                                count as f64 * 100.0 / stats.instructions as f64
+                            ))
+                            .weak(),
+                        );
+                        ui.end_row();
+                    }
+                });
+        });
+        ui.label(
+            RichText::new("The OS's instructions count too. Memory reads and writes are loads, stores and the stack, not instruction fetches.")
+                .small()
+                .weak(),
+        );
+    }
+
+    fn title(&self) -> String {
+        "Counters".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Counters".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Counters(
+                CountersPane::default(),
+            )))),
+        )
+    }
+}
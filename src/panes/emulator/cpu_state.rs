@@ -1,4 +1,5 @@
 use crate::emulator::micro_op::EguiDisplay;
+use crate::emulator::register_history::REGISTER_NAMES;
 use crate::emulator::{CpuState, Emulator, EmulatorCell, MCR_ADDR, PSR_ADDR};
 use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
 use crate::theme::ThemeSettings;
@@ -6,12 +7,34 @@ use egui::{Response, RichText};
 use serde::{Deserialize, Serialize};
 use std::ops::RangeInclusive;
 
+use super::plot::{step_plot, Series};
 use super::EmulatorPane;
 
+/// Cells we highlight when they change, in this order
+const R0: usize = 0;
+const PC: usize = 8;
+const MDR: usize = 9;
+const MAR: usize = 10;
+const IR: usize = 11;
+const PSR: usize = 12;
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CpuStatePane {
     use_negative: bool,
     display_base: u32,
+    /// Which of [`REGISTER_NAMES`] are on the history graph
+    #[serde(default)]
+    plotted: [bool; 10],
+    /// Which registers changed in the last step (R0-R7, PC, MDR, MAR, IR, PSR)
+    #[serde(skip)]
+    changed: [bool; 13],
+    /// (instruction count, phase) the last time we looked, a new one means the machine has stepped
+    #[serde(skip)]
+    seen_step: Option<(u64, usize)>,
+    /// R0-R7, PC, MDR, MAR, IR and PSR the last time we looked
+    #[serde(skip)]
+    seen_values: [u16; 13],
 }
 
 impl Default for CpuStatePane {
@@ -19,18 +42,26 @@ impl Default for CpuStatePane {
         Self {
             use_negative: false,
             display_base: 16,
+            plotted: [false; 10],
+            changed: [false; 13],
+            seen_step: None,
+            seen_values: [0; 13],
         }
     }
 }
 
 impl PaneDisplay for CpuStatePane {
     fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        self.update_changed(emulator);
         egui::ScrollArea::vertical().show(ui, |ui| {
             egui::CollapsingHeader::new("Registers & devices")
                 .default_open(true)
                 .show(ui, |ui| {
-                    self.render_register_view(ui, emulator);
+                    self.render_register_view(ui, emulator, theme);
                 });
+            ui.collapsing("History", |ui| {
+                self.render_history_view(ui, emulator, theme);
+            });
             ui.collapsing("Processor Cycle", |ui| {
                 self.render_cycle_view(ui, emulator, theme);
             });
@@ -132,8 +163,110 @@ fn register_view(
     }
 }
 
+/// Outline a register that changed in the last step
+fn mark_changed(ui: &egui::Ui, response: &Response, changed: bool, theme: &ThemeSettings) {
+    if changed {
+        ui.painter().rect_stroke(
+            response.rect.expand(1.0),
+            2.0,
+            egui::Stroke::new(1.5, theme.accent_color_positive),
+            egui::StrokeKind::Outside,
+        );
+    }
+}
+
 impl CpuStatePane {
-    fn render_register_view(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator) {
+    /// Work out which registers the last step/micro step touched. The cells' changed flags are only
+    /// peeked, other panes want them too, and nothing clears them between steps so the values seen
+    /// last frame are kept to compare against
+    fn update_changed(&mut self, emulator: &Emulator) {
+        let step = (
+            emulator.instruction_count,
+            emulator.execute_state.current_phase,
+        );
+        let cells = emulator.r.iter().chain([
+            &emulator.pc,
+            &emulator.mdr,
+            &emulator.mar,
+            &emulator.ir,
+            &emulator.memory[PSR_ADDR],
+        ]);
+        let mut values = [0; 13];
+        let mut flags = [false; 13];
+        for ((value, flag), cell) in values.iter_mut().zip(flags.iter_mut()).zip(cells) {
+            *value = cell.get();
+            *flag = cell.changed_peek();
+        }
+        // editing a register here also changes it, only take changes when the machine moved
+        if self.seen_step.is_some_and(|seen| seen != step) {
+            for (i, changed) in self.changed.iter_mut().enumerate() {
+                *changed = flags[i] && values[i] != self.seen_values[i];
+            }
+        }
+        self.seen_step = Some(step);
+        self.seen_values = values;
+    }
+
+    fn render_history_view(
+        &mut self,
+        ui: &mut egui::Ui,
+        emulator: &Emulator,
+        theme: &ThemeSettings,
+    ) {
+        ui.horizontal_wrapped(|ui| {
+            for (plotted, name) in self.plotted.iter_mut().zip(REGISTER_NAMES) {
+                ui.toggle_value(plotted, name);
+            }
+        });
+
+        let history = &emulator.register_history;
+        let series: Vec<_> = (0..REGISTER_NAMES.len())
+            .filter(|&i| self.plotted[i])
+            .map(|i| (i, history.series(i)))
+            .collect();
+        if series.is_empty() {
+            ui.label(
+                RichText::new(
+                    "Pick registers to graph their values over the last few thousand instructions",
+                )
+                .weak(),
+            );
+            return;
+        }
+        let series: Vec<Series<'_>> = series
+            .iter()
+            .map(|(i, points)| Series {
+                name: REGISTER_NAMES[*i],
+                color: egui::ecolor::Hsva::new(
+                    *i as f32 / REGISTER_NAMES.len() as f32,
+                    0.7,
+                    0.9,
+                    1.0,
+                )
+                .into(),
+                points,
+            })
+            .collect();
+        let start = history
+            .snapshots
+            .front()
+            .map_or(0, |snapshot| snapshot.instruction);
+        step_plot(
+            ui,
+            &series,
+            start,
+            emulator.instruction_count,
+            self.use_negative,
+            theme,
+        );
+    }
+
+    fn render_register_view(
+        &mut self,
+        ui: &mut egui::Ui,
+        emulator: &mut Emulator,
+        theme: &ThemeSettings,
+    ) {
         ui.horizontal(|ui| {
             ui.checkbox(&mut self.use_negative, "Show <0 as negative")
                 .on_hover_text("Whether to display the 2s complement registers as being negative if bit 15 is set. EG FFFF vs -0001.");
@@ -152,21 +285,26 @@ impl CpuStatePane {
                     for col in 0..4 {
                         let register = row * 4 + col;
                         ui.label(format!("R{register}:"));
-                        register_view(ui, &mut emulator.r[register], self.use_negative, self.display_base);
+                        let response = register_view(ui, &mut emulator.r[register], self.use_negative, self.display_base);
+                        mark_changed(ui, &response, self.changed[R0 + register], theme);
                     }
                     ui.end_row();
                 }
                 ui.label("PC:").on_hover_text("This register holds the next instruction that will be fetched in the fetch phase of the CPU. MEM[PC] -> IR");
-                register_view(ui, &mut emulator.pc, self.use_negative, self.display_base).on_hover_text("This register holds the next instruction that will be fetched in the fetch phase of the CPU. MEM[PC] -> IR");
+                let response = register_view(ui, &mut emulator.pc, self.use_negative, self.display_base).on_hover_text("This register holds the next instruction that will be fetched in the fetch phase of the CPU. MEM[PC] -> IR");
+                mark_changed(ui, &response, self.changed[PC], theme);
 
                 ui.label("MDR:").on_hover_text("This register holds the data that has been read from memory or will be written to memory.");
-                register_view(ui, &mut emulator.mdr, self.use_negative, self.display_base).on_hover_text("This register holds the data that has been read from memory or will be written to memory.");
+                let response = register_view(ui, &mut emulator.mdr, self.use_negative, self.display_base).on_hover_text("This register holds the data that has been read from memory or will be written to memory.");
+                mark_changed(ui, &response, self.changed[MDR], theme);
 
                 ui.label("MAR:").on_hover_text("This register holds the address of the memory location that will be read from or written to.");
-                register_view(ui, &mut emulator.mar, self.use_negative, self.display_base).on_hover_text("This register holds the address of the memory location that will be read from or written to.");
+                let response = register_view(ui, &mut emulator.mar, self.use_negative, self.display_base).on_hover_text("This register holds the address of the memory location that will be read from or written to.");
+                mark_changed(ui, &response, self.changed[MAR], theme);
 
                 ui.label("IR:").on_hover_text("This register holds the instruction that has been fetched from memory. This instruction is decoded and executed by the CPU.");
-                register_view(ui, &mut emulator.ir, self.use_negative, self.display_base).on_hover_text("This register holds the instruction that has been fetched from memory. This instruction is decoded and executed by the CPU.");
+                let response = register_view(ui, &mut emulator.ir, self.use_negative, self.display_base).on_hover_text("This register holds the instruction that has been fetched from memory. This instruction is decoded and executed by the CPU.");
+                mark_changed(ui, &response, self.changed[IR], theme);
                 ui.end_row();
 
                 let (n, z, p) = emulator.get_nzp();
@@ -205,7 +343,8 @@ impl CpuStatePane {
                 ).on_hover_text("Privilege Mode. This indicates the current privilege level of the CPU. PRIV=0 indicates supervisor mode, PRIV=1 indicates user mode.");
 
                 ui.label("PSR:").on_hover_text(RichText::new("mem[0xFFFC]").code()).on_hover_text("Processor Status Register. Layout: PSR[15] = 0 when in supervisor mode and 1 when user mode, PSR[2] = N, PSR[1] = Z, PSR[0] = P");
-                register_view(ui, &mut emulator.memory[PSR_ADDR], self.use_negative, self.display_base).on_hover_text(RichText::new("mem[0xFFFC]").code()).on_hover_text("Processor Status Register. Layout: PSR[15] = 0 when in supervisor mode and 1 when user mode, PSR[2] = N, PSR[1] = Z, PSR[0] = P");
+                let response = register_view(ui, &mut emulator.memory[PSR_ADDR], self.use_negative, self.display_base).on_hover_text(RichText::new("mem[0xFFFC]").code()).on_hover_text("Processor Status Register. Layout: PSR[15] = 0 when in supervisor mode and 1 when user mode, PSR[2] = N, PSR[1] = Z, PSR[0] = P");
+                mark_changed(ui, &response, self.changed[PSR], theme);
 
                 ui.label("MCR:").on_hover_text(RichText::new("mem[0xFFFE]").code()).on_hover_text("Machine Control Register, when MCR[15] is set the machine is running, otherwise it is halted");
                 register_view(ui, &mut emulator.memory[MCR_ADDR], self.use_negative, self.display_base).on_hover_text(RichText::new("mem[0xFFFE]").code()).on_hover_text("Machine Control Register, when MCR[15] is set the machine is running, otherwise it is halted");
@@ -265,6 +404,25 @@ impl CpuStatePane {
                 .code_editor()
                 .interactive(false),
         );
+        let timing = &emulator.timing;
+        ui.label(format!(
+            "Cycle {}, {} into this instruction. The last one took {}",
+            timing.cycles,
+            timing.instruction_so_far(),
+            timing.last_instruction
+        ))
+        .on_hover_text(
+            "A phase is a clock cycle, and so is each cycle waiting for memory to set R",
+        );
+        if let Some(waited) = timing.waiting {
+            ui.label(
+                RichText::new(format!(
+                    "Waiting for memory, R = 0 ({waited} of {} cycles)",
+                    timing.memory_latency
+                ))
+                .color(theme.warn_fg_color),
+            );
+        }
         // Display cycle list
         ui.label(RichText::new("CPU Pipeline Stages:").strong());
         for (i, cycle_name_str) in cycle_names.iter().enumerate() {
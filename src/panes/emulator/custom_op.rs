@@ -0,0 +1,150 @@
+use crate::emulator::ops::custom::{self, CustomInstruction};
+use crate::emulator::Emulator;
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+
+use super::EmulatorPane;
+
+/// What you start with, the usual architecture assignment
+const EXAMPLE: &str = include_str!("../../../assets/custom_ops/mul.ron");
+
+/// Define the instruction on the reserved opcode 1101
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CustomOpPane {
+    /// The RON definition being edited
+    source: String,
+    /// Where to open a definition from
+    path: String,
+    /// The definition was loaded last time, so load it again after a restart
+    active: bool,
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+impl Default for CustomOpPane {
+    fn default() -> Self {
+        Self {
+            source: EXAMPLE.to_owned(),
+            path: "mul.ron".to_owned(),
+            active: false,
+            error: None,
+        }
+    }
+}
+
+impl CustomOpPane {
+    fn load(&mut self) {
+        match CustomInstruction::from_ron(&self.source) {
+            Ok(instruction) => {
+                custom::set_loaded(Some(instruction));
+                self.active = true;
+                self.error = None;
+            }
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Load the definition again if it was loaded when the app was closed
+    pub fn restore(&mut self) {
+        if self.active {
+            self.load();
+        }
+    }
+}
+
+impl PaneDisplay for CustomOpPane {
+    fn render(&mut self, ui: &mut egui::Ui, _emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        let loaded = custom::loaded();
+
+        match &loaded {
+            Some(instruction) => {
+                ui.label(
+                    RichText::new(format!("Loaded: {}", instruction.signature()))
+                        .strong()
+                        .color(theme.accent_color_positive),
+                );
+                ui.label(RichText::new(instruction.layout()).monospace());
+            }
+            None => {
+                ui.label(
+                    RichText::new("Nothing loaded, 1101 is an illegal opcode")
+                        .color(theme.secondary_text_color),
+                );
+            }
+        }
+
+        ui.horizontal_wrapped(|ui| {
+            if ui
+                .button("Load")
+                .on_hover_text("Check the definition and use it for opcode 1101. Reassemble programs that use it afterwards.")
+                .clicked()
+            {
+                self.load();
+            }
+            if ui
+                .add_enabled(loaded.is_some(), egui::Button::new("Unload"))
+                .clicked()
+            {
+                custom::set_loaded(None);
+                self.active = false;
+            }
+            if ui.button("Reset to MUL example").clicked() {
+                self.source = EXAMPLE.to_owned();
+                self.error = None;
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            {
+                ui.separator();
+                ui.add(egui::TextEdit::singleline(&mut self.path).desired_width(160.0));
+                if ui.button("Open").clicked() {
+                    match std::fs::read_to_string(&self.path) {
+                        Ok(source) => {
+                            self.source = source;
+                            self.error = None;
+                        }
+                        Err(e) => self.error = Some(format!("Could not open {}: {e}", self.path)),
+                    }
+                }
+            }
+        });
+
+        if let Some(error) = &self.error {
+            ui.label(RichText::new(error).color(theme.error_fg_color));
+        }
+        ui.label(
+            RichText::new(
+                "Fields are the operands in the order they are written (Register, Immediate or PcOffset) and the bits they go in. \
+                 The plan uses the same steps as the built in instructions: Transfer(to, from), Alu(op, a, b) with Add, And, Not or Mul, \
+                 SetCc(register), WriteMemory and Message. Refer to a field by name with Reg(\"DR\"), Imm(\"IMM5\") or PcOffset(\"OFFSET\").",
+            )
+            .small()
+            .weak(),
+        );
+        ui.separator();
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.add(
+                egui::TextEdit::multiline(&mut self.source)
+                    .code_editor()
+                    .desired_width(f32::INFINITY)
+                    .desired_rows(20),
+            );
+        });
+    }
+
+    fn title(&self) -> String {
+        "Custom Instruction".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Custom Instruction".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::CustomOp(
+                CustomOpPane::default(),
+            )))),
+        )
+    }
+}
@@ -0,0 +1,602 @@
+//! The textbook LC-3 datapath, with the micro-ops of the current phase animated on it
+
+use crate::emulator::micro_op::{
+    DataDestination, DataSource, EguiDisplay, MAluOp, MachineFlag, MicroOp,
+};
+use crate::emulator::{CpuState, Emulator, PSR_ADDR};
+use crate::micro_op;
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::{pos2, vec2, Pos2, Rect, RichText, Stroke};
+use serde::{Deserialize, Serialize};
+
+use super::EmulatorPane;
+
+/// Where the bus runs, as a fraction of the diagram's height
+const BUS_Y: f32 = 0.08;
+
+/// The parts of the datapath that data moves between
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Block {
+    Pc,
+    Ir,
+    /// Sign extended offsets, trap vectors and constants that come out of the IR
+    Sext,
+    Registers,
+    Temp,
+    Psr,
+    Alu,
+    AluOut,
+    Mar,
+    Mdr,
+    Memory,
+}
+
+impl Block {
+    const ALL: [Block; 11] = [
+        Block::Pc,
+        Block::Ir,
+        Block::Sext,
+        Block::Registers,
+        Block::Temp,
+        Block::Psr,
+        Block::Alu,
+        Block::AluOut,
+        Block::Mar,
+        Block::Mdr,
+        Block::Memory,
+    ];
+
+    /// Centre and size, as fractions of the diagram
+    fn layout(self) -> (Pos2, egui::Vec2) {
+        match self {
+            Block::Pc => (pos2(0.08, 0.30), vec2(0.11, 0.12)),
+            Block::Ir => (pos2(0.22, 0.30), vec2(0.11, 0.12)),
+            Block::Sext => (pos2(0.22, 0.55), vec2(0.11, 0.12)),
+            Block::Registers => (pos2(0.40, 0.40), vec2(0.14, 0.42)),
+            Block::Temp => (pos2(0.58, 0.30), vec2(0.11, 0.12)),
+            Block::Psr => (pos2(0.92, 0.30), vec2(0.11, 0.12)),
+            Block::Alu => (pos2(0.40, 0.82), vec2(0.14, 0.12)),
+            Block::AluOut => (pos2(0.58, 0.82), vec2(0.11, 0.12)),
+            Block::Mar => (pos2(0.72, 0.55), vec2(0.11, 0.12)),
+            Block::Mdr => (pos2(0.86, 0.55), vec2(0.11, 0.12)),
+            Block::Memory => (pos2(0.79, 0.85), vec2(0.25, 0.14)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Block::Pc => "PC",
+            Block::Ir => "IR",
+            Block::Sext => "SEXT",
+            Block::Registers => "REG FILE",
+            Block::Temp => "TEMP",
+            Block::Psr => "PSR",
+            Block::Alu => "ALU",
+            Block::AluOut => "ALU_OUT",
+            Block::Mar => "MAR",
+            Block::Mdr => "MDR",
+            Block::Memory => "MEMORY",
+        }
+    }
+
+    fn from_source(source: &DataSource) -> Self {
+        match source {
+            DataSource::Register(_) => Block::Registers,
+            DataSource::PC => Block::Pc,
+            DataSource::IR => Block::Ir,
+            DataSource::MAR => Block::Mar,
+            DataSource::MDR => Block::Mdr,
+            DataSource::PSR => Block::Psr,
+            DataSource::AluOut => Block::AluOut,
+            DataSource::Temp => Block::Temp,
+            DataSource::Immediate(_)
+            | DataSource::PCOffset(_)
+            | DataSource::TrapVector(_)
+            | DataSource::Constant(_) => Block::Sext,
+        }
+    }
+
+    fn from_destination(destination: &DataDestination) -> Self {
+        match destination {
+            DataDestination::Register(_) => Block::Registers,
+            DataDestination::PC => Block::Pc,
+            DataDestination::IR => Block::Ir,
+            DataDestination::MAR => Block::Mar,
+            DataDestination::MDR => Block::Mdr,
+            DataDestination::PSR => Block::Psr,
+            DataDestination::AluOut => Block::AluOut,
+            DataDestination::Temp => Block::Temp,
+        }
+    }
+}
+
+/// What one step of the animation does
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Movement {
+    /// Over the bus
+    Transfer {
+        from: Block,
+        to: Block,
+        /// Register number if either end is in the register file
+        register: Option<u16>,
+    },
+    /// Operands into the ALU, result into ALU_OUT
+    Alu {
+        inputs: Vec<Block>,
+    },
+    SetCondCodes,
+    MemoryRead,
+    MemoryWrite,
+    /// Nothing moves, just show the text
+    Note,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Step {
+    movement: Movement,
+    text: String,
+    /// The op it came from, so the list can show it with colours
+    op: Option<usize>,
+}
+
+/// Turn the micro-ops of a phase into animation steps, adding the memory access that happens after the phase
+fn steps_for(ops: &[MicroOp]) -> Vec<Step> {
+    let mut steps = Vec::new();
+    let mut sets_mar = false;
+    let mut writes = false;
+    for (i, op) in ops.iter().enumerate() {
+        let movement = match op {
+            MicroOp::PhaseTransition(_) => continue,
+            MicroOp::Transfer {
+                source,
+                destination,
+            } => {
+                sets_mar |= matches!(destination, DataDestination::MAR);
+                let register = match (source, destination) {
+                    (DataSource::Register(n), _) | (_, DataDestination::Register(n)) => Some(*n),
+                    _ => None,
+                };
+                Movement::Transfer {
+                    from: Block::from_source(source),
+                    to: Block::from_destination(destination),
+                    register,
+                }
+            }
+            MicroOp::Alu {
+                operation,
+                operand1,
+                operand2,
+            } => {
+                let mut inputs = vec![Block::from_source(operand1)];
+                if !matches!(operation, MAluOp::Not) {
+                    inputs.push(Block::from_source(operand2));
+                }
+                Movement::Alu { inputs }
+            }
+            MicroOp::SetFlag(MachineFlag::UpdateCondCodes(_)) => Movement::SetCondCodes,
+            MicroOp::SetFlag(MachineFlag::WriteMemory) => {
+                writes = true;
+                continue;
+            }
+            MicroOp::Message(_) | MicroOp::Custom(..) => Movement::Note,
+        };
+        steps.push(Step {
+            movement,
+            text: op.to_string(),
+            op: Some(i),
+        });
+    }
+    if writes {
+        steps.push(Step {
+            movement: Movement::MemoryWrite,
+            text: "M[MAR] <- MDR".to_owned(),
+            op: None,
+        });
+    } else if sets_mar {
+        steps.push(Step {
+            movement: Movement::MemoryRead,
+            text: "MDR <- M[MAR]".to_owned(),
+            op: None,
+        });
+    }
+    steps
+}
+
+/// The datapath with the upcoming phase's micro-ops moving around it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatapathPane {
+    playing: bool,
+    /// Steps per second
+    speed: f32,
+    looping: bool,
+    /// Show this step instead of animating
+    #[serde(skip)]
+    pinned: Option<usize>,
+    /// When the current phase started animating, and which phase that was
+    #[serde(skip)]
+    started: Option<(f64, (u64, usize, bool))>,
+}
+
+impl Default for DatapathPane {
+    fn default() -> Self {
+        Self {
+            playing: true,
+            speed: 1.0,
+            looping: true,
+            pinned: None,
+            started: None,
+        }
+    }
+}
+
+/// A point `t` (0 to 1) of the way along `points`
+fn along(points: &[Pos2], t: f32) -> Pos2 {
+    let lengths: Vec<f32> = points.windows(2).map(|w| w[0].distance(w[1])).collect();
+    let total: f32 = lengths.iter().sum();
+    let mut left = total * t.clamp(0.0, 1.0);
+    for (w, length) in points.windows(2).zip(lengths) {
+        if left <= length && length > 0.0 {
+            return w[0] + (w[1] - w[0]) * (left / length);
+        }
+        left -= length;
+    }
+    points.last().copied().unwrap_or_default()
+}
+
+impl DatapathPane {
+    fn diagram(
+        &self,
+        ui: &mut egui::Ui,
+        emulator: &Emulator,
+        theme: &ThemeSettings,
+        step: Option<&Step>,
+        progress: f32,
+    ) {
+        let width = ui.available_width();
+        let (response, painter) =
+            ui.allocate_painter(vec2(width, (width * 0.6).max(240.0)), egui::Sense::hover());
+        let frame = response.rect;
+        painter.rect_filled(frame, 4.0, theme.code_bg_color);
+        let area = frame.shrink(8.0);
+        let at = |p: Pos2| area.min + vec2(p.x * area.width(), p.y * area.height());
+        let rect_of = |block: Block| {
+            let (centre, size) = block.layout();
+            Rect::from_center_size(
+                at(centre),
+                vec2(size.x * area.width(), size.y * area.height()),
+            )
+        };
+        let bus_y = at(pos2(0.0, BUS_Y)).y;
+        // where each block connects to the bus
+        let tap = |block: Block| rect_of(block).center_top();
+
+        let idle = theme.secondary_text_color;
+        let flow = theme.cpu_state_data_flow_color;
+        let active = theme.cpu_state_active_color;
+        let text_color = ui.visuals().text_color();
+        let font = egui::FontId::monospace(11.0);
+
+        let highlighted: Vec<Block> = match step.map(|s| &s.movement) {
+            Some(Movement::Transfer { from, to, .. }) => vec![*from, *to],
+            Some(Movement::Alu { inputs }) => {
+                let mut blocks = inputs.clone();
+                blocks.extend([Block::Alu, Block::AluOut]);
+                blocks
+            }
+            Some(Movement::SetCondCodes) => vec![Block::Registers, Block::Psr],
+            Some(Movement::MemoryRead) => vec![Block::Mar, Block::Memory, Block::Mdr],
+            Some(Movement::MemoryWrite) => vec![Block::Mar, Block::Mdr, Block::Memory],
+            Some(Movement::Note) | None => Vec::new(),
+        };
+
+        // the wires that are always there
+        painter.line_segment(
+            [pos2(area.left(), bus_y), pos2(area.right(), bus_y)],
+            Stroke::new(4.0, idle),
+        );
+        painter.text(
+            pos2(area.left(), bus_y - 4.0),
+            egui::Align2::LEFT_BOTTOM,
+            "BUS",
+            font.clone(),
+            idle,
+        );
+        for block in Block::ALL {
+            if !matches!(block, Block::Alu | Block::Memory) {
+                painter.line_segment(
+                    [tap(block), pos2(tap(block).x, bus_y)],
+                    Stroke::new(1.0, idle),
+                );
+            }
+        }
+        for (from, to) in [
+            (Block::Registers, Block::Alu),
+            (Block::Sext, Block::Alu),
+            (Block::Alu, Block::AluOut),
+            (Block::Mar, Block::Memory),
+            (Block::Mdr, Block::Memory),
+            (Block::Registers, Block::Psr),
+        ] {
+            painter.line_segment(
+                [rect_of(from).center(), rect_of(to).center()],
+                Stroke::new(1.0, idle.gamma_multiply(0.5)),
+            );
+        }
+
+        // the blocks, with what is in them
+        let value = |block: Block| match block {
+            Block::Pc => Some(emulator.pc.get()),
+            Block::Ir => Some(emulator.ir.get()),
+            Block::Mar => Some(emulator.mar.get()),
+            Block::Mdr => Some(emulator.mdr.get()),
+            Block::Psr => Some(emulator.memory[PSR_ADDR].get()),
+            Block::AluOut => Some(emulator.alu.alu_out.get()),
+            Block::Memory => Some(emulator.memory[emulator.word_address(emulator.mar.get())].get()),
+            _ => None,
+        };
+        let register = match step.map(|s| &s.movement) {
+            Some(Movement::Transfer { register, .. }) => *register,
+            _ => None,
+        };
+        for block in Block::ALL {
+            let rect = rect_of(block);
+            let lit = highlighted.contains(&block);
+            painter.rect(
+                rect,
+                3.0,
+                ui.visuals().extreme_bg_color,
+                Stroke::new(if lit { 2.5 } else { 1.0 }, if lit { active } else { idle }),
+                egui::StrokeKind::Inside,
+            );
+            painter.text(
+                rect.center_top() + vec2(0.0, 2.0),
+                egui::Align2::CENTER_TOP,
+                block.name(),
+                font.clone(),
+                if lit { active } else { text_color },
+            );
+            if let Some(value) = value(block) {
+                let label = if block == Block::Memory {
+                    format!("[x{:04X}] x{value:04X}", emulator.mar.get())
+                } else {
+                    format!("x{value:04X}")
+                };
+                painter.text(
+                    rect.center_bottom() - vec2(0.0, 2.0),
+                    egui::Align2::CENTER_BOTTOM,
+                    label,
+                    font.clone(),
+                    text_color,
+                );
+            }
+        }
+        // register file rows
+        let registers = rect_of(Block::Registers);
+        let row = (registers.height() - 16.0) / 8.0;
+        for (i, r) in emulator.r.iter().enumerate() {
+            let y = registers.top() + 16.0 + row * (i as f32 + 0.5);
+            let lit = register == Some(i as u16);
+            painter.text(
+                pos2(registers.center().x, y),
+                egui::Align2::CENTER_CENTER,
+                format!("R{i} x{:04X}", r.get()),
+                font.clone(),
+                if lit { active } else { text_color },
+            );
+        }
+        let psr = emulator.memory[PSR_ADDR].get();
+        painter.text(
+            rect_of(Block::Psr).center(),
+            egui::Align2::CENTER_CENTER,
+            format!(
+                "{}{}{}",
+                if psr & 0b100 != 0 { 'N' } else { '-' },
+                if psr & 0b010 != 0 { 'Z' } else { '-' },
+                if psr & 0b001 != 0 { 'P' } else { '-' },
+            ),
+            font.clone(),
+            text_color,
+        );
+
+        // the step that is happening
+        let Some(step) = step else {
+            return;
+        };
+        let paths: Vec<Vec<Pos2>> = match &step.movement {
+            Movement::Transfer { from, to, .. } => vec![vec![
+                tap(*from),
+                pos2(tap(*from).x, bus_y),
+                pos2(tap(*to).x, bus_y),
+                tap(*to),
+            ]],
+            Movement::Alu { inputs } => inputs
+                .iter()
+                .map(|input| {
+                    vec![
+                        rect_of(*input).center(),
+                        rect_of(Block::Alu).center(),
+                        rect_of(Block::AluOut).center(),
+                    ]
+                })
+                .collect(),
+            Movement::SetCondCodes => {
+                vec![vec![
+                    rect_of(Block::Registers).center(),
+                    rect_of(Block::Psr).center(),
+                ]]
+            }
+            Movement::MemoryRead => vec![vec![
+                rect_of(Block::Mar).center(),
+                rect_of(Block::Memory).center(),
+                rect_of(Block::Mdr).center(),
+            ]],
+            Movement::MemoryWrite => vec![
+                vec![
+                    rect_of(Block::Mar).center(),
+                    rect_of(Block::Memory).center(),
+                ],
+                vec![
+                    rect_of(Block::Mdr).center(),
+                    rect_of(Block::Memory).center(),
+                ],
+            ],
+            Movement::Note => Vec::new(),
+        };
+        for path in &paths {
+            painter.add(egui::Shape::line(path.clone(), Stroke::new(3.0, flow)));
+            painter.circle_filled(along(path, progress), 5.0, active);
+        }
+        painter.text(
+            frame.center_bottom() - vec2(0.0, 4.0),
+            egui::Align2::CENTER_BOTTOM,
+            &step.text,
+            egui::FontId::monospace(14.0),
+            active,
+        );
+    }
+}
+
+impl PaneDisplay for DatapathPane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        // the phase that runs next, like the CPU pane's "->"
+        let fetching = matches!(emulator.cpu_state, CpuState::Fetch);
+        let fetch_ops;
+        let ops = if fetching {
+            // the plan for the next instruction doesn't exist until it is fetched
+            fetch_ops = [
+                micro_op!(MAR <- PC),
+                micro_op!(ALU_OUT <- PC + C(1)),
+                micro_op!(PC <- AluOut),
+            ];
+            &fetch_ops[..]
+        } else {
+            emulator
+                .execute_state
+                .phase_ops(emulator.execute_state.current_phase)
+        };
+        let steps = steps_for(ops);
+
+        let now = ui.input(|i| i.time);
+        let key = (
+            emulator.instruction_count,
+            emulator.execute_state.current_phase,
+            fetching,
+        );
+        if self.started.map_or(true, |(_, started)| started != key) {
+            self.started = Some((now, key));
+            self.pinned = None;
+        }
+        let elapsed = (now - self.started.map_or(now, |(at, _)| at)) as f32 * self.speed;
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label(RichText::new(format!("Next phase: {}", emulator.cpu_state)).strong());
+            ui.separator();
+            if ui
+                .button(if self.playing {
+                    "⏸ Pause"
+                } else {
+                    "▶ Play"
+                })
+                .clicked()
+            {
+                self.playing = !self.playing;
+            }
+            if ui.button("⟲ Restart").clicked() {
+                self.started = None;
+                self.pinned = None;
+            }
+            ui.add(
+                egui::Slider::new(&mut self.speed, 0.25..=4.0)
+                    .text("steps/s")
+                    .logarithmic(true),
+            );
+            ui.checkbox(&mut self.looping, "Loop");
+        });
+
+        let finished = !self.looping && elapsed >= steps.len() as f32;
+        let (index, progress) = match self.pinned {
+            Some(i) => (Some(i), 1.0),
+            None if steps.is_empty() => (None, 0.0),
+            None if !self.playing || finished => (Some(steps.len() - 1), 1.0),
+            None => {
+                let index = elapsed as usize % steps.len();
+                (Some(index), elapsed.fract())
+            }
+        };
+        let index = index.filter(|&i| i < steps.len());
+        if self.playing && self.pinned.is_none() && !finished && !steps.is_empty() {
+            ui.ctx().request_repaint();
+        }
+
+        self.diagram(ui, emulator, theme, index.map(|i| &steps[i]), progress);
+
+        ui.separator();
+        if steps.is_empty() {
+            ui.label(RichText::new("Nothing happens in this phase").weak());
+        }
+        for (i, step) in steps.iter().enumerate() {
+            let text: egui::WidgetText = match step.op {
+                Some(op) => ops[op].display(theme, &ui.ctx().style()).into(),
+                None => RichText::new(&step.text)
+                    .color(theme.cpu_state_data_flow_color)
+                    .into(),
+            };
+            let response = ui.selectable_label(index == Some(i), text);
+            if response.clicked() {
+                self.pinned = if self.pinned == Some(i) {
+                    None
+                } else {
+                    Some(i)
+                };
+            }
+        }
+        ui.label(
+            RichText::new("Click a step to stop on it, click it again to keep animating")
+                .weak()
+                .small(),
+        );
+    }
+
+    fn title(&self) -> String {
+        "Datapath".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Datapath".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Datapath(
+                DatapathPane::default(),
+            )))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_access_follows_the_phase() {
+        let steps = steps_for(&[
+            micro_op!(-> EvaluateAddress),
+            micro_op!(MAR <- PC),
+            micro_op!(R(3) <- MDR),
+        ]);
+        assert_eq!(steps.len(), 3);
+        assert_eq!(
+            steps[1].movement,
+            Movement::Transfer {
+                from: Block::Mdr,
+                to: Block::Registers,
+                register: Some(3),
+            }
+        );
+        assert_eq!(steps[2].movement, Movement::MemoryRead);
+
+        let steps = steps_for(&[micro_op!(MAR <- PC), micro_op!(SET_FLAG(WriteMemory))]);
+        assert_eq!(steps.last().unwrap().movement, Movement::MemoryWrite);
+        assert_eq!(steps.len(), 2);
+    }
+}
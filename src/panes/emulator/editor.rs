@@ -1,5 +1,5 @@
-use crate::emulator::parse::{ParseError, ParseOutput};
-use crate::emulator::Emulator;
+use crate::emulator::parse::{CompilationArtifacts, ParseError, ParseOutput};
+use crate::emulator::{Emulator, Isa};
 use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
 use crate::theme::ThemeSettings;
 use serde::{Deserialize, Serialize};
@@ -7,9 +7,140 @@ use std::collections::BTreeSet;
 
 use super::EmulatorPane;
 
+/// Gutter breakpoints
+mod breakpoints;
+/// Autocomplete popup
+mod completion;
+/// Error squiggles
+mod diagnostics;
+/// Saving and loading documents
+mod files;
+/// Hover, go to definition and find references
+mod navigation;
+
+/// Room to the left of the code for markers
+const GUTTER_WIDTH: f32 = 14.0;
+
+const HELLO_WORLD: &str = r#".ORIG x3000
+; Simple Hello World program
+LEA R0, MESSAGE    ; Load the address of the message
+PUTS               ; Output the string
+HALT               ; Halt the program
+
+MESSAGE: .STRINGZ "Hello, World!"
+.END"#;
+
+/// One tab in the editor
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Document {
+    pub name: String,
+    /// Where it was last saved/opened from (a path on desktop, a name in local storage on the web)
+    pub path: Option<String>,
+    pub contents: String,
+    /// Changed since last save
+    pub dirty: bool,
+    /// Lines (1-based) with a breakpoint set from the gutter
+    #[serde(default)]
+    pub breakpoints: BTreeSet<usize>,
+    /// What the assembler thinks of it
+    #[serde(skip)]
+    diagnostic: Option<diagnostics::Diagnostic>,
+    /// Things that assembled but look wrong
+    #[serde(skip)]
+    warnings: Vec<diagnostics::Diagnostic>,
+    /// The last check, for hover info. When it didn't assemble this is only where the labels
+    /// and lines go, without machine code
+    #[serde(skip)]
+    assembled: Option<ParseOutput>,
+    /// `diagnostic` is up to date
+    #[serde(skip)]
+    checked: bool,
+    /// When the user last typed, so we can wait for them to stop before checking
+    #[serde(skip)]
+    edited_at: Option<f64>,
+}
+
+impl Document {
+    fn new(name: impl Into<String>, contents: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            path: None,
+            contents: contents.into(),
+            dirty: false,
+            breakpoints: BTreeSet::new(),
+            diagnostic: None,
+            warnings: Vec::new(),
+            assembled: None,
+            checked: false,
+            edited_at: None,
+        }
+    }
+
+    /// Is this exactly what the emulator last compiled
+    fn is_compiled(&self, metadata: &CompilationArtifacts) -> bool {
+        !metadata.last_compiled_source.is_empty()
+            && self
+                .contents
+                .split('\n')
+                .eq(metadata.last_compiled_source.iter().map(String::as_str))
+    }
+
+    /// Re-check the source if the user has stopped typing for a bit
+    fn check_if_due(&mut self, ctx: &egui::Context, isa: Isa) {
+        if self.checked {
+            return;
+        }
+        let now = ctx.input(|i| i.time);
+        let waited = self.edited_at.map_or(f64::INFINITY, |t| now - t);
+        if waited >= diagnostics::DEBOUNCE {
+            match diagnostics::check(&self.contents, isa) {
+                Ok((assembled, warnings)) => {
+                    self.assembled = Some(assembled);
+                    self.diagnostic = None;
+                    self.warnings = warnings;
+                }
+                Err(diagnostic) => {
+                    self.assembled = Emulator::layout_program(isa, &self.contents);
+                    self.diagnostic = Some(diagnostic);
+                    self.warnings.clear();
+                }
+            }
+            self.checked = true;
+        } else {
+            ctx.request_repaint_after(std::time::Duration::from_secs_f64(
+                diagnostics::DEBOUNCE - waited,
+            ));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
 pub struct EditorPane {
-    program: String,
+    documents: Vec<Document>,
+    /// The tab being edited
+    active: usize,
+    /// The tab the assemble button uses. `None` means whatever tab is open
+    assemble_target: Option<usize>,
+    /// What is typed in the path box
+    path_input: String,
+    /// Last thing that went wrong opening/saving
+    #[serde(skip)]
+    file_error: Option<String>,
+    /// A dirty tab we asked about closing
+    #[serde(skip)]
+    confirm_close: Option<usize>,
+    #[serde(skip)]
+    completer: completion::Completer,
+    /// The word that was right clicked, for the context menu
+    #[serde(skip)]
+    context_word: Option<String>,
+    /// A label and the lines that use it
+    #[serde(skip)]
+    references: Option<(String, Vec<usize>)>,
+    /// Line to move the cursor to next frame
+    #[serde(skip)]
+    pending_jump: Option<usize>,
     fade: f32,
     last_compilation_was_successful: bool,
 }
@@ -17,26 +148,301 @@ pub struct EditorPane {
 impl Default for EditorPane {
     fn default() -> Self {
         Self {
-            program: r#".ORIG x3000
-; Simple Hello World program
-LEA R0, MESSAGE    ; Load the address of the message
-PUTS               ; Output the string
-HALT               ; Halt the program
-
-MESSAGE: .STRINGZ "Hello, World!"
-.END"#
-                .to_string(),
+            documents: vec![Document::new("untitled.asm", HELLO_WORLD)],
+            active: 0,
+            assemble_target: None,
+            path_input: String::new(),
+            file_error: None,
+            confirm_close: None,
+            completer: completion::Completer::default(),
+            context_word: None,
+            references: None,
+            pending_jump: None,
             fade: 0.0,
             last_compilation_was_successful: false,
         }
     }
 }
 
+impl EditorPane {
+    /// The document being edited
+    pub fn active_document(&self) -> &Document {
+        &self.documents[self.active]
+    }
+
+    fn active_document_mut(&mut self) -> &mut Document {
+        &mut self.documents[self.active]
+    }
+
+    /// Which tab gets assembled
+    fn assemble_index(&self) -> usize {
+        self.assemble_target
+            .filter(|&i| i < self.documents.len())
+            .unwrap_or(self.active)
+    }
+
+    /// Mirror the emulator's breakpoints into the tab that is loaded in it (so ones set in the memory view show up too).
+    /// Returns that tab
+    fn sync_breakpoints(&mut self, emulator: &Emulator) -> Option<usize> {
+        let metadata = &emulator.metadata;
+        let compiled = self
+            .documents
+            .iter()
+            .position(|doc| doc.is_compiled(metadata))?;
+        let doc = &mut self.documents[compiled];
+        doc.breakpoints
+            .retain(|line| !metadata.line_to_address.contains_key(line));
+        doc.breakpoints.extend(
+            emulator
+                .breakpoints
+                .iter()
+                .filter_map(|address| metadata.address_to_line.get(address)),
+        );
+        Some(compiled)
+    }
+
+    /// Assemble a tab and load it into the emulator, moving its gutter breakpoints over with it
+    fn compile(&mut self, target: usize, emulator: &mut Emulator) {
+        // addresses of the loaded program, its breakpoints are kept by line in its tab. This is
+        // taken even if that tab has been edited since, otherwise they would stay behind on the
+        // new program's lines
+        let old_breakpoints: Vec<usize> =
+            emulator.metadata.address_to_line.keys().copied().collect();
+        let doc = &mut self.documents[target];
+        let data_to_load =
+            Emulator::parse_program_for(emulator.isa, &doc.contents, Some(&mut emulator.metadata));
+        doc.diagnostic = data_to_load
+            .as_ref()
+            .err()
+            .map(diagnostics::Diagnostic::from_error);
+        doc.warnings = match &data_to_load {
+            Ok(_) => diagnostics::warnings(&emulator.metadata),
+            Err(_) => Vec::new(),
+        };
+        doc.checked = true;
+        doc.assembled = match &data_to_load {
+            Ok(assembled) => Some(assembled.clone()),
+            Err(_) => Emulator::layout_program(emulator.isa, &doc.contents),
+        };
+        if let Ok(ParseOutput {
+            machine_code,
+            orig_address,
+            ..
+        }) = data_to_load
+        {
+            // Flash memory
+            emulator.flash_memory(machine_code, orig_address);
+
+            for address in old_breakpoints {
+                emulator.breakpoints.remove(&address);
+            }
+            emulator.breakpoints.extend(
+                doc.breakpoints
+                    .iter()
+                    .filter_map(|line| emulator.metadata.line_to_address.get(line)),
+            );
+
+            self.fade = 1.0;
+            self.last_compilation_was_successful = true;
+        } else {
+            self.fade = 1.0;
+            self.last_compilation_was_successful = false;
+        }
+    }
+
+    fn open(&mut self, path: &str) {
+        // already open? just switch to it
+        if let Some(i) = self
+            .documents
+            .iter()
+            .position(|doc| doc.path.as_deref() == Some(path))
+        {
+            self.active = i;
+            return;
+        }
+        match files::load(path) {
+            Ok(contents) => {
+                let mut doc = Document::new(files::display_name(path), contents);
+                doc.path = Some(path.to_owned());
+                self.documents.push(doc);
+                self.active = self.documents.len() - 1;
+                self.file_error = None;
+            }
+            Err(e) => self.file_error = Some(e),
+        }
+    }
+
+    /// Save the active tab to `path`, or where it came from if `None`
+    fn save(&mut self, path: Option<String>) {
+        let Some(path) = path.or_else(|| self.active_document().path.clone()) else {
+            self.file_error = Some("Enter a path to save to".to_owned());
+            return;
+        };
+        match files::save(&path, &self.active_document().contents) {
+            Ok(()) => {
+                let doc = self.active_document_mut();
+                doc.name = files::display_name(&path);
+                doc.path = Some(path);
+                doc.dirty = false;
+                self.file_error = None;
+            }
+            Err(e) => self.file_error = Some(e),
+        }
+    }
+
+    fn close(&mut self, i: usize) {
+        self.documents.remove(i);
+        if self.documents.is_empty() {
+            self.documents.push(Document::new("untitled.asm", ""));
+        }
+        // keep the indexes pointing at the same tabs
+        let shift = |idx: usize| if idx > i { idx - 1 } else { idx };
+        self.active = shift(self.active).min(self.documents.len() - 1);
+        self.assemble_target = match self.assemble_target {
+            Some(t) if t == i => None,
+            Some(t) => Some(shift(t)),
+            None => None,
+        };
+    }
+
+    fn render_tabs(&mut self, ui: &mut egui::Ui, theme: &ThemeSettings) {
+        let mut close = None;
+        ui.horizontal_wrapped(|ui| {
+            for (i, doc) in self.documents.iter().enumerate() {
+                let mut text = doc.name.clone();
+                if doc.dirty {
+                    text.push('*');
+                }
+                if self.assemble_target == Some(i) {
+                    text = format!("⚙ {text}");
+                }
+                let tab = ui
+                    .selectable_label(i == self.active, text)
+                    .on_hover_text(doc.path.as_deref().unwrap_or("Not saved yet"));
+                if tab.clicked() {
+                    self.active = i;
+                }
+                if tab.middle_clicked() {
+                    close = Some(i);
+                }
+                if ui.small_button("x").on_hover_text("Close").clicked() {
+                    close = Some(i);
+                }
+                ui.separator();
+            }
+            if ui.button("+").on_hover_text("New tab").clicked() {
+                let name = format!("untitled{}.asm", self.documents.len() + 1);
+                self.documents
+                    .push(Document::new(name, ".ORIG x3000\n\nHALT\n.END"));
+                self.active = self.documents.len() - 1;
+            }
+        });
+
+        if let Some(i) = close {
+            if self.documents[i].dirty {
+                self.confirm_close = Some(i);
+            } else {
+                self.close(i);
+            }
+        }
+
+        if let Some(i) = self.confirm_close.filter(|&i| i < self.documents.len()) {
+            ui.horizontal(|ui| {
+                ui.colored_label(
+                    theme.warn_fg_color,
+                    format!(
+                        "{} has unsaved changes. Close anyway?",
+                        self.documents[i].name
+                    ),
+                );
+                if ui.button("Discard").clicked() {
+                    self.close(i);
+                    self.confirm_close = None;
+                }
+                if ui.button("Cancel").clicked() {
+                    self.confirm_close = None;
+                }
+            });
+        }
+    }
+
+    fn render_file_bar(&mut self, ui: &mut egui::Ui, theme: &ThemeSettings) {
+        ui.horizontal(|ui| {
+            #[cfg(not(target_arch = "wasm32"))]
+            let hint = "path/to/program.asm";
+            #[cfg(target_arch = "wasm32")]
+            let hint = "file name (saved in this browser)";
+            ui.add(
+                egui::TextEdit::singleline(&mut self.path_input)
+                    .hint_text(hint)
+                    .desired_width(200.0),
+            );
+
+            let path = self.path_input.trim().to_owned();
+            if ui
+                .add_enabled(!path.is_empty(), egui::Button::new("Open"))
+                .clicked()
+            {
+                self.open(&path);
+            }
+            if ui
+                .add_enabled(!path.is_empty(), egui::Button::new("Save As"))
+                .clicked()
+            {
+                self.save(Some(path));
+            }
+            let save = ui
+                .button("Save")
+                .on_hover_text("Save this tab where it was opened from (Ctrl+S)");
+            let shortcut = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::S);
+            if save.clicked() || ui.input_mut(|i| i.consume_shortcut(&shortcut)) {
+                self.save(None);
+            }
+
+            #[cfg(target_arch = "wasm32")]
+            ui.menu_button("Saved files", |ui| {
+                let names = files::list();
+                if names.is_empty() {
+                    ui.label("Nothing saved yet");
+                }
+                for name in names {
+                    ui.horizontal(|ui| {
+                        if ui.button(&name).clicked() {
+                            self.open(&name);
+                            ui.close();
+                        }
+                        if ui.small_button("🗑").on_hover_text("Delete").clicked() {
+                            if let Err(e) = files::delete(&name) {
+                                self.file_error = Some(e);
+                            }
+                        }
+                    });
+                }
+            });
+        });
+
+        if let Some(error) = &self.file_error {
+            ui.colored_label(theme.error_fg_color, error);
+        }
+    }
+}
+
 impl PaneDisplay for EditorPane {
     fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
         if emulator.metadata.last_compiled_source.is_empty() {
             self.last_compilation_was_successful = false;
         }
+        self.active = self.active.min(self.documents.len().saturating_sub(1));
+        if self.documents.is_empty() {
+            self.documents.push(Document::new("untitled.asm", ""));
+        }
+
+        let compiled = self.sync_breakpoints(emulator);
+
+        self.render_tabs(ui, theme);
+        self.render_file_bar(ui, theme);
+        ui.separator();
+
         egui::ScrollArea::vertical().show(ui, |ui| {
             // Make the code editor borderless and fill the available width
             let editor_frame = egui::Frame::new()
@@ -44,27 +450,169 @@ impl PaneDisplay for EditorPane {
                 .inner_margin(egui::Margin::same(0));
 
             editor_frame.show(ui, |ui| {
-                egui_code_editor::CodeEditor::default()
-                    .with_ui_fontsize(ui)
-                    .with_syntax(
-                        egui_code_editor::Syntax::new("lc3_assembly")
-                            .with_comment(";")
-                            .with_keywords(BTreeSet::from([
-                                "ADD", "AND", "BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP", "BRZP",
-                                "BRNZP", "JMP", "JSR", "JSRR", "LD", "LDI", "LDR", "LEA", "NOT",
-                                "RET", "RTI", "ST", "STI", "STR", "TRAP", "GETC", "OUT", "PUTS",
-                                "IN", "HALT",
-                            ]))
-                            .with_special(BTreeSet::from([
-                                ":", ".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".END",
-                            ]))
-                            .with_case_sensitive(false),
-                    )
-                    .vscroll(false)
-                    .with_theme(egui_code_editor::ColorTheme::SONOKAI)
-                    .show(ui, &mut self.program);
+                let doc = &mut self.documents[self.active];
+                doc.check_if_due(ui.ctx(), emulator.isa);
+
+                ui.horizontal_top(|ui| {
+                    ui.spacing_mut().item_spacing.x = 0.0;
+                    let (gutter, _) = ui.allocate_exact_size(
+                        egui::vec2(GUTTER_WIDTH, 0.0),
+                        egui::Sense::hover(),
+                    );
+                    // only needed to move breakpoints along with an edit
+                    let before = (!doc.breakpoints.is_empty()).then(|| doc.contents.clone());
+
+                    let completed = self.completer.take_keys(ui);
+                    if let Some(item) = completed {
+                        self.completer.accept(item, &mut doc.contents, ui.ctx());
+                    }
+
+                    let output = egui_code_editor::CodeEditor::default()
+                        .id_source(format!("editor_{}", self.active))
+                        .with_ui_fontsize(ui)
+                        .with_syntax(
+                            egui_code_editor::Syntax::new("lc3_assembly")
+                                .with_comment(";")
+                                .with_keywords(BTreeSet::from([
+                                    "ADD", "AND", "BR", "BRN", "BRZ", "BRP", "BRNZ", "BRNP",
+                                    "BRZP", "BRNZP", "JMP", "JSR", "JSRR", "LD", "LDI", "LDR",
+                                    "LEA", "NOT", "RET", "RTI", "ST", "STI", "STR", "TRAP",
+                                    "GETC", "OUT", "PUTS", "IN", "PUTSP", "HALT",
+                                ]))
+                                .with_special(BTreeSet::from([
+                                    ":", ".ORIG", ".FILL", ".BLKW", ".STRINGZ", ".END",
+                                ]))
+                                .with_case_sensitive(false),
+                        )
+                        .vscroll(false)
+                        .with_theme(egui_code_editor::ColorTheme::SONOKAI)
+                        .show(ui, &mut doc.contents);
+                    self.completer.show(ui, &output, &doc.contents, emulator.isa, theme);
+                    if output.response.changed() || completed.is_some() {
+                        doc.dirty = true;
+                        doc.checked = false;
+                        doc.edited_at = Some(ui.input(|i| i.time));
+                        if let Some(before) = before {
+                            doc.breakpoints =
+                                breakpoints::follow_edit(&before, &doc.contents, &doc.breakpoints);
+                        }
+                    }
+
+                    let gutter = egui::Rect::from_x_y_ranges(
+                        gutter.x_range(),
+                        output.response.rect.y_range(),
+                    );
+                    let metadata = &emulator.metadata;
+                    let executing_line = (compiled == Some(self.active))
+                        .then(|| metadata.address_to_line.get(&emulator.currently_executing))
+                        .flatten()
+                        .copied();
+                    let clicked = breakpoints::gutter(
+                        ui,
+                        &output,
+                        gutter,
+                        &doc.contents,
+                        &doc.breakpoints,
+                        executing_line,
+                        theme,
+                    );
+                    if let Some(line) = clicked {
+                        if compiled == Some(self.active) {
+                            // loaded in the emulator, set it there and let the sync show it
+                            if let Some(&address) = metadata.line_to_address.get(&line) {
+                                if !emulator.breakpoints.remove(&address) {
+                                    emulator.breakpoints.insert(address);
+                                }
+                            }
+                        } else if !doc.breakpoints.remove(&line) {
+                            doc.breakpoints.insert(line);
+                        }
+                    }
+
+                    for diagnostic in doc.diagnostic.iter().chain(&doc.warnings) {
+                        diagnostics::paint(ui, &output, gutter, &doc.contents, diagnostic, theme);
+                    }
+
+                    let pointer = output.response.hover_pos().map(|pos| {
+                        output
+                            .galley
+                            .cursor_from_pos(pos - output.galley_pos)
+                            .index
+                    });
+                    if let (Some(index), Some(assembled)) = (pointer, &doc.assembled) {
+                        if let Some(add_contents) =
+                            navigation::hover_ui(assembled, &doc.contents, index, theme)
+                        {
+                            output.response.clone().on_hover_ui_at_pointer(add_contents);
+                        }
+                    }
+                    let word = pointer
+                        .and_then(|index| navigation::word_at(&doc.contents, index))
+                        .map(|(_, word)| word);
+                    if output.response.clicked() && ui.input(|i| i.modifiers.command) {
+                        self.pending_jump = word.as_ref().and_then(|word| {
+                            navigation::find_label(&doc.contents, word, emulator.isa).0
+                        });
+                    }
+                    if output.response.secondary_clicked() {
+                        self.context_word = word;
+                    }
+                    output.response.context_menu(|ui| match &self.context_word {
+                        Some(word) => {
+                            let (definition, uses) =
+                                navigation::find_label(&doc.contents, word, emulator.isa);
+                            if ui
+                                .add_enabled(
+                                    definition.is_some(),
+                                    egui::Button::new(format!("Go to definition of {word}")),
+                                )
+                                .clicked()
+                            {
+                                self.pending_jump = definition;
+                                ui.close();
+                            }
+                            if ui.button(format!("Find references to {word}")).clicked() {
+                                self.references = Some((word.clone(), uses));
+                                ui.close();
+                            }
+                        }
+                        None => {
+                            ui.label("Right click on a label");
+                        }
+                    });
+
+                    if let Some(line) = self.pending_jump.take() {
+                        navigation::jump_to_line(ui, &output, &doc.contents, line);
+                    }
+                });
             });
 
+            if let Some((label, uses)) = &self.references {
+                let mut close = false;
+                let doc = &self.documents[self.active];
+                ui.group(|ui| {
+                    ui.horizontal(|ui| {
+                        ui.strong(format!("References to {label} ({})", uses.len()));
+                        close = ui.small_button("x").clicked();
+                    });
+                    for &line in uses {
+                        let text = doc.contents.split('\n').nth(line - 1).unwrap_or_default();
+                        if ui
+                            .button(egui::RichText::new(format!("{line}: {}", text.trim())).monospace())
+                            .clicked()
+                        {
+                            self.pending_jump = Some(line);
+                        }
+                    }
+                    if uses.is_empty() {
+                        ui.label("Not used anywhere");
+                    }
+                });
+                if close {
+                    self.references = None;
+                }
+            }
+
             // Show error or success feedback
             {
                 let artifacts = &mut emulator.metadata;
@@ -79,7 +627,10 @@ impl PaneDisplay for EditorPane {
                         ParseError::GenerationError(s, token_span) => {
                             ui.colored_label(
                                 ui.visuals().error_fg_color,
-                                format!("Code generation error at {token_span:?}: {s}"),
+                                format!(
+                                    "Code generation error on line {}: {s}",
+                                    token_span.line
+                                ),
                             );
                         }
                     }
@@ -115,37 +666,40 @@ impl PaneDisplay for EditorPane {
             let button_color = blend(just_compiled, base, fade);
 
             ui.horizontal(|ui| {
-                let button = egui::Button::new("Compile").fill(button_color);
+                let target = self.assemble_index();
+                let button = egui::Button::new(format!(
+                    "Compile {}",
+                    self.documents[target].name
+                ))
+                .fill(button_color);
                 if ui.add(button).clicked() {
-                    let data_to_load =
-                        Emulator::parse_program(&self.program, Some(&mut emulator.metadata));
-                    if let Ok(ParseOutput {
-                        machine_code,
-                        orig_address,
-                        ..
-                    }) = data_to_load
-                    {
-                        // Flash memory
-                        emulator.flash_memory(machine_code, orig_address);
-
-                        self.fade = 1.0;
-                        self.last_compilation_was_successful = true;
-                    } else {
-                        self.fade = 1.0;
-                        self.last_compilation_was_successful = false;
-                    }
+                    self.compile(target, emulator);
+                }
+
+                let mut pinned = self.assemble_target == Some(self.active);
+                if ui
+                    .checkbox(&mut pinned, "Always compile this tab")
+                    .on_hover_text("Keep compiling this tab (marked ⚙) even when you switch to another one, handy for a main file with others open for reference.")
+                    .changed()
+                {
+                    self.assemble_target = pinned.then_some(self.active);
                 }
             });
 
             // Decrease fade every tick
             if self.fade > 0.0 {
                 self.fade = (self.fade - 0.04).max(0.0);
+                ui.ctx().request_repaint();
             }
         });
     }
 
     fn title(&self) -> String {
-        "Editor".to_string()
+        match self.documents.get(self.active) {
+            Some(doc) if doc.dirty => format!("Editor - {}*", doc.name),
+            Some(doc) => format!("Editor - {}", doc.name),
+            None => "Editor".to_string(),
+        }
     }
 
     fn children() -> PaneTree {
@@ -157,3 +711,38 @@ impl PaneDisplay for EditorPane {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_recompile_after_edit_moves_breakpoints() {
+        let mut emulator = Emulator::new();
+        let mut editor = EditorPane {
+            documents: vec![Document::new(
+                "main.asm",
+                ".ORIG x3000\nADD R0, R0, #1\nADD R1, R1, #1\nHALT\n.END",
+            )],
+            ..Default::default()
+        };
+        editor.documents[0].breakpoints.insert(3);
+        editor.compile(0, &mut emulator);
+        assert_eq!(emulator.breakpoints, HashSet::from([0x3001]));
+
+        // a line goes in above the breakpoint, which moves down with its code
+        editor.documents[0].contents =
+            ".ORIG x3000\nADD R2, R2, #1\nADD R0, R0, #1\nADD R1, R1, #1\nHALT\n.END".to_owned();
+        editor.documents[0].breakpoints = BTreeSet::from([4]);
+        editor.compile(0, &mut emulator);
+        assert_eq!(
+            emulator.breakpoints,
+            HashSet::from([0x3002]),
+            "The old program's breakpoint should be gone"
+        );
+
+        editor.sync_breakpoints(&emulator);
+        assert_eq!(editor.documents[0].breakpoints, BTreeSet::from([4]));
+    }
+}
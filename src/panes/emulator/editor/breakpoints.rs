@@ -0,0 +1,169 @@
+//! Breakpoints set from the editor gutter. The document remembers them by line so they stick
+//! to the code while it is being edited, and they get turned into addresses for the emulator
+//! whenever the document is compiled.
+
+use std::collections::BTreeSet;
+
+use crate::theme::ThemeSettings;
+
+/// Which (1-based) line a char index is on
+pub fn line_at(source: &str, char_index: usize) -> usize {
+    source
+        .chars()
+        .take(char_index)
+        .filter(|&c| c == '\n')
+        .count()
+        + 1
+}
+
+/// Char index of the start of a (1-based) line
+fn line_start(source: &str, line: usize) -> usize {
+    source
+        .split('\n')
+        .take(line.saturating_sub(1))
+        .map(|text| text.chars().count() + 1)
+        .sum()
+}
+
+/// Move breakpoint lines to where their code ended up after an edit.
+/// Lines above the edit stay put, lines below it move with it and lines that got deleted lose their breakpoint.
+pub fn follow_edit(old: &str, new: &str, lines: &BTreeSet<usize>) -> BTreeSet<usize> {
+    let old_lines: Vec<&str> = old.split('\n').collect();
+    let new_lines: Vec<&str> = new.split('\n').collect();
+    let shortest = old_lines.len().min(new_lines.len());
+
+    let prefix = old_lines
+        .iter()
+        .zip(&new_lines)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines
+        .iter()
+        .rev()
+        .zip(new_lines.iter().rev())
+        .take(shortest - prefix)
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_changed_end = old_lines.len() - suffix;
+    let new_changed_end = new_lines.len() - suffix;
+
+    lines
+        .iter()
+        .filter_map(|&line| {
+            let index = line - 1;
+            if index < prefix {
+                Some(index)
+            } else if index >= old_changed_end {
+                Some(index + new_changed_end - old_changed_end)
+            } else if index < new_changed_end {
+                // edited in place
+                Some(index)
+            } else {
+                // deleted
+                None
+            }
+        })
+        .map(|index| index + 1)
+        .collect()
+}
+
+/// Draw breakpoint dots and the executing line, returns the line the user clicked in the gutter (if any)
+pub fn gutter(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    gutter: egui::Rect,
+    source: &str,
+    breakpoints: &BTreeSet<usize>,
+    executing_line: Option<usize>,
+    theme: &ThemeSettings,
+) -> Option<usize> {
+    let row = |line: usize| {
+        let rect = output
+            .galley
+            .pos_from_cursor(egui::text::CCursor::new(line_start(source, line)))
+            .translate(output.galley_pos.to_vec2());
+        egui::Rect::from_x_y_ranges(gutter.left()..=output.response.rect.right(), rect.y_range())
+    };
+    let painter = ui.painter().with_clip_rect(ui.clip_rect());
+    let line_count = source.split('\n').count();
+
+    for &line in breakpoints.iter().filter(|&&line| line <= line_count) {
+        let rect = row(line);
+        painter.rect_filled(rect, 0.0, theme.breakpoint_bg.gamma_multiply(0.3));
+        painter.circle_filled(
+            egui::pos2(gutter.center().x, rect.center().y),
+            gutter.width().min(rect.height()) / 3.0,
+            theme.accent_color_negative,
+        );
+    }
+
+    if let Some(line) = executing_line.filter(|&line| line <= line_count) {
+        let rect = row(line);
+        painter.rect_filled(rect, 0.0, theme.pc_line_bg.gamma_multiply(0.3));
+        let x = gutter.center().x;
+        let y = rect.center().y;
+        let size = gutter.width().min(rect.height()) / 3.0;
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                egui::pos2(x - size, y - size),
+                egui::pos2(x + size, y),
+                egui::pos2(x - size, y + size),
+            ],
+            theme.accent_color_positive,
+            egui::Stroke::NONE,
+        ));
+    }
+
+    let response = ui
+        .interact(
+            gutter,
+            output.response.id.with("gutter"),
+            egui::Sense::click(),
+        )
+        .on_hover_cursor(egui::CursorIcon::PointingHand)
+        .on_hover_text("Click to toggle a breakpoint");
+    if response.clicked() {
+        let pos = response.interact_pointer_pos()?;
+        let cursor = output
+            .galley
+            .cursor_from_pos(egui::vec2(0.0, pos.y - output.galley_pos.y));
+        return Some(line_at(source, cursor.index));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn follow(old: &str, new: &str, lines: &[usize]) -> Vec<usize> {
+        follow_edit(old, new, &lines.iter().copied().collect())
+            .into_iter()
+            .collect()
+    }
+
+    #[test]
+    fn lines_above_the_edit_stay() {
+        assert_eq!(follow("a\nb\nc", "a\nb\nc\nd", &[1, 3]), vec![1, 3]);
+    }
+
+    #[test]
+    fn lines_below_the_edit_move() {
+        assert_eq!(follow("a\nb\nc", "new\na\nb\nc", &[1, 3]), vec![2, 4]);
+        assert_eq!(follow("a\nb\nc\nd", "a\nd", &[4]), vec![2]);
+    }
+
+    #[test]
+    fn edited_lines_keep_and_deleted_lines_lose_it() {
+        assert_eq!(follow("a\nb\nc", "a\nbb\nc", &[2]), vec![2]);
+        assert_eq!(follow("a\nb\nc", "a\nc", &[2]), vec![]);
+    }
+
+    #[test]
+    fn line_at_counts_from_one() {
+        assert_eq!(line_at("a\nb\nc", 0), 1);
+        assert_eq!(line_at("a\nb\nc", 2), 2);
+        assert_eq!(line_at("a\nb\nc", 5), 3);
+    }
+}
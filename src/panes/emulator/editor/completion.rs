@@ -0,0 +1,456 @@
+//! Autocompletion for opcodes, directives, registers and the labels in the document.
+//! Everything goes through the real [`Lexer`] so we only ever suggest what the assembler will take.
+
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::emulator::ops::custom;
+use crate::emulator::parse::{Lexer, OpToken, Token};
+use crate::emulator::Isa;
+use crate::theme::ThemeSettings;
+
+/// Don't let the list get longer than this
+const MAX_ITEMS: usize = 12;
+
+/// Every built in mnemonic [`OpToken::from_str`] accepts, in the case we like to write them
+const MNEMONICS: &[&str] = &[
+    "ADD", "AND", "BR", "BRn", "BRz", "BRp", "BRnz", "BRnp", "BRzp", "BRnzp", "JMP", "JSR", "JSRR",
+    "LD", "LDI", "LDR", "LEA", "NOT", "RET", "RTI", "ST", "STI", "STR", "TRAP", "GETC", "OUT",
+    "PUTS", "IN", "PUTSP", "HALT", "LDB", "STB", "LDW", "STW", "XOR", "LSHF", "RSHFL", "RSHFA",
+];
+
+/// Every directive the parser knows
+const DIRECTIVES: &[(&str, &str)] = &[
+    (".ORIG", ".ORIG address"),
+    (".FILL", ".FILL value or label"),
+    (".BLKW", ".BLKW count"),
+    (".STRINGZ", ".STRINGZ \"text\""),
+    (".END", ".END"),
+];
+
+/// What operands an op wants
+pub fn signature(op: &OpToken) -> &'static str {
+    match op {
+        OpToken::Add => "ADD DR, SR1, SR2  |  ADD DR, SR1, imm5",
+        OpToken::And => "AND DR, SR1, SR2  |  AND DR, SR1, imm5",
+        OpToken::Br(..) => "BRnzp PCoffset9 (label)",
+        OpToken::Jmp => "JMP BaseR",
+        OpToken::Jsr => "JSR PCoffset11 (label)",
+        OpToken::Jsrr => "JSRR BaseR",
+        OpToken::Ld => "LD DR, PCoffset9 (label)",
+        OpToken::Ldi => "LDI DR, PCoffset9 (label)",
+        OpToken::Ldr => "LDR DR, BaseR, offset6",
+        OpToken::Lea => "LEA DR, PCoffset9 (label)",
+        OpToken::Not => "NOT DR, SR",
+        OpToken::Ret => "RET (JMP R7)",
+        OpToken::Rti => "RTI",
+        OpToken::St => "ST SR, PCoffset9 (label)",
+        OpToken::Sti => "STI SR, PCoffset9 (label)",
+        OpToken::Str => "STR SR, BaseR, offset6",
+        OpToken::Trap(None) => "TRAP trapvect8",
+        OpToken::Trap(Some(0x20)) => "GETC (TRAP x20) read a char into R0",
+        OpToken::Trap(Some(0x21)) => "OUT (TRAP x21) write the char in R0",
+        OpToken::Trap(Some(0x22)) => "PUTS (TRAP x22) write the string at R0",
+        OpToken::Trap(Some(0x23)) => "IN (TRAP x23) prompt for a char into R0",
+        OpToken::Trap(Some(0x24)) => "PUTSP (TRAP x24) write the packed string at R0",
+        OpToken::Trap(Some(0x25)) => "HALT (TRAP x25) stop the machine",
+        OpToken::Trap(Some(_)) => "TRAP trapvect8",
+        OpToken::Custom => "the custom instruction loaded for opcode 1101",
+        OpToken::Ldb => "LDB DR, BaseR, boffset6 (LC-3b)",
+        OpToken::Stb => "STB SR, BaseR, boffset6 (LC-3b)",
+        OpToken::Ldw => "LDW DR, BaseR, offset6 (LC-3b)",
+        OpToken::Stw => "STW SR, BaseR, offset6 (LC-3b)",
+        OpToken::Xor => "XOR DR, SR1, SR2  |  XOR DR, SR1, imm5 (LC-3b)",
+        OpToken::Lshf => "LSHF DR, SR, amount4 (LC-3b)",
+        OpToken::Rshfl => "RSHFL DR, SR, amount4 (LC-3b)",
+        OpToken::Rshfa => "RSHFA DR, SR, amount4 (LC-3b)",
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Opcode,
+    Directive,
+    Register,
+    Label,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    pub text: String,
+    pub kind: Kind,
+    pub detail: String,
+}
+
+/// What could finish off the word at the cursor
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Completions {
+    /// The (char) range of the half typed word, this is what gets replaced
+    pub range: Range<usize>,
+    pub items: Vec<Item>,
+    /// Operand hint for the op on this line
+    pub signature: Option<&'static str>,
+}
+
+pub(super) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '.' || c == '_'
+}
+
+/// The mnemonics that assemble on `isa`, with the custom instruction when one is loaded
+fn mnemonics(isa: Isa) -> Vec<(String, OpToken)> {
+    let custom = custom::loaded().map(|op| op.mnemonic.to_ascii_uppercase());
+    MNEMONICS
+        .iter()
+        .map(|&text| text.to_owned())
+        .chain(custom)
+        .filter_map(|text| {
+            let op = OpToken::from_str(&text).ok()?;
+            let assembles = match isa {
+                Isa::Lc3 => !op.lc3b_only(),
+                Isa::Lc3b => !op.lc3_only(),
+            };
+            assembles.then_some((text, op))
+        })
+        .collect()
+}
+
+/// Tokens for a single line. The lexer only calls something a label if it comes after an EOL so we give it one
+pub(super) fn lex_line(line: &str, isa: Isa) -> Option<Vec<Token>> {
+    Lexer::with_isa(&format!("\n{line}"), isa)
+        .tokenize()
+        .ok()
+        .map(|tokens| tokens.into_iter().map(|span| span.token).collect())
+}
+
+/// Every label defined in the source. Lines that don't lex are skipped so half typed code doesn't hide everything
+pub fn labels(source: &str, isa: Isa) -> Vec<String> {
+    let mut labels: Vec<String> = source
+        .split('\n')
+        .filter_map(|line| lex_line(line, isa))
+        .flatten()
+        .filter_map(|token| match token {
+            Token::Label(name) => Some(name),
+            _ => None,
+        })
+        .collect();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+/// Work out what is being typed at `cursor` (a char index) and what could go there
+pub fn complete(source: &str, cursor: usize, isa: Isa) -> Completions {
+    let chars: Vec<char> = source.chars().collect();
+    let cursor = cursor.min(chars.len());
+    let mut start = cursor;
+    while start > 0 && is_word_char(chars[start - 1]) {
+        start -= 1;
+    }
+    let prefix: String = chars[start..cursor].iter().collect();
+    let line_start = chars[..start]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1);
+    let before: String = chars[line_start..start].iter().collect();
+
+    let mut completions = Completions {
+        range: start..cursor,
+        ..Default::default()
+    };
+
+    // nothing to offer in comments, strings or after the lexer gives up
+    if before.contains(';') || before.matches('"').count() % 2 == 1 {
+        return completions;
+    }
+    let Some(tokens) = lex_line(&before, isa) else {
+        return completions;
+    };
+
+    let op = tokens.iter().find_map(|token| match token {
+        Token::Opcode(op) => Some(*op),
+        _ => None,
+    });
+    let directive = tokens
+        .iter()
+        .any(|token| matches!(token, Token::Directive(_)));
+    completions.signature = op.as_ref().map(signature);
+
+    if prefix.is_empty() {
+        return completions;
+    }
+
+    let mut items = Vec::new();
+    if op.is_none() && !directive {
+        // start of an instruction
+        items.extend(mnemonics(isa).into_iter().map(|(text, op)| Item {
+            text,
+            kind: Kind::Opcode,
+            detail: signature(&op).to_owned(),
+        }));
+        items.extend(DIRECTIVES.iter().map(|&(text, detail)| Item {
+            text: text.to_owned(),
+            kind: Kind::Directive,
+            detail: detail.to_owned(),
+        }));
+    } else {
+        // operands
+        if op.is_some() {
+            items.extend((0..8).map(|r| Item {
+                text: format!("R{r}"),
+                kind: Kind::Register,
+                detail: "register".to_owned(),
+            }));
+        }
+        items.extend(labels(source, isa).into_iter().map(|text| Item {
+            text,
+            kind: Kind::Label,
+            detail: "label".to_owned(),
+        }));
+    }
+
+    let upper = prefix.to_ascii_uppercase();
+    items.retain(|item| {
+        let text = item.text.to_ascii_uppercase();
+        text.starts_with(&upper) && text != upper
+    });
+    items.truncate(MAX_ITEMS);
+    completions.items = items;
+    completions
+}
+
+/// The popup state for one editor
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Completer {
+    completions: Completions,
+    selected: usize,
+    /// Only pop up while typing, moving the cursor around or pressing escape closes it
+    active: bool,
+    last_cursor: Option<usize>,
+    /// An item clicked in the popup, applied next frame
+    clicked: Option<usize>,
+    /// The text edit, so we can put its cursor after what we insert
+    editor_id: Option<egui::Id>,
+}
+
+impl Completer {
+    /// Grab the keys we need before the text edit sees them. Returns the item to insert, if any
+    pub fn take_keys(&mut self, ui: &egui::Ui) -> Option<usize> {
+        if let Some(clicked) = self.clicked.take() {
+            return Some(clicked);
+        }
+        let focused = self
+            .editor_id
+            .is_some_and(|id| ui.memory(|memory| memory.has_focus(id)));
+        let count = self.completions.items.len();
+        if !self.active || !focused || count == 0 {
+            return None;
+        }
+        ui.input_mut(|input| {
+            use egui::{Key, Modifiers};
+            if input.consume_key(Modifiers::NONE, Key::ArrowDown) {
+                self.selected = (self.selected + 1) % count;
+            }
+            if input.consume_key(Modifiers::NONE, Key::ArrowUp) {
+                self.selected = (self.selected + count - 1) % count;
+            }
+            if input.consume_key(Modifiers::NONE, Key::Escape) {
+                self.active = false;
+            }
+            let accept = input.consume_key(Modifiers::NONE, Key::Tab)
+                || input.consume_key(Modifiers::NONE, Key::Enter);
+            (self.active && accept).then_some(self.selected)
+        })
+    }
+
+    /// Put item `index` into the text in place of the half typed word
+    pub fn accept(&mut self, index: usize, contents: &mut String, ctx: &egui::Context) {
+        let Some(item) = self.completions.items.get(index) else {
+            return;
+        };
+        let range = self.completions.range.clone();
+        let byte = |char_index: usize| {
+            contents
+                .char_indices()
+                .nth(char_index)
+                .map_or(contents.len(), |(i, _)| i)
+        };
+        let bytes = byte(range.start)..byte(range.end);
+        contents.replace_range(bytes, &item.text);
+
+        let cursor = range.start + item.text.chars().count();
+        if let Some(id) = self.editor_id {
+            if let Some(mut state) = egui::text_edit::TextEditState::load(ctx, id) {
+                state
+                    .cursor
+                    .set_char_range(Some(egui::text::CCursorRange::one(
+                        egui::text::CCursor::new(cursor),
+                    )));
+                state.store(ctx, id);
+            }
+            ctx.memory_mut(|memory| memory.request_focus(id));
+        }
+        self.active = false;
+        self.last_cursor = Some(cursor);
+    }
+
+    /// Look at what the user just did and show the popup if they are typing
+    pub fn show(
+        &mut self,
+        ui: &egui::Ui,
+        output: &egui::text_edit::TextEditOutput,
+        contents: &str,
+        isa: Isa,
+        theme: &ThemeSettings,
+    ) {
+        self.editor_id = Some(output.response.id);
+        let cursor = output
+            .cursor_range
+            .filter(|range| range.is_empty() && output.response.has_focus())
+            .map(|range| range.primary.index);
+
+        if output.response.changed() {
+            self.active = true;
+        } else if cursor != self.last_cursor {
+            self.active = false;
+        }
+        self.last_cursor = cursor;
+
+        let Some(cursor) = cursor.filter(|_| self.active) else {
+            self.completions = Completions::default();
+            return;
+        };
+        let completions = complete(contents, cursor, isa);
+        if completions.items != self.completions.items {
+            self.selected = 0;
+        }
+        self.completions = completions;
+
+        if self.completions.items.is_empty() && self.completions.signature.is_none() {
+            return;
+        }
+        let at = output
+            .galley
+            .pos_from_cursor(egui::text::CCursor::new(self.completions.range.start))
+            .translate(output.galley_pos.to_vec2())
+            .left_bottom();
+        self.clicked = popup(ui, at, &self.completions, self.selected, theme);
+    }
+}
+
+/// Draw the list under the cursor. Returns the index of an item that was clicked
+fn popup(
+    ui: &egui::Ui,
+    at: egui::Pos2,
+    completions: &Completions,
+    selected: usize,
+    theme: &ThemeSettings,
+) -> Option<usize> {
+    let mut clicked = None;
+    egui::Area::new(ui.id().with("completions"))
+        .order(egui::Order::Foreground)
+        .fixed_pos(at)
+        .show(ui.ctx(), |ui| {
+            egui::Frame::popup(ui.style()).show(ui, |ui| {
+                if let Some(signature) = completions.signature {
+                    ui.label(egui::RichText::new(signature).monospace().weak());
+                }
+                for (i, item) in completions.items.iter().enumerate() {
+                    let colour = match item.kind {
+                        Kind::Opcode => theme.editor_opcode_color,
+                        Kind::Directive => theme.editor_directive_color,
+                        Kind::Register => theme.editor_register_color,
+                        Kind::Label => theme.editor_label_color,
+                    };
+                    let text = egui::RichText::new(&item.text).monospace().color(colour);
+                    let response = ui
+                        .selectable_label(i == selected, text)
+                        .on_hover_text(&item.detail);
+                    if i == selected {
+                        ui.label(egui::RichText::new(&item.detail).small().weak());
+                    }
+                    if response.clicked() {
+                        clicked = Some(i);
+                    }
+                }
+            });
+        });
+    clicked
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(completions: &Completions) -> Vec<&str> {
+        completions
+            .items
+            .iter()
+            .map(|item| item.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn every_mnemonic_is_an_op() {
+        for mnemonic in MNEMONICS {
+            assert!(
+                OpToken::from_str(mnemonic).is_ok(),
+                "{mnemonic} is not accepted by the lexer"
+            );
+        }
+    }
+
+    #[test]
+    fn opcodes_at_the_start_of_a_line() {
+        let source = ".ORIG x3000\nLOOP ld";
+        let completions = complete(source, source.len(), Isa::Lc3);
+        assert_eq!(texts(&completions), vec!["LDI", "LDR"]);
+        assert_eq!(completions.range, source.len() - 2..source.len());
+    }
+
+    #[test]
+    fn registers_and_labels_as_operands() {
+        let source = "LOOP ADD R1, R1, #1\nRESULT .FILL 0\nLD R";
+        let completions = complete(source, source.len(), Isa::Lc3);
+        assert_eq!(completions.signature, Some(signature(&OpToken::Ld)));
+        assert!(texts(&completions).contains(&"R0"));
+        assert!(texts(&completions).contains(&"RESULT"));
+        assert!(!texts(&completions).contains(&"LOOP"));
+    }
+
+    #[test]
+    fn opcodes_for_the_machine() {
+        let _custom = custom::test_lock();
+        custom::set_loaded(Some(
+            custom::CustomInstruction::from_ron(include_str!(
+                "../../../../assets/custom_ops/mul.ron"
+            ))
+            .unwrap(),
+        ));
+        assert_eq!(
+            texts(&complete("L", 1, Isa::Lc3)),
+            vec!["LD", "LDI", "LDR", "LEA"]
+        );
+        assert_eq!(
+            texts(&complete("L", 1, Isa::Lc3b)),
+            vec!["LEA", "LDB", "LDW", "LSHF"]
+        );
+        assert_eq!(texts(&complete("LOOP M", 6, Isa::Lc3)), vec!["MUL"]);
+        assert!(complete("LOOP M", 6, Isa::Lc3b).items.is_empty());
+        custom::set_loaded(None);
+        assert!(complete("LOOP M", 6, Isa::Lc3).items.is_empty());
+    }
+
+    #[test]
+    fn nothing_in_comments() {
+        let source = "ADD R1, R1, #1 ; ad";
+        assert!(complete(source, source.len(), Isa::Lc3).items.is_empty());
+    }
+
+    #[test]
+    fn labels_skip_broken_lines() {
+        assert_eq!(
+            labels("A .FILL 1\nB .STRINGZ \"oops\nC HALT", Isa::Lc3),
+            vec!["A".to_owned(), "C".to_owned()]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+//! Squiggles under whatever the assembler is unhappy about. We re-run the lexer and parser
+//! shortly after the user stops typing so they don't have to press compile to find typos.
+
+use std::ops::Range;
+
+use crate::emulator::parse::{CompilationArtifacts, ParseError, ParseOutput};
+use crate::emulator::{Emulator, Isa};
+use crate::theme::ThemeSettings;
+
+/// How long (in seconds) to wait after the last keystroke before re-checking
+pub const DEBOUNCE: f64 = 0.4;
+
+/// Something wrong with a line of the source
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    /// 1-based like the parser
+    pub line: usize,
+    /// Where the offending token starts, `None` if we only know the line
+    pub column: Option<usize>,
+    pub message: String,
+    /// It still assembles, this is only something that looks wrong
+    pub warning: bool,
+}
+
+impl Diagnostic {
+    pub fn from_error(error: &ParseError) -> Self {
+        match error {
+            ParseError::TokenizeError(message, line) => Self {
+                line: *line,
+                column: None,
+                message: format!("Syntax error: {message}"),
+                warning: false,
+            },
+            ParseError::GenerationError(message, token) => Self {
+                line: token.line,
+                column: Some(token.column),
+                message: message.clone(),
+                warning: false,
+            },
+        }
+    }
+
+    /// The characters (indexes into the whole source) to underline
+    pub fn char_range(&self, source: &str) -> Range<usize> {
+        let line_start: usize = source
+            .split('\n')
+            .take(self.line.saturating_sub(1))
+            .map(|line| line.chars().count() + 1)
+            .sum();
+        let Some(text) = source.split('\n').nth(self.line.saturating_sub(1)) else {
+            return line_start..line_start;
+        };
+        let chars: Vec<char> = text.chars().collect();
+        // annotation warnings point at their comment, all of that is the problem
+        if let Some(start) = self.column.filter(|&c| chars.get(c) == Some(&';')) {
+            let end = chars
+                .iter()
+                .rposition(|c| !c.is_whitespace())
+                .map_or(start, |i| i + 1);
+            return line_start + start..line_start + end;
+        }
+        // ignore the comment, nobody wants their comment underlined
+        let code_end = chars.iter().position(|&c| c == ';').unwrap_or(chars.len());
+
+        let token = self.column.filter(|&c| c < code_end).and_then(|start| {
+            let len = chars[start..code_end]
+                .iter()
+                .take_while(|c| !c.is_whitespace() && **c != ',')
+                .count();
+            (len > 0).then_some(start..start + len)
+        });
+
+        // if we don't know (or the error points at the end of the line) do the whole line
+        let range = token.unwrap_or_else(|| {
+            let start = chars[..code_end]
+                .iter()
+                .position(|c| !c.is_whitespace())
+                .unwrap_or(0);
+            let end = chars[..code_end]
+                .iter()
+                .rposition(|c| !c.is_whitespace())
+                .map_or(start, |i| i + 1);
+            start..end
+        });
+        line_start + range.start..line_start + range.end
+    }
+}
+
+/// Run the assembler over `source` without touching the emulator, with any warnings if it assembles
+pub fn check(source: &str, isa: Isa) -> Result<(ParseOutput, Vec<Diagnostic>), Diagnostic> {
+    let mut artifacts = CompilationArtifacts::default();
+    let assembled = Emulator::parse_program_for(isa, source, Some(&mut artifacts))
+        .map_err(|e| Diagnostic::from_error(&e))?;
+    Ok((assembled, warnings(&artifacts)))
+}
+
+/// The warnings from a compile, like annotations that didn't make sense
+pub fn warnings(artifacts: &CompilationArtifacts) -> Vec<Diagnostic> {
+    artifacts
+        .warnings
+        .iter()
+        .map(|warning| Diagnostic {
+            warning: true,
+            ..Diagnostic::from_error(warning)
+        })
+        .collect()
+}
+
+/// Draw the squiggle and the gutter marker, with the message on hover for both
+pub fn paint(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    gutter: egui::Rect,
+    source: &str,
+    diagnostic: &Diagnostic,
+    theme: &ThemeSettings,
+) {
+    let colour = if diagnostic.warning {
+        theme.warn_fg_color
+    } else {
+        theme.error_fg_color
+    };
+    let range = diagnostic.char_range(source);
+    let to_screen = |index| {
+        output
+            .galley
+            .pos_from_cursor(egui::text::CCursor::new(index))
+            .translate(output.galley_pos.to_vec2())
+    };
+    let start = to_screen(range.start);
+    let end = to_screen(range.end);
+    let painter = ui
+        .painter()
+        .with_clip_rect(output.text_clip_rect.union(gutter));
+
+    // zigzag along the bottom of the token
+    let right = end.left().max(start.left() + 6.0);
+    let bottom = start.bottom() - 1.0;
+    let mut points = Vec::new();
+    let mut x = start.left();
+    let mut up = false;
+    while x < right {
+        points.push(egui::pos2(x, if up { bottom - 2.0 } else { bottom }));
+        x += 2.0;
+        up = !up;
+    }
+    points.push(egui::pos2(right, if up { bottom - 2.0 } else { bottom }));
+    painter.add(egui::Shape::line(points, egui::Stroke::new(1.0, colour)));
+
+    let marker = egui::Rect::from_center_size(
+        egui::pos2(gutter.center().x, start.center().y),
+        egui::vec2(gutter.width(), start.height()),
+    );
+    // square so it can't be mistaken for a breakpoint
+    let size = marker.width().min(marker.height()) / 2.0;
+    painter.rect_filled(
+        egui::Rect::from_center_size(marker.center(), egui::vec2(size, size)),
+        0.0,
+        colour,
+    );
+
+    let squiggle = egui::Rect::from_x_y_ranges(start.left()..=right, start.y_range());
+    for (rect, name) in [(marker, "marker"), (squiggle, "squiggle")] {
+        ui.interact(rect, output.response.id.with(name), egui::Sense::hover())
+            .on_hover_text(
+                egui::RichText::new(format!("Line {}: {}", diagnostic.line, diagnostic.message))
+                    .color(colour),
+            );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn underlines_the_bad_token() {
+        let source = ".ORIG x3000\nADD R1, R2, R9\nHALT\n.END";
+        let diagnostic = check(source, Isa::Lc3).expect_err("R9 is not a register");
+        assert_eq!(diagnostic.line, 2);
+        let range = diagnostic.char_range(source);
+        assert_eq!(
+            source
+                .chars()
+                .skip(range.start)
+                .take(range.len())
+                .collect::<String>(),
+            "R9"
+        );
+    }
+
+    #[test]
+    fn whole_line_without_a_column() {
+        let source = ".ORIG x3000\n  LEA R0, \"oops ; comment\nHALT";
+        let diagnostic = Diagnostic {
+            line: 2,
+            column: None,
+            message: String::new(),
+            warning: false,
+        };
+        let range = diagnostic.char_range(source);
+        assert_eq!(
+            source
+                .chars()
+                .skip(range.start)
+                .take(range.len())
+                .collect::<String>(),
+            "LEA R0, \"oops"
+        );
+    }
+
+    #[test]
+    fn clean_program_has_no_diagnostic() {
+        assert!(check(".ORIG x3000\nHALT\n.END", Isa::Lc3).is_ok());
+    }
+
+    #[test]
+    fn bad_annotation_is_a_warning_on_its_comment() {
+        let source = ".ORIG x3000\nHALT ;@region oops x3010 x3000\n.END";
+        let (_, warnings) = check(source, Isa::Lc3).expect("annotations don't stop it assembling");
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].warning);
+        assert_eq!(warnings[0].line, 2);
+        let range = warnings[0].char_range(source);
+        assert_eq!(
+            source
+                .chars()
+                .skip(range.start)
+                .take(range.len())
+                .collect::<String>(),
+            ";@region oops x3010 x3000"
+        );
+    }
+}
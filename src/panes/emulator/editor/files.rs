@@ -0,0 +1,79 @@
+//! Where editor documents get saved. On desktop that is just a path on disk,
+//! in the browser there is no file system so we keep them in local storage under their name.
+
+/// Read a saved document
+pub fn load(path: &str) -> Result<String, String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::fs::read_to_string(path).map_err(|e| format!("Could not open {path}: {e}"))
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        local_storage()?
+            .get_item(&storage_key(path))
+            .map_err(|e| format!("Could not open {path}: {e:?}"))?
+            .ok_or_else(|| format!("No saved file called {path}"))
+    }
+}
+
+/// Write a document, replacing whatever was there
+pub fn save(path: &str, contents: &str) -> Result<(), String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::fs::write(path, contents).map_err(|e| format!("Could not save {path}: {e}"))
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        local_storage()?
+            .set_item(&storage_key(path), contents)
+            .map_err(|e| format!("Could not save {path}: {e:?}"))
+    }
+}
+
+/// The name to show in the tab for a path
+pub fn display_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned())
+}
+
+#[cfg(target_arch = "wasm32")]
+const KEY_PREFIX: &str = "lc3_file:";
+
+#[cfg(target_arch = "wasm32")]
+fn storage_key(name: &str) -> String {
+    format!("{KEY_PREFIX}{name}")
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, String> {
+    web_sys::window()
+        .ok_or("No window to get local storage from")?
+        .local_storage()
+        .map_err(|e| format!("{e:?}"))?
+        .ok_or_else(|| "Local storage is not available in this browser".to_owned())
+}
+
+/// Everything saved in the browser, for the open menu
+#[cfg(target_arch = "wasm32")]
+pub fn list() -> Vec<String> {
+    let Ok(storage) = local_storage() else {
+        return Vec::new();
+    };
+    let len = storage.length().unwrap_or(0);
+    let mut names: Vec<String> = (0..len)
+        .filter_map(|i| storage.key(i).ok().flatten())
+        .filter_map(|key| key.strip_prefix(KEY_PREFIX).map(str::to_owned))
+        .collect();
+    names.sort();
+    names
+}
+
+/// Forget a file saved in the browser
+#[cfg(target_arch = "wasm32")]
+pub fn delete(name: &str) -> Result<(), String> {
+    local_storage()?
+        .remove_item(&storage_key(name))
+        .map_err(|e| format!("Could not delete {name}: {e:?}"))
+}
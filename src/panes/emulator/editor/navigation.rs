@@ -0,0 +1,236 @@
+//! Hover info, go to definition and find references
+
+use std::ops::Range;
+use std::str::FromStr;
+
+use super::completion::{is_word_char, lex_line};
+use crate::emulator::parse::{pc_offset, OpToken, ParseOutput, Token};
+use crate::emulator::Isa;
+use crate::panes::emulator::help::{instruction_segments, render_binary_representation_view};
+use crate::theme::ThemeSettings;
+
+/// The word around a char index and its range
+pub fn word_at(source: &str, char_index: usize) -> Option<(Range<usize>, String)> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut start = char_index.min(chars.len());
+    let mut end = start;
+    while start > 0 && is_word_char(chars[start - 1]) {
+        start -= 1;
+    }
+    while end < chars.len() && is_word_char(chars[end]) {
+        end += 1;
+    }
+    (start < end).then(|| (start..end, chars[start..end].iter().collect()))
+}
+
+/// Lines (1-based) that define and use `label`
+pub fn find_label(source: &str, label: &str, isa: Isa) -> (Option<usize>, Vec<usize>) {
+    let mut definition = None;
+    let mut uses = Vec::new();
+    for (i, line) in source.split('\n').enumerate() {
+        for token in lex_line(line, isa).unwrap_or_default() {
+            match token {
+                Token::Label(name) if name == label => {
+                    definition.get_or_insert(i + 1);
+                }
+                Token::LabelRef(name) if name == label => {
+                    uses.push(i + 1);
+                    break;
+                }
+                _ => {}
+            }
+        }
+    }
+    (definition, uses)
+}
+
+/// The opcode on a line, if it has one
+fn line_op(source: &str, line: usize, isa: Isa) -> Option<OpToken> {
+    let text = source.split('\n').nth(line.checked_sub(1)?)?;
+    lex_line(text, isa)?
+        .into_iter()
+        .find_map(|token| match token {
+            Token::Opcode(op) => Some(op),
+            _ => None,
+        })
+}
+
+/// How many bits of PC offset an op has, for the ones that take a label
+fn offset_bits(op: &OpToken) -> Option<u32> {
+    match op {
+        OpToken::Br(..)
+        | OpToken::Ld
+        | OpToken::Ldi
+        | OpToken::Lea
+        | OpToken::St
+        | OpToken::Sti => Some(9),
+        OpToken::Jsr => Some(11),
+        _ => None,
+    }
+}
+
+/// What to say about `label` used on `line`
+pub struct LabelInfo {
+    pub address: usize,
+    /// The PC offset from the line and how many bits it has to fit in
+    pub offset: Option<(i32, u32)>,
+}
+
+impl LabelInfo {
+    pub fn new(assembled: &ParseOutput, source: &str, label: &str, line: usize) -> Option<Self> {
+        let address = *assembled.labels.get(label)?;
+        let offset = line_op(source, line, assembled.isa)
+            .as_ref()
+            .and_then(offset_bits)
+            .zip(assembled.line_to_address.get(&line))
+            .and_then(|(bits, &from)| {
+                Some((i32::from(pc_offset(assembled.isa, from, address)?), bits))
+            });
+        Some(Self { address, offset })
+    }
+
+    /// Can the offset be encoded
+    pub fn in_range(&self) -> bool {
+        self.offset.map_or(true, |(offset, bits)| {
+            let half = 1 << (bits - 1);
+            (-half..half).contains(&offset)
+        })
+    }
+}
+
+/// Something to draw in a tooltip
+pub type HoverContents = Box<dyn FnOnce(&mut egui::Ui)>;
+
+/// Tooltip contents for the word at `char_index`. `None` if there is nothing worth saying
+pub fn hover_ui(
+    assembled: &ParseOutput,
+    source: &str,
+    char_index: usize,
+    theme: &ThemeSettings,
+) -> Option<HoverContents> {
+    let (range, word) = word_at(source, char_index)?;
+    let line = super::breakpoints::line_at(source, range.start);
+    let theme = theme.clone();
+
+    if let Some(info) = LabelInfo::new(assembled, source, &word, line) {
+        return Some(Box::new(move |ui: &mut egui::Ui| {
+            ui.label(egui::RichText::new(&word).monospace().strong());
+            ui.label(format!("Address: x{:04X}", info.address));
+            if let Some((offset, bits)) = info.offset {
+                ui.label(format!("PC offset from here: {offset} ({bits} bits)"));
+                if !info.in_range() {
+                    ui.colored_label(
+                        theme.warn_fg_color,
+                        format!(
+                            "Too far away! A {bits} bit offset only reaches {} to {}",
+                            -(1 << (bits - 1)),
+                            (1 << (bits - 1)) - 1
+                        ),
+                    );
+                }
+            }
+            ui.label(egui::RichText::new("Ctrl+click to go to it").weak());
+        }));
+    }
+
+    OpToken::from_str(&word).ok()?;
+    let address = *assembled.line_to_address.get(&line)?;
+    let encoded = *assembled
+        .machine_code
+        .get(address.checked_sub(assembled.orig_address)? / assembled.isa.word_size())?;
+    Some(Box::new(move |ui: &mut egui::Ui| {
+        ui.label(
+            egui::RichText::new(format!("x{address:04X}: x{encoded:04X}"))
+                .monospace()
+                .strong(),
+        );
+        render_binary_representation_view(ui, instruction_segments(encoded, &theme), &theme);
+    }))
+}
+
+/// Put the cursor at the start of `line` and scroll to it
+pub fn jump_to_line(
+    ui: &egui::Ui,
+    output: &egui::text_edit::TextEditOutput,
+    source: &str,
+    line: usize,
+) {
+    let index: usize = source
+        .split('\n')
+        .take(line.saturating_sub(1))
+        .map(|text| text.chars().count() + 1)
+        .sum();
+    let cursor = egui::text::CCursor::new(index);
+    let id = output.response.id;
+    if let Some(mut state) = egui::text_edit::TextEditState::load(ui.ctx(), id) {
+        state
+            .cursor
+            .set_char_range(Some(egui::text::CCursorRange::one(cursor)));
+        state.store(ui.ctx(), id);
+    }
+    ui.memory_mut(|memory| memory.request_focus(id));
+    let rect = output
+        .galley
+        .pos_from_cursor(cursor)
+        .translate(output.galley_pos.to_vec2());
+    ui.scroll_to_rect(rect, Some(egui::Align::Center));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{Emulator, Isa};
+
+    const SOURCE: &str =
+        ".ORIG x3000\nLOOP ADD R1, R1, #-1\nBRp LOOP\nLD R2, DATA\nHALT\nDATA .FILL LOOP\n.END";
+
+    #[test]
+    fn finds_definition_and_uses() {
+        assert_eq!(find_label(SOURCE, "LOOP", Isa::Lc3), (Some(2), vec![3, 6]));
+        assert_eq!(find_label(SOURCE, "NOPE", Isa::Lc3), (None, vec![]));
+    }
+
+    #[test]
+    fn label_offsets() {
+        let assembled = Emulator::parse_program(SOURCE, None).unwrap();
+        let info = LabelInfo::new(&assembled, SOURCE, "LOOP", 3).unwrap();
+        assert_eq!(info.address, 0x3000);
+        assert_eq!(info.offset, Some((-2, 9)));
+        assert!(info.in_range());
+
+        let info = LabelInfo::new(&assembled, SOURCE, "LOOP", 6).unwrap();
+        assert_eq!(info.offset, None, ".FILL doesn't use an offset");
+
+        let far = LabelInfo {
+            address: 0x4000,
+            offset: Some((300, 9)),
+        };
+        assert!(!far.in_range());
+    }
+
+    #[test]
+    fn lc3b_offsets_count_words() {
+        let source = ".ORIG x3000\nLOOP ADD R1, R1, #-1\nBRp LOOP\nHALT\n.END";
+        let assembled = Emulator::parse_program_for(Isa::Lc3b, source, None).unwrap();
+        let info = LabelInfo::new(&assembled, source, "LOOP", 3).unwrap();
+        assert_eq!(info.address, 0x3000);
+        assert_eq!(info.offset, Some((-2, 9)));
+    }
+
+    #[test]
+    fn too_far_still_has_label_info() {
+        let source = ".ORIG x3000\nBR FAR\n.BLKW #300\nFAR HALT\n.END";
+        assert!(Emulator::parse_program(source, None).is_err());
+        let layout = Emulator::layout_program(Isa::Lc3, source).unwrap();
+        let info = LabelInfo::new(&layout, source, "FAR", 2).unwrap();
+        assert_eq!(info.address, 0x3000 + 301);
+        assert_eq!(info.offset, Some((300, 9)));
+        assert!(!info.in_range());
+    }
+
+    #[test]
+    fn word_at_finds_the_whole_word() {
+        assert_eq!(word_at("BRp LOOP", 6), Some((4..8, "LOOP".to_owned())));
+        assert_eq!(word_at("BRp  LOOP", 4), None);
+    }
+}
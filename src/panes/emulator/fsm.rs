@@ -0,0 +1,250 @@
+use crate::emulator::fsm::{path, transition, FsmState};
+use crate::emulator::{CpuState, Emulator, Isa};
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::{vec2, RichText, Stroke};
+use serde::{Deserialize, Serialize};
+
+use super::EmulatorPane;
+
+/// Most micro steps an FSM step will take, in case something never leaves a phase
+const MAX_MICRO_STEPS: usize = 16;
+
+/// The textbook control FSM, following the current instruction through its numbered states
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FsmPane {
+    /// Which of the states finishing in the next phase we are up to, they all run together
+    #[serde(skip)]
+    cursor: usize,
+    /// The step the cursor belongs to
+    #[serde(skip)]
+    cursor_for: Option<(u64, usize, bool)>,
+    /// Why the last step stopped short
+    #[serde(skip)]
+    error: Option<String>,
+}
+
+/// Where the machine is, changes whenever a phase runs
+fn position(emulator: &Emulator) -> (u64, usize, bool) {
+    (
+        emulator.instruction_count,
+        emulator.next_phase(),
+        matches!(emulator.cpu_state, CpuState::Fetch),
+    )
+}
+
+impl FsmPane {
+    /// The index in `states` of the state the FSM is in
+    fn current(&self, states: &[FsmState], next_phase: usize) -> Option<usize> {
+        let first = states.iter().position(|s| s.phase >= next_phase)?;
+        let waiting = states[first..]
+            .iter()
+            .take_while(|s| s.phase == states[first].phase)
+            .count();
+        Some(first + self.cursor.min(waiting - 1))
+    }
+
+    /// Move on one state, running the phase once every state that finishes in it has been stepped through
+    fn step(&mut self, emulator: &mut Emulator, states: &[FsmState], current: usize) {
+        let phase = states[current].phase;
+        if states.get(current + 1).is_some_and(|s| s.phase == phase) {
+            self.cursor += 1;
+            return;
+        }
+
+        let was_running = emulator.running();
+        emulator.start_running();
+        for _ in 0..MAX_MICRO_STEPS {
+            self.error = emulator.micro_step().err();
+            if self.error.is_some()
+                || !emulator.running()
+                || matches!(emulator.cpu_state, CpuState::Fetch)
+                || emulator.next_phase() > phase
+            {
+                break;
+            }
+        }
+        if !was_running && emulator.running() {
+            emulator.stop_running();
+        }
+        self.cursor = 0;
+    }
+}
+
+impl PaneDisplay for FsmPane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        if emulator.isa != Isa::Lc3 {
+            ui.label(
+                RichText::new("These are the LC-3's states, the LC-3b's FSM isn't drawn.")
+                    .color(theme.secondary_text_color),
+            );
+            return;
+        }
+        if self.cursor_for != Some(position(emulator)) {
+            self.cursor = 0;
+            self.cursor_for = Some(position(emulator));
+        }
+        let inputs = emulator.fsm_inputs();
+        let states = path(&inputs);
+        // the microsequencer knows exactly which state it is in
+        let microcoded = emulator.microsequencer.as_ref().map(|s| s.state);
+        let current = match microcoded {
+            Some(number) => states.iter().position(|s| s.number == number),
+            None => self.current(&states, emulator.next_phase()),
+        };
+
+        ui.horizontal_wrapped(|ui| {
+            ui.label(
+                RichText::new(format!("x{:04X}", inputs.instruction))
+                    .monospace()
+                    .strong(),
+            );
+            if let Some(line) = emulator
+                .metadata
+                .address_to_line
+                .get(&match emulator.cpu_state {
+                    CpuState::Fetch => emulator.pc.get() as usize,
+                    _ => emulator.currently_executing,
+                })
+                .and_then(|line| {
+                    emulator
+                        .metadata
+                        .last_compiled_source
+                        .get(line.checked_sub(1)?)
+                })
+            {
+                ui.label(RichText::new(line.trim()).monospace());
+            }
+            ui.separator();
+            let button = ui
+                .add_enabled(current.is_some(), egui::Button::new("⤵ Step state"))
+                .on_hover_text("Go to the next FSM state. The emulator runs a phase at a time, so states that share a phase happen together when you leave the last of them");
+            if let (true, Some(current)) = (button.clicked(), current) {
+                if microcoded.is_some() {
+                    // a state is a clock cycle
+                    let was_running = emulator.running();
+                    emulator.start_running();
+                    self.error = emulator.micro_step().err();
+                    if !was_running && emulator.running() {
+                        emulator.stop_running();
+                    }
+                } else {
+                    self.step(emulator, &states, current);
+                }
+            }
+        });
+        if let Some(error) = &self.error {
+            ui.colored_label(theme.error_fg_color, error);
+        }
+        ui.separator();
+
+        // the path as a row of numbered circles
+        let radius = 14.0;
+        let gap = 26.0;
+        let width = states.len() as f32 * (radius * 2.0 + gap) + radius * 2.0;
+        egui::ScrollArea::horizontal().show(ui, |ui| {
+            let (rect, _) =
+                ui.allocate_exact_size(vec2(width, radius * 2.0 + 8.0), egui::Sense::hover());
+            let painter = ui.painter_at(rect);
+            let centre = |i: usize| {
+                rect.left_center() + vec2(radius + 4.0 + i as f32 * (radius * 2.0 + gap), 0.0)
+            };
+            let font = egui::FontId::monospace(12.0);
+            // back to 18 for the next instruction
+            for i in 0..=states.len() {
+                let (number, color) = match states.get(i) {
+                    Some(state) => {
+                        let color = match current {
+                            Some(c) if c == i => theme.cpu_state_active_color,
+                            Some(c) if i < c => theme.secondary_text_color,
+                            None => theme.secondary_text_color,
+                            _ => ui.visuals().text_color(),
+                        };
+                        (state.number, color)
+                    }
+                    None => (18, theme.secondary_text_color.gamma_multiply(0.5)),
+                };
+                if i > 0 {
+                    painter.arrow(
+                        centre(i - 1) + vec2(radius + 2.0, 0.0),
+                        vec2(gap - 4.0, 0.0),
+                        Stroke::new(1.5, theme.secondary_text_color),
+                    );
+                }
+                let stroke = if Some(i) == current { 3.0 } else { 1.5 };
+                painter.circle_stroke(centre(i), radius, Stroke::new(stroke, color));
+                painter.text(
+                    centre(i),
+                    egui::Align2::CENTER_CENTER,
+                    number.to_string(),
+                    font.clone(),
+                    color,
+                );
+            }
+        });
+
+        match current {
+            Some(c) => {
+                let state = &states[c];
+                ui.label(
+                    RichText::new(format!("State {}: {}", state.number, state.rtl))
+                        .strong()
+                        .color(theme.cpu_state_active_color),
+                );
+                ui.label(format!("Next: {}", transition(&states, c, &inputs)));
+            }
+            None => {
+                ui.label(RichText::new("The instruction is done, next is state 18").weak());
+            }
+        }
+        ui.separator();
+
+        egui::Grid::new("fsm_states")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for title in ["State", "Does", "Our phase", "Then"] {
+                    ui.label(RichText::new(title).strong());
+                }
+                ui.end_row();
+                let phases = [
+                    "Fetch",
+                    "Decode",
+                    "Evaluate Address",
+                    "Fetch Operands",
+                    "Execute",
+                    "Store Result",
+                ];
+                for (i, state) in states.iter().enumerate() {
+                    let mut number = RichText::new(state.number.to_string()).monospace();
+                    if Some(i) == current {
+                        number = number.strong().color(theme.cpu_state_active_color);
+                    }
+                    ui.label(number);
+                    ui.label(RichText::new(state.rtl).monospace());
+                    ui.label(phases[state.phase]);
+                    ui.label(transition(&states, i, &inputs));
+                    ui.end_row();
+                }
+            });
+        ui.label(
+            RichText::new("State numbers are from Patt & Patel appendix C (2nd edition). TRAP saves PC and PSR on the supervisor stack like the 3rd edition instead of putting PC in R7.")
+                .small()
+                .weak(),
+        );
+    }
+
+    fn title(&self) -> String {
+        "FSM".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "FSM".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Fsm(
+                FsmPane::default(),
+            )))),
+        )
+    }
+}
@@ -36,6 +36,7 @@ struct InstructionFields {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct HelpPane {
     instruction_fields: InstructionFields,
 }
@@ -218,14 +219,27 @@ fn render_general_help_ui(ui: &mut Ui) {
                 "'Step': Execute one full instruction.",
                 "'Micro Step': Execute a single micro-operation within an instruction's cycle.",
                 "'Reset': Reload the last compiled program and reset the machine state.",
+                "'Memory latency': How many clock cycles memory takes to set R. Phases that use memory wait on it, and the 'CPU State' pane counts the cycles each instruction takes.",
+                "'Native traps': Run GETC, OUT, PUTS, IN, PUTSP and HALT in Rust instead of the OS, each one you tick skips its OS routine. Output is the same, just much faster. The headless runner takes --native-traps all (or a list like PUTS,OUT).",
             ],
         ),
         (
             "Debugging",
             &[
-                "The 'CPU State' pane shows registers, flags, and the current instruction cycle.",
+                "The 'CPU State' pane shows registers, flags, and the current instruction cycle. Registers the last step changed are outlined, and 'History' graphs them over time.",
                 "The 'Memory' pane allows you to inspect and modify memory content and set break points.",
                 "Set breakpoints by clicking the '🛑' button next to a line in the memory view.",
+                "You can also click left of a line in the editor, it moves with your code and becomes a real breakpoint when you compile.",
+                "Name parts of memory with ';@region NAME START END' and label a line's address with ';@note text' in your code, or use 'Regions' and right click an address in the memory view.",
+                "The 'Memory Timeline' pane lists every read and write the program made. Filter it by address, label or PC and click an address to plot its value over time.",
+                "The 'Stack' pane shows the memory around R6 split into frames, either from the JSRs that haven't returned yet or by following saved R5s like lcc's calling convention.",
+                "The 'Datapath' pane draws the LC-3 datapath and animates the micro-ops of the phase that runs next: transfers over the bus, ALU operations and memory reads and writes. Click a step to stop on it.",
+                "The 'Custom Instruction' pane defines an instruction for the reserved opcode 1101 (like MUL) from a RON file: its mnemonic, where its operands go and the micro-ops it runs. Once loaded the assembler accepts it, reassemble after changing it.",
+                "The 'FSM' pane shows which numbered state of the textbook control FSM (18, 33, 35, 32, ...) the machine is in for the current instruction and why it goes to the next one. 'Step state' steps one FSM state at a time.",
+                "The 'Microcode' pane can switch the machine to a microsequencer running a control store in the format of appendix C. Each step is then one clock cycle, and the pane shows the control signals (LD.MAR, GatePC, PCMUX, ALUK, ...) and how J, COND and IRD picked the next state.",
+                "The 'Pipeline' pane times the program on a 5 stage pipeline (IF ID EX MEM WB) with or without forwarding. Its diagram shows each instruction against the cycles, stalls for data hazards and the instructions flushed after taken branches, with the CPI so far.",
+                "The 'Cache' pane puts a cache in front of memory. Pick its sets, ways, block size, write policy and replacement, then run your program to see its hits and misses, what is in each set and a hit/miss column in the 'Memory Timeline'.",
+                "The 'Counters' pane counts cycles, instructions by opcode, memory reads and writes, traps, exceptions and privilege switches, and shows how many instructions a second the current speed gets. 'Reset counters' starts them from zero, and the headless runner prints them with --stats.",
             ],
         ),
         (
@@ -578,13 +592,132 @@ fn ui_offset_selector(ui: &mut Ui, value: &mut i16, bits: u8, label: &str) {
     });
 }
 
-struct BinarySegment {
-    text: String,
-    color: Color32,
-    description: &'static str,
+pub(crate) struct BinarySegment {
+    pub text: String,
+    pub color: Color32,
+    pub description: &'static str,
 }
 
-fn render_binary_representation_view(
+/// Split any instruction word into its fields, so things outside the help pane (editor hover) can show it
+pub(crate) fn instruction_segments(word: u16, theme: &ThemeSettings) -> Vec<BinarySegment> {
+    let bits = |hi: u16, lo: u16| {
+        format_binary(
+            (word >> lo) & ((1 << (hi - lo + 1)) - 1),
+            (hi - lo + 1) as usize,
+        )
+    };
+    let seg = |text: String, color: Color32, description: &'static str| BinarySegment {
+        text,
+        color,
+        description,
+    };
+    let opcode = |description| seg(bits(15, 12), theme.opcode_color, description);
+    let operand = |hi, lo, description| seg(bits(hi, lo), theme.help_operand_color, description);
+    let fixed = |hi, lo, description| {
+        seg(
+            bits(hi, lo),
+            theme.help_binary_layout_fixed_bits_color,
+            description,
+        )
+    };
+    let offset = |hi, lo, description| seg(bits(hi, lo), theme.help_offset_color, description);
+    let immediate =
+        |hi, lo, description| seg(bits(hi, lo), theme.help_immediate_color, description);
+
+    let add_and = |name| {
+        let mut segments = vec![
+            opcode(name),
+            operand(11, 9, "DR: Destination Register"),
+            operand(8, 6, "SR1: Source Register 1"),
+        ];
+        if word & 0x20 != 0 {
+            segments.push(fixed(5, 5, "Mode: 1 for immediate"));
+            segments.push(immediate(4, 0, "imm5: 5-bit immediate value"));
+        } else {
+            segments.push(fixed(5, 3, "Mode: 0 for register, plus unused bits"));
+            segments.push(operand(2, 0, "SR2: Source Register 2"));
+        }
+        segments
+    };
+    let pc_relative = |name, register| {
+        vec![
+            opcode(name),
+            operand(11, 9, register),
+            offset(8, 0, "PCoffset9: 9-bit PC-relative offset"),
+        ]
+    };
+    let base_relative = |name, register| {
+        vec![
+            opcode(name),
+            operand(11, 9, register),
+            operand(8, 6, "BaseR: Base Register"),
+            offset(5, 0, "offset6: 6-bit offset from BaseR"),
+        ]
+    };
+
+    match word >> 12 {
+        0b0000 => vec![
+            opcode("Opcode for BR"),
+            seg(
+                bits(11, 11),
+                theme.help_strong_label_color,
+                "n: branch if negative",
+            ),
+            seg(
+                bits(10, 10),
+                theme.help_strong_label_color,
+                "z: branch if zero",
+            ),
+            seg(
+                bits(9, 9),
+                theme.help_strong_label_color,
+                "p: branch if positive",
+            ),
+            offset(8, 0, "PCoffset9: 9-bit PC-relative offset"),
+        ],
+        0b0001 => add_and("Opcode for ADD"),
+        0b0101 => add_and("Opcode for AND"),
+        0b0010 => pc_relative("Opcode for LD", "DR: Destination Register"),
+        0b1010 => pc_relative("Opcode for LDI", "DR: Destination Register"),
+        0b1110 => pc_relative("Opcode for LEA", "DR: Destination Register"),
+        0b0011 => pc_relative("Opcode for ST", "SR: Source Register"),
+        0b1011 => pc_relative("Opcode for STI", "SR: Source Register"),
+        0b0110 => base_relative("Opcode for LDR", "DR: Destination Register"),
+        0b0111 => base_relative("Opcode for STR", "SR: Source Register"),
+        0b0100 if word & 0x0800 != 0 => vec![
+            opcode("Opcode for JSR"),
+            fixed(11, 11, "Mode: 1 for PC-relative"),
+            offset(10, 0, "PCoffset11: 11-bit PC-relative offset"),
+        ],
+        0b0100 => vec![
+            opcode("Opcode for JSRR"),
+            fixed(11, 9, "Mode: 0 for register, plus unused bits"),
+            operand(8, 6, "BaseR: Base Register"),
+            fixed(5, 0, "Unused"),
+        ],
+        0b1001 => vec![
+            opcode("Opcode for NOT"),
+            operand(11, 9, "DR: Destination Register"),
+            operand(8, 6, "SR: Source Register"),
+            fixed(5, 0, "Always 111111"),
+        ],
+        0b1100 => vec![
+            opcode("Opcode for JMP/RET"),
+            fixed(11, 9, "Unused"),
+            operand(8, 6, "BaseR: Base Register (R7 for RET)"),
+            fixed(5, 0, "Unused"),
+        ],
+        0b1111 => vec![
+            opcode("Opcode for TRAP"),
+            fixed(11, 8, "Unused"),
+            immediate(7, 0, "trapvect8: Trap vector"),
+        ],
+        0b1000 => vec![opcode("Opcode for RTI"), fixed(11, 0, "Unused")],
+        _ => vec![opcode("Reserved opcode"), fixed(11, 0, "Unused")],
+    }
+}
+
+pub(crate) fn render_binary_representation_view(
     ui: &mut Ui,
     segments: Vec<BinarySegment>,
     theme: &ThemeSettings,
@@ -4,58 +4,159 @@ use crate::theme::ThemeSettings;
 use egui::{OutputCommand, RichText};
 use serde::{Deserialize, Serialize};
 
+use super::terminal::Terminal;
 use super::EmulatorPane;
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, Default)]
+#[serde(default)]
 pub struct IoPane {
     terminal_input: String,
     interactive_input: String,
+    /// Text to send all at once (whole lines of input)
+    paste_input: String,
+    /// File to read scripted input from
+    script_path: String,
+    /// Why the last lot of input was not sent
+    #[serde(skip)]
+    input_error: Option<String>,
+    /// Rebuilt from the emulator output so no need to save it
+    #[serde(skip)]
+    terminal: Terminal,
+}
+
+impl IoPane {
+    fn send(&mut self, emulator: &mut Emulator, text: &str) {
+        self.input_error = emulator.queue_input(text).err();
+    }
 }
 
 impl PaneDisplay for IoPane {
-    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, _theme: &mut ThemeSettings) {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
         egui::ScrollArea::vertical().show(ui, |ui| {
             // Display terminal output
             ui.label(RichText::new("Terminal:").strong());
 
+            self.terminal.sync(&emulator.output);
+
             let terminal_height = 200.0;
-            egui::ScrollArea::vertical()
-                .max_height(terminal_height)
-                .stick_to_bottom(true)
+            egui::Frame::new()
+                .fill(theme.terminal_bg_color)
+                .inner_margin(4.0)
                 .show(ui, |ui| {
-                    ui.add(
-                        egui::TextEdit::multiline(&mut emulator.output)
-                            .desired_width(f32::INFINITY)
-                            .desired_rows(10)
-                            .interactive(false)
-                            .font(egui::TextStyle::Monospace),
-                    );
+                    egui::ScrollArea::both()
+                        .max_height(terminal_height)
+                        .min_scrolled_height(terminal_height)
+                        .stick_to_bottom(true)
+                        .show(ui, |ui| {
+                            ui.set_min_width(ui.available_width());
+                            let font_id = egui::TextStyle::Monospace.resolve(ui.style());
+                            let job = self.terminal.layout_job(theme, font_id);
+                            ui.add(egui::Label::new(job).extend().selectable(true));
+                        });
                 });
 
             ui.horizontal(|ui| {
                 ui.label(">");
-                ui.add(
+                let response = ui.add(
                     egui::TextEdit::singleline(&mut self.terminal_input)
                         .desired_width(ui.available_width())
                         .hint_text("Type here to send input to the emulator...")
                         .font(egui::TextStyle::Monospace),
                 );
 
+                // Everything typed (or pasted) this frame goes in the queue, not just the last char
                 if !self.terminal_input.is_empty() {
-                    emulator.set_in_char(self.terminal_input.chars().last().unwrap());
-                    self.terminal_input.clear();
+                    let typed = std::mem::take(&mut self.terminal_input);
+                    self.send(emulator, &typed);
+                }
+
+                // singleline edits eat the enter key so send the newline ourselves
+                if response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter)) {
+                    self.send(emulator, "\n");
+                    response.request_focus();
+                }
+            });
+
+            if let Some(error) = &self.input_error {
+                ui.label(RichText::new(error).color(theme.error_fg_color));
+            }
+
+            if !emulator.input_queue.is_empty() {
+                ui.horizontal(|ui| {
+                    ui.label(format!(
+                        "{} char(s) waiting to be read",
+                        emulator.input_queue.len()
+                    ));
+                    if ui.button("Clear Queue").clicked() {
+                        emulator.input_queue.clear();
+                    }
+                });
+            }
+
+            egui::CollapsingHeader::new("Paste / Scripted Input").show(ui, |ui| {
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.paste_input)
+                        .desired_width(f32::INFINITY)
+                        .desired_rows(3)
+                        .hint_text(
+                            "Lines of input, sent one char at a time as the program reads them",
+                        )
+                        .font(egui::TextStyle::Monospace),
+                );
+                if ui.button("Send").clicked() {
+                    let text = self.paste_input.clone();
+                    self.send(emulator, &text);
+                    if self.input_error.is_none() {
+                        self.paste_input.clear();
+                    }
                 }
+
+                #[cfg(not(target_arch = "wasm32"))]
+                ui.horizontal(|ui| {
+                    ui.label("File:");
+                    ui.text_edit_singleline(&mut self.script_path);
+                    if ui.button("Load").clicked() {
+                        match std::fs::read_to_string(&self.script_path) {
+                            Ok(text) => self.send(emulator, &text),
+                            Err(e) => {
+                                self.input_error =
+                                    Some(format!("Could not read {}: {e}", self.script_path))
+                            }
+                        }
+                    }
+                });
+                ui.small("You can also drop a text file here to type its contents in.");
             });
 
+            // Files dropped on the terminal are scripted input
+            let dropped = if ui.ui_contains_pointer() {
+                ui.ctx().input(|i| i.raw.dropped_files.clone())
+            } else {
+                Vec::new()
+            };
+            for file in dropped {
+                let text = match (&file.bytes, &file.path) {
+                    (Some(bytes), _) => {
+                        String::from_utf8(bytes.to_vec()).map_err(|e| e.to_string())
+                    }
+                    (None, Some(path)) => std::fs::read_to_string(path).map_err(|e| e.to_string()),
+                    (None, None) => continue,
+                };
+                match text {
+                    Ok(text) => self.send(emulator, &text),
+                    Err(e) => self.input_error = Some(format!("Could not read {}: {e}", file.name)),
+                }
+            }
+
             // Control buttons
             ui.horizontal(|ui| {
                 if ui.button("Clear Output").clicked() {
                     emulator.output.clear();
                 }
 
-                let output_clone = emulator.output.clone();
                 if ui.button("Copy to Clipboard").clicked() {
-                    ui.output_mut(|o| o.commands.push(OutputCommand::CopyText(output_clone)));
+                    let text = self.terminal.plain_text();
+                    ui.output_mut(|o| o.commands.push(OutputCommand::CopyText(text)));
                 }
             });
         });
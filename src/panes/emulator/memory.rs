@@ -1,10 +1,12 @@
-use crate::emulator::{Emulator, EmulatorCell};
+use crate::emulator::ops::{custom, OpCode};
+use crate::emulator::regions::MemoryRegion;
+use crate::emulator::{area_from_address, Emulator, EmulatorCell, Isa, MemoryArea};
 use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
 use crate::theme::ThemeSettings;
 use egui::{Align, RichText};
 use egui_extras::{Column, TableBuilder};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::ops::RangeInclusive;
 
 use super::EmulatorPane;
@@ -14,6 +16,7 @@ use super::EmulatorPane;
 // }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct MemoryPane {
     follow_pc: bool,
     jump_addr_str: String,
@@ -22,6 +25,67 @@ pub struct MemoryPane {
     display_base: u32,
     highlighted: HashMap<usize, f32>, // highlighed with fade off (fades in 1 second from 1.0 -> 0)
     was_running: bool,
+    /// Decoded text for rows we have shown, with the word it was decoded from.
+    /// (We check the word itself rather than the cell's changed flag, nothing clears that flag so it is always set)
+    #[serde(skip)]
+    decoded: HashMap<usize, (u16, String)>,
+    /// The compile (and custom instruction) `decoded` was filled for, a recompile means offsets might be labels now
+    #[serde(skip)]
+    decoded_for: (usize, usize, Isa),
+    /// Regions made in the pane, on top of the ones declared by the program
+    #[serde(default)]
+    regions: Vec<MemoryRegion>,
+    /// Notes made in the pane, these win over `;@note`s from the program
+    #[serde(default)]
+    notes: BTreeMap<usize, String>,
+    #[serde(default)]
+    show_regions: bool,
+}
+
+/// Don't let the decode cache grow forever if someone scrolls through all of memory
+const MAX_DECODED: usize = 4096;
+
+/// Decode a word for the instruction column, showing PC offsets as the label they point to if there is one
+pub(super) fn disassemble(
+    isa: Isa,
+    address: usize,
+    word: u16,
+    addr_to_label: &HashMap<usize, String>,
+) -> String {
+    let Some(op) = OpCode::decode(isa, EmulatorCell::new(word)) else {
+        return String::new();
+    };
+    let text = op.to_string();
+
+    let offset = match (isa, word >> 12) {
+        // shift the sign bit to the top and back to sign extend
+        (Isa::Lc3, 0x0 | 0x2 | 0x3 | 0xA | 0xB | 0xE) | (Isa::Lc3b, 0x0 | 0xE) => {
+            ((word << 7) as i16) >> 7
+        }
+        (_, 0x4) if word & 0x0800 != 0 => ((word << 5) as i16) >> 5,
+        _ => return text,
+    };
+    // the LC-3b ops print their offsets already in bytes
+    let step = isa.word_size() as i16;
+    let offset = offset.wrapping_mul(step);
+    let target = (address as u16)
+        .wrapping_add(step as u16)
+        .wrapping_add(offset as u16) as usize;
+    let Some(label) = addr_to_label.get(&target) else {
+        return text;
+    };
+
+    // the ops all print offsets as `#off (xHEX)`
+    let needle = format!("#{offset} (x");
+    match text.find(&needle) {
+        Some(start) => {
+            let end = text[start..]
+                .find(')')
+                .map_or(text.len(), |i| start + i + 1);
+            format!("{}{label}{}", &text[..start], &text[end..])
+        }
+        None => text,
+    }
 }
 
 impl Default for MemoryPane {
@@ -33,10 +97,96 @@ impl Default for MemoryPane {
             target_scroll_addr: None,
             highlighted: HashMap::new(),
             display_base: 16,
+            decoded: HashMap::new(),
+            decoded_for: (0, 0, Isa::Lc3),
+            regions: Vec::new(),
+            notes: BTreeMap::new(),
+            show_regions: false,
         }
     }
 }
 
+/// Name and colour for the built in areas of memory
+fn area_band(address: usize, theme: &ThemeSettings) -> (&'static str, egui::Color32) {
+    match area_from_address(&EmulatorCell::new(address as u16)) {
+        MemoryArea::TrapVectorTable => ("Trap vectors", theme.memory_os_data_bg),
+        MemoryArea::IntrruptVectorTable => ("Interrupt vectors", theme.memory_os_data_bg),
+        MemoryArea::OperatingSystem => ("OS", theme.memory_os_code_bg),
+        MemoryArea::UserSpace => ("User space", theme.memory_user_code_bg),
+        MemoryArea::DeviceRegisters => ("Devices", theme.memory_device_registers_bg),
+    }
+}
+
+/// The smallest region holding `address`, so a region inside another one still shows up
+fn region_at<'a>(
+    regions: impl Iterator<Item = &'a MemoryRegion>,
+    address: usize,
+) -> Option<&'a MemoryRegion> {
+    regions
+        .filter(|region| region.contains(address))
+        .min_by_key(|region| region.end - region.start)
+}
+
+impl MemoryPane {
+    /// List the program's regions and let the user add/edit their own
+    fn regions_ui(&mut self, ui: &mut egui::Ui, program_regions: &[MemoryRegion]) {
+        for region in program_regions {
+            ui.horizontal(|ui| {
+                let (rect, _) =
+                    ui.allocate_exact_size(egui::vec2(16.0, 16.0), egui::Sense::hover());
+                ui.painter().rect_filled(rect, 2.0, region.color);
+                ui.label(
+                    RichText::new(format!(
+                        "{} x{:04X}-x{:04X}",
+                        region.name, region.start, region.end
+                    ))
+                    .monospace(),
+                );
+                ui.label(RichText::new("(from program)").weak());
+            });
+        }
+
+        let mut remove = None;
+        for (i, region) in self.regions.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgba(&mut region.color);
+                ui.add(egui::TextEdit::singleline(&mut region.name).desired_width(100.0));
+                ui.add(egui::DragValue::new(&mut region.start).hexadecimal(4, false, true));
+                ui.label("to");
+                ui.add(egui::DragValue::new(&mut region.end).hexadecimal(4, false, true));
+                region.end = region.end.max(region.start);
+                if ui.button("🗑").on_hover_text("Remove region").clicked() {
+                    remove = Some(i);
+                }
+            });
+        }
+        if let Some(i) = remove {
+            self.regions.remove(i);
+        }
+
+        ui.horizontal(|ui| {
+            if ui.button("Add region").clicked() {
+                let name = format!("region {}", self.regions.len() + 1);
+                self.regions.push(MemoryRegion::new(name, 0x3000, 0x30FF));
+            }
+            if !self.regions.is_empty()
+                && ui
+                    .button("Copy as ;@region lines")
+                    .on_hover_text("Paste these into your program to keep the regions with it")
+                    .clicked()
+            {
+                let text = self
+                    .regions
+                    .iter()
+                    .map(MemoryRegion::to_annotation)
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                ui.ctx().copy_text(text);
+            }
+        });
+    }
+}
+
 impl PaneDisplay for MemoryPane {
     fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
         let artifacts = &emulator.metadata;
@@ -47,6 +197,14 @@ impl PaneDisplay for MemoryPane {
 
         self.was_running = emulator.running();
 
+        let decoded_for = (artifacts.generation, custom::generation(), emulator.isa);
+        if self.decoded_for != decoded_for || self.decoded.len() > MAX_DECODED {
+            self.decoded.clear();
+            self.decoded_for = decoded_for;
+        }
+        // Finished fading
+        self.highlighted.retain(|_, fade| *fade > 0.0);
+
         ui.horizontal(|ui| {
             // --- Controls ---
             ui.checkbox(&mut self.follow_pc, "Follow PC");
@@ -86,8 +244,16 @@ impl PaneDisplay for MemoryPane {
             ui.radio_value(&mut self.display_base, 2, "Bin");
             ui.radio_value(&mut self.display_base, 10, "Dec");
             ui.radio_value(&mut self.display_base, 16, "Hex");
+
+            ui.toggle_value(&mut self.show_regions, "Regions")
+                .on_hover_text("Name and colour parts of memory. Programs can declare them with ;@region NAME START END");
         });
 
+        if self.show_regions {
+            ui.separator();
+            self.regions_ui(ui, &artifacts.regions);
+        }
+
         ui.separator();
         let available_height = ui.available_height();
 
@@ -98,12 +264,14 @@ impl PaneDisplay for MemoryPane {
             .resizable(true)
             .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
             .column(Column::auto().at_least(15.0)) // breakpoint toggle
+            .column(Column::auto().at_least(90.0)) // Region band
             .column(Column::auto().at_least(100.0)) // Label
             .column(Column::auto().at_least(60.0)) // Address + PC indicator
             .column(Column::auto().at_least(50.0)) // Value (editable)
             .column(Column::auto().at_least(60.0)) // Value (formatted)
             .column(Column::auto().at_least(150.0)) // Instruction
-            .column(Column::remainder().at_least(80.0)) // ASCII
+            .column(Column::auto().at_least(40.0)) // ASCII
+            .column(Column::remainder().at_least(80.0)) // Note
             .max_scroll_height(available_height)
             .min_scrolled_height(0.0);
 
@@ -137,6 +305,9 @@ impl PaneDisplay for MemoryPane {
                 ui.col(|ui| {
                     ui.label(RichText::new("BP").monospace().strong());
                 });
+                ui.col(|ui| {
+                    ui.label(RichText::new("Region").monospace().strong());
+                });
                 ui.col(|ui| {
                     ui.label(RichText::new("Label").monospace().strong());
                 });
@@ -152,6 +323,9 @@ impl PaneDisplay for MemoryPane {
                 ui.col(|ui| {
                     ui.label(RichText::new("ASCII").monospace().strong());
                 });
+                ui.col(|ui| {
+                    ui.label(RichText::new("Note").monospace().strong());
+                });
             })
             .body(|mut body| {
                 let item_spacing = body.ui_mut().spacing().item_spacing;
@@ -210,6 +384,37 @@ impl PaneDisplay for MemoryPane {
                         }
                     });
 
+                    // Region band, the name goes on the first row of each region
+                    row.col(|ui| {
+                        let gapless_rect = ui.max_rect().expand2(0.5 * item_spacing);
+                        let region =
+                            region_at(self.regions.iter().chain(&artifacts.regions), row_index);
+                        let (area, area_color) = area_band(row_index, theme);
+                        let (name, color, first) = match region {
+                            Some(region) => (
+                                region.name.as_str(),
+                                region.color.gamma_multiply(0.5),
+                                region.start as usize == row_index,
+                            ),
+                            None => (
+                                area,
+                                area_color,
+                                row_index == 0 || area_band(row_index - 1, theme).0 != area,
+                            ),
+                        };
+                        ui.painter().rect_filled(gapless_rect, 0.0, color);
+                        let text = if first { name } else { "" };
+                        let response = ui.label(RichText::new(text).monospace());
+                        ui.interact(gapless_rect, response.id.with("band"), egui::Sense::hover())
+                            .on_hover_text(match region {
+                                Some(region) => format!(
+                                    "{} x{:04X}-x{:04X} ({area})",
+                                    region.name, region.start, region.end
+                                ),
+                                None => area.to_owned(),
+                            });
+                    });
+
                     // label
                     row.col(|ui| {
                         paint_bg(ui);
@@ -235,7 +440,24 @@ impl PaneDisplay for MemoryPane {
                             if is_pc_line { " (PC)" } else { "" }
                         );
                         let rich_text = RichText::new(addr_text).monospace();
-                        ui.label(rich_text);
+                        ui.add(egui::Label::new(rich_text).sense(egui::Sense::click()))
+                            .on_hover_text("Right click to add a note")
+                            .context_menu(|ui| {
+                                let mut note = self
+                                    .notes
+                                    .get(&row_index)
+                                    .or_else(|| artifacts.notes.get(&row_index))
+                                    .cloned()
+                                    .unwrap_or_default();
+                                ui.label("Note:");
+                                // an empty note hides the program's one
+                                if ui.text_edit_singleline(&mut note).changed() {
+                                    self.notes.insert(row_index, note);
+                                }
+                                if ui.button("Done").clicked() {
+                                    ui.close();
+                                }
+                            });
                     });
 
                     // Value Edit Column
@@ -283,18 +505,21 @@ impl PaneDisplay for MemoryPane {
 
                         let value_u16 = memory_cell.get();
 
-                        // Try to decode as an instruction if it's a plausible address
-                        let decoded_op = crate::emulator::ops::OpCode::from_instruction(
-                            EmulatorCell::new(value_u16),
-                        )
-                        .map(|op| format!("{op}"));
-
-                        if let Some(decoded_str) = decoded_op {
-                            // Display decoded Op struct
-                            ui.label(RichText::new(decoded_str).monospace());
-                        } else {
-                            ui.label(RichText::new("").monospace());
-                        }
+                        // Only decode rows we can see, and only when the word has changed
+                        let decoded = match self.decoded.get(&row_index) {
+                            Some((word, text)) if *word == value_u16 => text,
+                            _ => {
+                                let text = disassemble(
+                                    emulator.isa,
+                                    row_index,
+                                    value_u16,
+                                    &artifacts.addr_to_label,
+                                );
+                                self.decoded.insert(row_index, (value_u16, text));
+                                &self.decoded[&row_index].1
+                            }
+                        };
+                        ui.label(RichText::new(decoded).monospace());
                     });
 
                     // ASCII Column
@@ -306,6 +531,17 @@ impl PaneDisplay for MemoryPane {
 
                         ui.label(RichText::new(ascii_char).monospace().weak()); // Weak color for less emphasis
                     });
+
+                    // Note Column
+                    row.col(|ui| {
+                        if let Some(note) = self
+                            .notes
+                            .get(&row_index)
+                            .or_else(|| artifacts.notes.get(&row_index))
+                        {
+                            ui.label(RichText::new(note).italics());
+                        }
+                    });
                 });
             });
         if animation_needed {
@@ -326,3 +562,28 @@ impl PaneDisplay for MemoryPane {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets_become_labels() {
+        let labels = HashMap::from([(0x3000, "LOOP".to_owned())]);
+        // BRp #-2 at x3001 goes back to x3000
+        let text = disassemble(Isa::Lc3, 0x3001, 0x03FE, &labels);
+        assert!(text.starts_with("BRP LOOP"), "got {text}");
+        // same thing somewhere else has no label to point at
+        assert!(disassemble(Isa::Lc3, 0x4001, 0x03FE, &labels).contains("#-2"));
+        // on the LC-3b it counts words, so back 2 words from x3002 is x3000
+        let text = disassemble(Isa::Lc3b, 0x3002, 0x03FE, &labels);
+        assert!(text.starts_with("BRP LOOP"), "got {text}");
+    }
+
+    #[test]
+    fn reserved_opcode_is_blank() {
+        let _custom = custom::test_lock();
+        custom::set_loaded(None);
+        assert_eq!(disassemble(Isa::Lc3, 0x3000, 0xD000, &HashMap::new()), "");
+    }
+}
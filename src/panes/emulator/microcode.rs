@@ -0,0 +1,149 @@
+use crate::emulator::microcode::{control_store, Microinstruction};
+use crate::emulator::{Emulator, Isa};
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+
+use super::EmulatorPane;
+
+/// The microcoded machine: the control signals of each cycle and the whole control store
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MicrocodePane {
+    show_store: bool,
+}
+
+fn signal_chips(ui: &mut egui::Ui, mi: &Microinstruction, color: egui::Color32) {
+    ui.horizontal_wrapped(|ui| {
+        for signal in mi.signals.active() {
+            ui.label(RichText::new(signal).monospace().color(color));
+        }
+        let sequencing = if mi.ird {
+            "IRD".to_owned()
+        } else {
+            format!("J={} COND={}", mi.j, mi.cond)
+        };
+        ui.label(RichText::new(sequencing).monospace().weak());
+    });
+}
+
+impl PaneDisplay for MicrocodePane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        if emulator.isa != Isa::Lc3 {
+            ui.label(
+                RichText::new(
+                    "The control store is the LC-3's, the LC-3b runs on the phase executor.",
+                )
+                .color(theme.secondary_text_color),
+            );
+            return;
+        }
+        let between_instructions = emulator.between_instructions();
+        ui.horizontal_wrapped(|ui| {
+            let mut on = emulator.microsequencer.is_some();
+            if ui
+                .add_enabled(
+                    between_instructions,
+                    egui::Checkbox::new(&mut on, "Run on the microsequencer"),
+                )
+                .on_hover_text("Steps become clock cycles of the control store below instead of phases. You can only switch between instructions.")
+                .changed()
+            {
+                emulator.use_microsequencer(on);
+            }
+            if emulator.microsequencer.is_some() && ui.button("⏵ Cycle").clicked() {
+                let was_running = emulator.running();
+                emulator.start_running();
+                let _ = emulator.micro_step();
+                if !was_running && emulator.running() {
+                    emulator.stop_running();
+                }
+            }
+        });
+
+        match &emulator.microsequencer {
+            Some(sequencer) => {
+                ui.label(format!("{} cycles", sequencer.cycles));
+                ui.separator();
+                if let Some(cycle) = &sequencer.last {
+                    if let Some(mi) = control_store(cycle.state) {
+                        ui.label(
+                            RichText::new(format!("Last cycle, state {}: {}", cycle.state, mi.rtl))
+                                .strong(),
+                        );
+                        signal_chips(ui, &mi, theme.cpu_state_active_color);
+                        ui.label(match cycle.bus {
+                            Some(value) => format!("Bus: x{value:04X}"),
+                            None => "Bus: not driven".to_owned(),
+                        });
+                        ui.label(format!("Then {} because {}", cycle.next, cycle.why));
+                    }
+                    ui.separator();
+                }
+                if let Some(mi) = control_store(sequencer.state) {
+                    ui.label(
+                        RichText::new(format!("Next, state {}: {}", sequencer.state, mi.rtl))
+                            .strong()
+                            .color(theme.cpu_state_active_color),
+                    );
+                    signal_chips(ui, &mi, ui.visuals().text_color());
+                }
+            }
+            None => {
+                ui.label(
+                    RichText::new("Programs run on the phase executor. Turn the microsequencer on to run them a clock cycle at a time.")
+                        .color(theme.secondary_text_color),
+                );
+            }
+        }
+        ui.separator();
+
+        let current = emulator.microsequencer.as_ref().map(|s| s.state);
+        ui.checkbox(&mut self.show_store, "Show the control store");
+        if self.show_store {
+            egui::ScrollArea::both().show(ui, |ui| {
+                egui::Grid::new("control_store")
+                    .num_columns(3)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for title in ["State", "Does", "Signals"] {
+                            ui.label(RichText::new(title).strong());
+                        }
+                        ui.end_row();
+                        for state in 0..64 {
+                            let Some(mi) = control_store(state) else {
+                                continue;
+                            };
+                            let mut number = RichText::new(state.to_string()).monospace();
+                            if Some(state) == current {
+                                number = number.strong().color(theme.cpu_state_active_color);
+                            }
+                            ui.label(number);
+                            ui.label(RichText::new(mi.rtl).monospace());
+                            signal_chips(ui, &mi, ui.visuals().text_color());
+                            ui.end_row();
+                        }
+                    });
+            });
+        }
+        ui.label(
+            RichText::new("Signals are named like Patt & Patel appendix C. Memory sets R after the latency set in 'Controls', the states waiting on it repeat until then.")
+                .small()
+                .weak(),
+        );
+    }
+
+    fn title(&self) -> String {
+        "Microcode".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Microcode".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Microcode(
+                MicrocodePane::default(),
+            )))),
+        )
+    }
+}
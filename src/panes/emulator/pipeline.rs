@@ -0,0 +1,151 @@
+use crate::emulator::pipeline::STAGE_NAMES;
+use crate::emulator::Emulator;
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+
+use super::memory::disassemble;
+use super::EmulatorPane;
+
+/// The pipeline diagram: the last few instructions against the cycles they went through each stage in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PipelinePane {
+    /// How many instructions to draw
+    rows: usize,
+}
+
+impl Default for PipelinePane {
+    fn default() -> Self {
+        Self { rows: 12 }
+    }
+}
+
+impl PaneDisplay for PipelinePane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        ui.horizontal_wrapped(|ui| {
+            let mut on = emulator.pipeline.is_some();
+            if ui
+                .checkbox(&mut on, "Time a 5 stage pipeline")
+                .on_hover_text("Work out when each instruction would go through IF, ID, EX, MEM and WB. It counts from the next instruction, the program runs the same either way.")
+                .changed()
+            {
+                emulator.use_pipeline(on);
+            }
+            if let Some(pipeline) = &mut emulator.pipeline {
+                ui.checkbox(&mut pipeline.forwarding, "Forwarding")
+                    .on_hover_text("Without it an instruction waits in ID until the one it needs a register from is in WB");
+                ui.add(egui::Slider::new(&mut self.rows, 4..=64).text("rows"));
+            }
+        });
+        let Some(pipeline) = &emulator.pipeline else {
+            ui.label(
+                RichText::new("Turn it on and run a program to draw its pipeline diagram and CPI.")
+                    .color(theme.secondary_text_color),
+            );
+            return;
+        };
+
+        let stats = pipeline.stats;
+        ui.label(format!(
+            "{} instructions in {} cycles, CPI {:.2}. {} stall cycles, {} flushed",
+            stats.instructions,
+            stats.cycles,
+            stats.cpi(),
+            stats.stall_cycles,
+            stats.flushed
+        ));
+        ui.separator();
+
+        let shown: Vec<_> = pipeline
+            .records
+            .iter()
+            .skip(pipeline.records.len().saturating_sub(self.rows))
+            .collect();
+        let Some(first) = shown.first().map(|record| record.stages[0]) else {
+            return;
+        };
+        let last = shown
+            .iter()
+            .map(|record| record.flushed_at.unwrap_or(record.stages[4]))
+            .max()
+            .unwrap_or(first);
+
+        egui::ScrollArea::both().show(ui, |ui| {
+            egui::Grid::new("pipeline_diagram")
+                .striped(true)
+                .spacing([4.0, 2.0])
+                .show(ui, |ui| {
+                    ui.label(RichText::new("Address").strong());
+                    ui.label(RichText::new("Instruction").strong());
+                    for cycle in first..=last {
+                        ui.label(RichText::new(cycle.to_string()).monospace().weak());
+                    }
+                    ui.end_row();
+
+                    for record in shown {
+                        let word = if record.flushed() {
+                            emulator.memory[emulator.word_address(record.pc)].get()
+                        } else {
+                            record.word
+                        };
+                        let text = disassemble(
+                            emulator.isa,
+                            record.pc as usize,
+                            word,
+                            &emulator.metadata.addr_to_label,
+                        );
+                        let color = if record.flushed() {
+                            theme.secondary_text_color
+                        } else {
+                            ui.visuals().text_color()
+                        };
+                        ui.label(
+                            RichText::new(format!("x{:04X}", record.pc))
+                                .monospace()
+                                .color(theme.memory_address_color),
+                        );
+                        ui.label(RichText::new(text).monospace().color(color));
+                        for cycle in first..=last {
+                            match record.stage_at(cycle) {
+                                Some((stage, held)) => {
+                                    let mut cell = RichText::new(STAGE_NAMES[stage]).monospace();
+                                    cell = if held {
+                                        cell.color(theme.warn_fg_color)
+                                    } else if record.flushed() {
+                                        cell.strikethrough().color(theme.secondary_text_color)
+                                    } else {
+                                        cell.color(theme.cpu_state_active_color)
+                                    };
+                                    ui.label(cell);
+                                }
+                                None => {
+                                    ui.label("");
+                                }
+                            }
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+        ui.label(
+            RichText::new("Stalls are coloured, crossed out rows were fetched after a taken branch, jump, trap or exception and thrown away when it got through EX.")
+                .small()
+                .weak(),
+        );
+    }
+
+    fn title(&self) -> String {
+        "Pipeline".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Pipeline".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Pipeline(
+                PipelinePane::default(),
+            )))),
+        )
+    }
+}
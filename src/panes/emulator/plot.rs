@@ -0,0 +1,146 @@
+//! A small step plot for values that change over time (instruction count on the x axis)
+
+use egui::{Color32, Stroke};
+
+use crate::theme::ThemeSettings;
+
+/// One line on the plot. `points` are (instruction, value) sorted by instruction, the value holds until the next point
+pub struct Series<'a> {
+    pub name: &'a str,
+    pub color: Color32,
+    pub points: &'a [(u64, u16)],
+}
+
+/// The value a series had at instruction `x`
+fn value_at(points: &[(u64, u16)], x: u64) -> Option<u16> {
+    let index = points.partition_point(|&(at, _)| at <= x);
+    index.checked_sub(1).map(|i| points[i].1)
+}
+
+/// Draw `series` from instruction `start` to `end`. `signed` shows values as 2s complement
+pub fn step_plot(
+    ui: &mut egui::Ui,
+    series: &[Series<'_>],
+    start: u64,
+    end: u64,
+    signed: bool,
+    theme: &ThemeSettings,
+) {
+    let as_number = |value: u16| {
+        if signed {
+            value as i16 as f64
+        } else {
+            value as f64
+        }
+    };
+    let (low, high) = series
+        .iter()
+        .flat_map(|s| s.points.iter().map(|&(_, value)| as_number(value)))
+        .fold((f64::MAX, f64::MIN), |(low, high), v| {
+            (low.min(v), high.max(v))
+        });
+    if low > high {
+        ui.label(egui::RichText::new("Nothing to plot yet").weak());
+        return;
+    }
+    // keep flat lines off the edges
+    let (low, high) = if low == high {
+        (low - 1.0, high + 1.0)
+    } else {
+        (low, high)
+    };
+    let end = end.max(start + 1);
+
+    let (response, painter) = ui.allocate_painter(
+        egui::vec2(ui.available_width(), 120.0),
+        egui::Sense::hover(),
+    );
+    let frame = response.rect;
+    painter.rect_filled(frame, 2.0, theme.code_bg_color);
+    let rect = frame.shrink2(egui::vec2(6.0, 14.0));
+
+    let to_x = |x: u64| rect.left() + rect.width() * (x - start) as f32 / (end - start) as f32;
+    let to_y = |v: f64| rect.bottom() - rect.height() * ((v - low) / (high - low)) as f32;
+
+    let font = egui::FontId::monospace(10.0);
+    let weak = ui.visuals().weak_text_color();
+    painter.text(
+        frame.left_top(),
+        egui::Align2::LEFT_TOP,
+        format!("{high}"),
+        font.clone(),
+        weak,
+    );
+    painter.text(
+        frame.left_bottom(),
+        egui::Align2::LEFT_BOTTOM,
+        format!("{low}   #{start}"),
+        font.clone(),
+        weak,
+    );
+    painter.text(
+        frame.right_bottom(),
+        egui::Align2::RIGHT_BOTTOM,
+        format!("#{end}"),
+        font.clone(),
+        weak,
+    );
+
+    for s in series {
+        let visible: Vec<_> = s
+            .points
+            .iter()
+            .filter(|&&(at, _)| at > start && at <= end)
+            .copied()
+            .collect();
+        let mut value = value_at(s.points, start);
+        let mut line = Vec::new();
+        if let Some(v) = value {
+            line.push(egui::pos2(to_x(start), to_y(as_number(v))));
+        }
+        for (at, v) in visible {
+            let x = to_x(at);
+            if let Some(previous) = value {
+                line.push(egui::pos2(x, to_y(as_number(previous))));
+            }
+            line.push(egui::pos2(x, to_y(as_number(v))));
+            value = Some(v);
+        }
+        if let Some(v) = value {
+            line.push(egui::pos2(to_x(end), to_y(as_number(v))));
+        }
+        painter.add(egui::Shape::line(line, Stroke::new(1.5, s.color)));
+    }
+
+    // what everything was at the pointer
+    if let Some(pointer) = response.hover_pos() {
+        let t = ((pointer.x - rect.left()) / rect.width()).clamp(0.0, 1.0);
+        let x = start + ((end - start) as f32 * t).round() as u64;
+        painter.vline(to_x(x), rect.y_range(), Stroke::new(1.0, weak));
+        response.on_hover_ui_at_pointer(|ui| {
+            ui.label(format!("Instruction #{x}"));
+            for s in series {
+                if let Some(value) = value_at(s.points, x) {
+                    ui.colored_label(
+                        s.color,
+                        format!("{}: x{value:04X} ({})", s.name, as_number(value)),
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn value_holds_until_the_next_point() {
+        let points = [(2, 10), (5, 20)];
+        assert_eq!(value_at(&points, 1), None);
+        assert_eq!(value_at(&points, 2), Some(10));
+        assert_eq!(value_at(&points, 4), Some(10));
+        assert_eq!(value_at(&points, 9), Some(20));
+    }
+}
@@ -0,0 +1,330 @@
+use crate::emulator::regions::color_for;
+use crate::emulator::stack::{lcc_frames, lcc_slots, CallFrame, Slot};
+use crate::emulator::{Emulator, PrivilegeLevel, USER_SPACE_END, USER_SPACE_START};
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use super::EmulatorPane;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum FrameSource {
+    /// JSR/RET pairs we watched happen
+    Calls,
+    /// Follow saved R5s like lcc output does
+    FramePointer,
+}
+
+/// The memory around R6 drawn as a stack, split into frames
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StackPane {
+    supervisor: bool,
+    source: FrameSource,
+    /// How many words above R6 to show
+    depth: u16,
+    /// How many free words below R6 to show
+    free: u16,
+}
+
+impl Default for StackPane {
+    fn default() -> Self {
+        Self {
+            supervisor: false,
+            source: FrameSource::FramePointer,
+            depth: 24,
+            free: 4,
+        }
+    }
+}
+
+/// A frame as the pane draws it: its name and what is in each of its slots
+struct Frame {
+    name: String,
+    /// Addresses it covers
+    range: std::ops::RangeInclusive<u16>,
+    slots: HashMap<u16, String>,
+}
+
+fn with_label(address: u16, labels: &HashMap<usize, String>) -> String {
+    match labels.get(&(address as usize)) {
+        Some(label) => format!("x{address:04X} ({label})"),
+        None => format!("x{address:04X}"),
+    }
+}
+
+fn subroutine_name(call: &CallFrame, labels: &HashMap<usize, String>) -> String {
+    let name = labels
+        .get(&(call.target as usize))
+        .cloned()
+        .unwrap_or_else(|| format!("x{:04X}", call.target));
+    format!("{name} (called from x{:04X})", call.call_site)
+}
+
+impl StackPane {
+    /// Frames from the JSRs we saw, each one is what got pushed after its call
+    fn call_frames(&self, emulator: &Emulator, sp: u16, calls: &[&CallFrame]) -> Vec<Frame> {
+        let labels = &emulator.metadata.addr_to_label;
+        let mut frames = Vec::new();
+        let mut bottom = sp;
+        for call in calls.iter().rev() {
+            let Some(top) = call.stack_pointer.checked_sub(1) else {
+                continue;
+            };
+            if top < bottom {
+                // pushed nothing (yet)
+                continue;
+            }
+            // a word holding the return address is probably where R7 got saved
+            let slots = (bottom..=top)
+                .filter(|&address| emulator.memory[address as usize].get() == call.return_address)
+                .map(|address| (address, "saved R7?".to_owned()))
+                .collect();
+            frames.push(Frame {
+                name: subroutine_name(call, labels),
+                range: bottom..=top,
+                slots,
+            });
+            bottom = call.stack_pointer;
+        }
+        frames
+    }
+
+    /// Frames from the chain of saved R5s
+    fn lcc_frames(
+        &self,
+        emulator: &Emulator,
+        sp: u16,
+        top: u16,
+        calls: &[&CallFrame],
+    ) -> Vec<Frame> {
+        let labels = &emulator.metadata.addr_to_label;
+        let frames = lcc_frames(&emulator.memory[..], emulator.r[5].get(), sp, top);
+        let slots = lcc_slots(&frames, sp);
+        frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                let fp = frame.frame_pointer;
+                let name = calls
+                    .iter()
+                    .find(|call| call.return_address == frame.return_address)
+                    .map_or_else(
+                        || format!("frame at x{fp:04X}"),
+                        |call| subroutine_name(call, labels),
+                    );
+                let frame_slots: HashMap<u16, String> = slots
+                    .iter()
+                    .filter(|(_, (index, _))| *index == i)
+                    .map(|(&address, (_, slot))| {
+                        let text = match slot {
+                            Slot::Local(n) => format!("local (R5-{n})"),
+                            Slot::SavedFramePointer => {
+                                format!(
+                                    "saved R5, caller's frame x{:04X}",
+                                    frame.saved_frame_pointer
+                                )
+                            }
+                            Slot::ReturnAddress => format!(
+                                "saved R7, returns to {}",
+                                with_label(frame.return_address, labels)
+                            ),
+                            Slot::ReturnValue => "return value".to_owned(),
+                            Slot::Argument(n) => format!("argument {} (R5+{})", n + 1, n + 4),
+                        };
+                        (address, text)
+                    })
+                    .collect();
+                let low = frame_slots.keys().copied().min().unwrap_or(fp);
+                let high = frame_slots.keys().copied().max().unwrap_or(fp);
+                Frame {
+                    name,
+                    range: low..=high,
+                    slots: frame_slots,
+                }
+            })
+            .collect()
+    }
+}
+
+impl PaneDisplay for StackPane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        let in_user_mode = matches!(emulator.priv_level(), PrivilegeLevel::User);
+
+        ui.horizontal_wrapped(|ui| {
+            ui.radio_value(&mut self.supervisor, false, "User stack");
+            ui.radio_value(&mut self.supervisor, true, "Supervisor stack");
+            ui.separator();
+            ui.label("Frames from:");
+            ui.radio_value(&mut self.source, FrameSource::FramePointer, "R5 (lcc)")
+                .on_hover_text(
+                    "Follow the saved frame pointers, for code using lcc's calling convention",
+                );
+            ui.radio_value(&mut self.source, FrameSource::Calls, "JSR/RET")
+                .on_hover_text("Each JSR that hasn't returned yet starts a frame");
+        });
+        ui.horizontal(|ui| {
+            ui.label("Show");
+            ui.add(egui::DragValue::new(&mut self.depth).range(1..=512));
+            ui.label("words above R6 and");
+            ui.add(egui::DragValue::new(&mut self.free).range(0..=64));
+            ui.label("free words below it");
+        });
+
+        // R6 is whichever stack we are on right now, the other one is saved
+        let active = self.supervisor != in_user_mode;
+        let sp = match (active, self.supervisor) {
+            (true, _) => emulator.r[6].get(),
+            (false, true) => emulator.saved_ssp.get(),
+            (false, false) => emulator.saved_usp.get(),
+        };
+        let top = if self.supervisor {
+            USER_SPACE_START as u16 - 1
+        } else {
+            USER_SPACE_END as u16
+        };
+        let calls: Vec<&CallFrame> = emulator
+            .call_stack
+            .frames
+            .iter()
+            .filter(|call| call.user != self.supervisor)
+            .collect();
+
+        let frames = match self.source {
+            FrameSource::Calls => self.call_frames(emulator, sp, &calls),
+            // the saved R5 chain only makes sense for the stack we are on
+            FrameSource::FramePointer if active => self.lcc_frames(emulator, sp, top, &calls),
+            FrameSource::FramePointer => Vec::new(),
+        };
+
+        ui.label(
+            RichText::new(format!(
+                "R6 = x{sp:04X}{}. The stack grows toward lower addresses, so the top of the stack is at the top here.",
+                if active { "" } else { " (saved, not in use right now)" }
+            ))
+            .weak(),
+        );
+        if frames.is_empty() {
+            ui.label(
+                RichText::new(match self.source {
+                    FrameSource::FramePointer if !active => {
+                        "The frame pointer chain can only be followed on the stack in use"
+                    }
+                    FrameSource::FramePointer => "R5 doesn't point at an lcc style frame",
+                    FrameSource::Calls => "No subroutine calls in progress",
+                })
+                .weak(),
+            );
+        }
+        ui.separator();
+
+        let labels = &emulator.metadata.addr_to_label;
+        let first = sp.saturating_sub(self.free);
+        let last = sp.saturating_add(self.depth).min(top);
+        let mut shown_frame = None;
+
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            egui::Grid::new("stack_grid")
+                .num_columns(5)
+                .striped(false)
+                .show(ui, |ui| {
+                    for title in ["Frame", "Address", "Value", "", ""] {
+                        ui.label(RichText::new(title).monospace().strong());
+                    }
+                    ui.end_row();
+
+                    for address in first..=last {
+                        let value = emulator.memory[address as usize].get();
+                        let frame = frames
+                            .iter()
+                            .position(|frame| frame.range.contains(&address));
+                        let free = address < sp;
+
+                        // band with the frame's name on its first row
+                        match frame {
+                            Some(i) => {
+                                let name = &frames[i].name;
+                                let (rect, response) = ui.allocate_exact_size(
+                                    egui::vec2(180.0, ui.spacing().interact_size.y),
+                                    egui::Sense::hover(),
+                                );
+                                ui.painter().rect_filled(
+                                    rect,
+                                    0.0,
+                                    color_for(name).gamma_multiply(0.4),
+                                );
+                                if shown_frame != Some(i) {
+                                    ui.painter().text(
+                                        rect.left_center() + egui::vec2(4.0, 0.0),
+                                        egui::Align2::LEFT_CENTER,
+                                        name,
+                                        egui::FontId::monospace(11.0),
+                                        ui.visuals().strong_text_color(),
+                                    );
+                                }
+                                response.on_hover_text(name);
+                                shown_frame = Some(i);
+                            }
+                            None => {
+                                ui.label("");
+                            }
+                        }
+
+                        let mut address_text = with_label(address, labels);
+                        if address == sp {
+                            address_text.push_str(" ← R6");
+                        }
+                        if active && address == emulator.r[5].get() {
+                            address_text.push_str(" ← R5");
+                        }
+                        let address_text = RichText::new(address_text).monospace();
+                        ui.label(if free {
+                            address_text.weak()
+                        } else {
+                            address_text.strong()
+                        });
+
+                        let value_text =
+                            RichText::new(format!("x{value:04X} {:>6}", value as i16)).monospace();
+                        ui.label(if free { value_text.weak() } else { value_text });
+
+                        // what the value might be pointing at
+                        ui.label(
+                            RichText::new(
+                                labels
+                                    .get(&(value as usize))
+                                    .map_or("", |label| label.as_str()),
+                            )
+                            .monospace()
+                            .color(theme.opcode_color),
+                        );
+
+                        let role = if free {
+                            Some("free")
+                        } else {
+                            frame
+                                .and_then(|i| frames[i].slots.get(&address))
+                                .map(String::as_str)
+                        };
+                        ui.label(RichText::new(role.unwrap_or("")).italics());
+                        ui.end_row();
+                    }
+                });
+        });
+    }
+
+    fn title(&self) -> String {
+        "Stack".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Stack".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Stack(
+                StackPane::default(),
+            )))),
+        )
+    }
+}
@@ -0,0 +1,482 @@
+//! A small VT100ish terminal so programs can draw menus and games with escape codes.
+//!
+//! Supported:
+//! - `\r`, `\n`, backspace and tab
+//! - `ESC[nA` `ESC[nB` `ESC[nC` `ESC[nD` cursor movement, `ESC[r;cH` / `ESC[r;cf` cursor position
+//! - `ESC[nJ` clear screen, `ESC[nK` clear line
+//! - `ESC[...m` bold, underline, inverse and the 16 colours
+//! - `ESC[s` `ESC[u` `ESC7` `ESC8` save and restore the cursor, `ESC[?25l` / `ESC[?25h` hide and show it
+//!
+//! Anything else is swallowed so at least it doesn't make a mess.
+
+use egui::text::LayoutJob;
+use egui::{Color32, FontId, Stroke, TextFormat};
+
+use crate::theme::ThemeSettings;
+
+/// Size of the screen the cursor can move around in. Anything that scrolls off the top is kept as scrollback.
+pub const TERMINAL_ROWS: usize = 24;
+pub const TERMINAL_COLS: usize = 80;
+/// How many lines of scrollback to keep before we start forgetting
+const MAX_SCROLLBACK: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TermColor {
+    #[default]
+    Default,
+    /// 0-7 normal, 8-15 bright
+    Ansi(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CellStyle {
+    pub fg: TermColor,
+    pub bg: TermColor,
+    pub bold: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalCell {
+    pub ch: char,
+    pub style: CellStyle,
+}
+
+impl Default for TerminalCell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            style: CellStyle::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+enum ParseState {
+    #[default]
+    Ground,
+    /// Just seen ESC
+    Escape,
+    /// In `ESC[`, collecting the parameters
+    Csi(String),
+    /// `ESC(` or `ESC)` picks a character set, we only have the one so skip the next char
+    Charset,
+}
+
+type Line = Vec<TerminalCell>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Terminal {
+    scrollback: Vec<Line>,
+    /// Always [`TERMINAL_ROWS`] lines, lines are only as long as what has been written to them
+    screen: Vec<Line>,
+    cursor_row: usize,
+    cursor_col: usize,
+    saved_cursor: (usize, usize),
+    pub cursor_visible: bool,
+    style: CellStyle,
+    state: ParseState,
+    /// The output we have already fed in, so we only process what is new each frame
+    fed: String,
+}
+
+impl Default for Terminal {
+    fn default() -> Self {
+        Self {
+            scrollback: Vec::new(),
+            screen: vec![Vec::new(); TERMINAL_ROWS],
+            cursor_row: 0,
+            cursor_col: 0,
+            saved_cursor: (0, 0),
+            cursor_visible: true,
+            style: CellStyle::default(),
+            state: ParseState::Ground,
+            fed: String::new(),
+        }
+    }
+}
+
+impl Terminal {
+    /// Catch up with the emulator output. If the output was cleared or replaced we start over.
+    pub fn sync(&mut self, output: &str) {
+        if !output.starts_with(&self.fed) {
+            *self = Self::default();
+        }
+        let new = &output[self.fed.len()..];
+        if new.is_empty() {
+            return;
+        }
+        for c in new.chars() {
+            self.put(c);
+        }
+        self.fed.push_str(new);
+    }
+
+    /// Where the cursor is on the screen (row, col)
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cursor_row, self.cursor_col)
+    }
+
+    /// Process one char of output
+    pub fn put(&mut self, c: char) {
+        match std::mem::take(&mut self.state) {
+            ParseState::Ground => self.put_ground(c),
+            ParseState::Escape => match c {
+                '[' => self.state = ParseState::Csi(String::new()),
+                '(' | ')' => self.state = ParseState::Charset,
+                '7' => self.saved_cursor = (self.cursor_row, self.cursor_col),
+                '8' => (self.cursor_row, self.cursor_col) = self.saved_cursor,
+                'c' => {
+                    let fed = std::mem::take(&mut self.fed);
+                    *self = Self {
+                        fed,
+                        ..Self::default()
+                    };
+                }
+                _ => {} // unsupported, drop it
+            },
+            ParseState::Charset => {}
+            ParseState::Csi(mut params) => {
+                if c.is_ascii_digit() || c == ';' || c == '?' {
+                    params.push(c);
+                    self.state = ParseState::Csi(params);
+                } else {
+                    self.csi(&params, c);
+                }
+            }
+        }
+    }
+
+    fn put_ground(&mut self, c: char) {
+        match c {
+            '\x1b' => self.state = ParseState::Escape,
+            '\n' => {
+                // LC3 programs only ever send \n so treat it like \r\n
+                self.cursor_col = 0;
+                self.line_feed();
+            }
+            '\r' => self.cursor_col = 0,
+            '\x08' => self.cursor_col = self.cursor_col.saturating_sub(1),
+            '\t' => self.cursor_col = ((self.cursor_col / 8 + 1) * 8).min(TERMINAL_COLS - 1),
+            '\x07' => {} // bell, no
+            c if c.is_control() => {}
+            c => {
+                if self.cursor_col >= TERMINAL_COLS {
+                    self.cursor_col = 0;
+                    self.line_feed();
+                }
+                let cell = TerminalCell {
+                    ch: c,
+                    style: self.style,
+                };
+                let line = &mut self.screen[self.cursor_row];
+                if line.len() <= self.cursor_col {
+                    line.resize(self.cursor_col + 1, TerminalCell::default());
+                }
+                line[self.cursor_col] = cell;
+                self.cursor_col += 1;
+            }
+        }
+    }
+
+    fn line_feed(&mut self) {
+        if self.cursor_row + 1 < TERMINAL_ROWS {
+            self.cursor_row += 1;
+        } else {
+            let top = self.screen.remove(0);
+            self.screen.push(Vec::new());
+            self.scrollback.push(top);
+            if self.scrollback.len() > MAX_SCROLLBACK {
+                self.scrollback.remove(0);
+            }
+        }
+    }
+
+    fn csi(&mut self, params: &str, command: char) {
+        if params.starts_with('?') {
+            // private modes, we only know about the cursor
+            match (params, command) {
+                ("?25", 'l') => self.cursor_visible = false,
+                ("?25", 'h') => self.cursor_visible = true,
+                _ => {}
+            }
+            return;
+        }
+
+        let args: Vec<usize> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        let arg = |i: usize| args.get(i).copied().unwrap_or(0);
+        // movement treats a missing or 0 count as 1
+        let count = arg(0).max(1);
+
+        match command {
+            'A' => self.cursor_row = self.cursor_row.saturating_sub(count),
+            'B' => self.cursor_row = (self.cursor_row + count).min(TERMINAL_ROWS - 1),
+            'C' => self.cursor_col = (self.cursor_col + count).min(TERMINAL_COLS - 1),
+            'D' => self.cursor_col = self.cursor_col.saturating_sub(count),
+            'H' | 'f' => {
+                self.cursor_row = arg(0).clamp(1, TERMINAL_ROWS) - 1;
+                self.cursor_col = arg(1).clamp(1, TERMINAL_COLS) - 1;
+            }
+            'J' => match arg(0) {
+                0 => {
+                    self.clear_line_from(self.cursor_row, self.cursor_col);
+                    for line in &mut self.screen[self.cursor_row + 1..] {
+                        line.clear();
+                    }
+                }
+                1 => {
+                    for line in &mut self.screen[..self.cursor_row] {
+                        line.clear();
+                    }
+                    self.clear_line_to(self.cursor_row, self.cursor_col);
+                }
+                2 => self.screen.iter_mut().for_each(Vec::clear),
+                3 => {
+                    self.screen.iter_mut().for_each(Vec::clear);
+                    self.scrollback.clear();
+                }
+                _ => {}
+            },
+            'K' => match arg(0) {
+                0 => self.clear_line_from(self.cursor_row, self.cursor_col),
+                1 => self.clear_line_to(self.cursor_row, self.cursor_col),
+                2 => self.screen[self.cursor_row].clear(),
+                _ => {}
+            },
+            'm' => self.sgr(&args),
+            's' => self.saved_cursor = (self.cursor_row, self.cursor_col),
+            'u' => (self.cursor_row, self.cursor_col) = self.saved_cursor,
+            _ => {} // unsupported, drop it
+        }
+    }
+
+    fn clear_line_from(&mut self, row: usize, col: usize) {
+        self.screen[row].truncate(col);
+    }
+
+    fn clear_line_to(&mut self, row: usize, col: usize) {
+        for cell in self.screen[row].iter_mut().take(col + 1) {
+            *cell = TerminalCell::default();
+        }
+    }
+
+    /// Select Graphic Rendition, the `ESC[...m` colour and style codes
+    fn sgr(&mut self, args: &[usize]) {
+        for &code in args {
+            match code {
+                0 => self.style = CellStyle::default(),
+                1 => self.style.bold = true,
+                4 => self.style.underline = true,
+                7 => self.style.inverse = true,
+                22 => self.style.bold = false,
+                24 => self.style.underline = false,
+                27 => self.style.inverse = false,
+                30..=37 => self.style.fg = TermColor::Ansi((code - 30) as u8),
+                39 => self.style.fg = TermColor::Default,
+                40..=47 => self.style.bg = TermColor::Ansi((code - 40) as u8),
+                49 => self.style.bg = TermColor::Default,
+                90..=97 => self.style.fg = TermColor::Ansi((code - 90 + 8) as u8),
+                100..=107 => self.style.bg = TermColor::Ansi((code - 100 + 8) as u8),
+                _ => {}
+            }
+        }
+    }
+
+    /// The lines worth showing: all of the scrollback then the screen down to the cursor or the last line with text on it
+    fn visible_lines(&self) -> impl Iterator<Item = &Line> {
+        let last_used = self
+            .screen
+            .iter()
+            .rposition(|line| !line.is_empty())
+            .unwrap_or(0)
+            .max(self.cursor_row);
+        self.scrollback
+            .iter()
+            .chain(self.screen[..=last_used].iter())
+    }
+
+    /// What you would get by selecting it all, without any of the escape codes
+    pub fn plain_text(&self) -> String {
+        self.visible_lines()
+            .map(|line| {
+                line.iter()
+                    .map(|cell| cell.ch)
+                    .collect::<String>()
+                    .trim_end()
+                    .to_string()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Turn the terminal into something egui can draw, using the theme colours
+    pub fn layout_job(&self, theme: &ThemeSettings, font_id: FontId) -> LayoutJob {
+        let mut job = LayoutJob::default();
+        let cursor_line = self.scrollback.len() + self.cursor_row;
+        let show_cursor = self.cursor_visible;
+
+        let color = |color: TermColor, bold: bool, default: Color32| match color {
+            TermColor::Default => default,
+            // bold makes the normal colours bright, like a real vt100
+            TermColor::Ansi(n) if bold && n < 8 => theme.terminal_ansi_colors[n as usize + 8],
+            TermColor::Ansi(n) => theme.terminal_ansi_colors[n as usize & 0xF],
+        };
+
+        for (i, line) in self.visible_lines().enumerate() {
+            if i > 0 {
+                job.append(
+                    "\n",
+                    0.0,
+                    TextFormat::simple(font_id.clone(), theme.terminal_text_color),
+                );
+            }
+
+            let mut cells = line.clone();
+            if show_cursor && i == cursor_line && cells.len() <= self.cursor_col {
+                cells.resize(self.cursor_col + 1, TerminalCell::default());
+            }
+
+            // Group runs of the same style so we don't end up with a section per char
+            let mut start = 0;
+            while start < cells.len() {
+                let is_cursor =
+                    |col: usize| show_cursor && i == cursor_line && col == self.cursor_col;
+                let style = cells[start].style;
+                let mut end = start + 1;
+                while end < cells.len()
+                    && cells[end].style == style
+                    && !is_cursor(end)
+                    && !is_cursor(start)
+                {
+                    end += 1;
+                }
+
+                let mut fg = color(style.fg, style.bold, theme.terminal_text_color);
+                let mut bg = color(style.bg, false, Color32::TRANSPARENT);
+                if style.inverse {
+                    let bg_or_default = if bg == Color32::TRANSPARENT {
+                        theme.terminal_bg_color
+                    } else {
+                        bg
+                    };
+                    (fg, bg) = (bg_or_default, fg);
+                }
+                if is_cursor(start) {
+                    bg = theme.terminal_cursor_color;
+                }
+
+                let text: String = cells[start..end].iter().map(|cell| cell.ch).collect();
+                job.append(
+                    &text,
+                    0.0,
+                    TextFormat {
+                        font_id: font_id.clone(),
+                        color: fg,
+                        background: bg,
+                        underline: if style.underline {
+                            Stroke::new(1.0, fg)
+                        } else {
+                            Stroke::NONE
+                        },
+                        ..Default::default()
+                    },
+                );
+                start = end;
+            }
+        }
+
+        job
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulator::{parse::ParseOutput, Emulator};
+
+    fn term(output: &str) -> Terminal {
+        let mut terminal = Terminal::default();
+        terminal.sync(output);
+        terminal
+    }
+
+    #[test]
+    fn test_plain_output_and_carriage_return() {
+        let terminal = term("Hello\nWorld\rw");
+        assert_eq!(terminal.plain_text(), "Hello\nworld");
+        assert_eq!(terminal.cursor(), (1, 1));
+    }
+
+    #[test]
+    fn test_clear_screen_and_cursor_position() {
+        let terminal = term("junk\njunk\n\x1b[2J\x1b[H1) Play\x1b[2;1H2) Quit\x1b[1;4HPLAY");
+        assert_eq!(terminal.plain_text(), "1) PLAY\n2) Quit");
+    }
+
+    #[test]
+    fn test_cursor_movement_and_clear_line() {
+        let terminal = term("abcdef\x1b[3D\x1b[K!\x1b[A\x1b[2Cx");
+        // moving up from the first row stays on the first row
+        assert_eq!(terminal.plain_text(), "abc!  x");
+    }
+
+    #[test]
+    fn test_colours() {
+        let terminal = term("\x1b[1;31mred\x1b[0m plain \x1b[44mblue");
+        let line = &terminal.screen[0];
+        assert_eq!(line[0].style.fg, TermColor::Ansi(1));
+        assert!(line[0].style.bold);
+        assert_eq!(line[3].style, CellStyle::default());
+        assert_eq!(line[10].style.bg, TermColor::Ansi(4));
+        assert_eq!(terminal.plain_text(), "red plain blue");
+    }
+
+    #[test]
+    fn test_scrolling_and_resync() {
+        let output: String = (0..30).map(|i| format!("line {i}\n")).collect();
+        let mut terminal = term(&output);
+        assert!(terminal.plain_text().starts_with("line 0\n"));
+        assert_eq!(terminal.cursor(), (TERMINAL_ROWS - 1, 0));
+
+        // output got cleared so we start again
+        terminal.sync("new");
+        assert_eq!(terminal.plain_text(), "new");
+    }
+
+    #[test]
+    fn test_unknown_sequences_are_dropped() {
+        let terminal = term("a\x1b[5nb\x1b(Bc\x1bZd");
+        assert_eq!(terminal.plain_text(), "abcd");
+    }
+
+    #[test]
+    fn test_escape_codes_from_a_program() {
+        let mut machine_state = Emulator::new();
+        let ParseOutput {
+            machine_code,
+            orig_address,
+            ..
+        } = Emulator::parse_program(
+            r#".ORIG x3000
+            LEA R0, MSG
+            PUTS
+            HALT
+            MSG .STRINGZ "junk\e[2J\e[H\e[32mMenu"
+            .END"#,
+            None,
+        )
+        .unwrap();
+        machine_state.flash_memory(machine_code, orig_address);
+        assert_eq!(machine_state.memory[orig_address + 7].get(), 0x1B);
+
+        assert!(machine_state.run(Some(10000)).is_ok());
+
+        let terminal = term(&machine_state.output);
+        assert!(
+            terminal.plain_text().starts_with("Menu"),
+            "Screen should have been cleared, got {:?}",
+            terminal.plain_text()
+        );
+    }
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+
+use crate::emulator::access_log::{AccessKind, MemoryAccess, MAX_ACCESSES};
+use crate::emulator::regions::parse_address;
+use crate::emulator::Emulator;
+use crate::panes::{Pane, PaneDisplay, PaneTree, RealPane};
+use crate::theme::ThemeSettings;
+use egui::RichText;
+use egui_extras::{Column, TableBuilder};
+use serde::{Deserialize, Serialize};
+
+use super::plot::{step_plot, Series};
+use super::EmulatorPane;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum SortBy {
+    Time,
+    Address,
+    Pc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+struct Filter {
+    /// Address, label or a range of them like `BUFFER-BUFFER_END`
+    address: String,
+    /// Same as address but for the instruction doing the access
+    pc: String,
+    reads: bool,
+    writes: bool,
+    /// Instruction count range, `to` of 0 means up to now
+    from: u64,
+    to: u64,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self {
+            address: String::new(),
+            pc: String::new(),
+            reads: true,
+            writes: true,
+            from: 0,
+            to: 0,
+        }
+    }
+}
+
+/// `A` or `A-B` where A and B are addresses or labels. Empty means everything
+fn parse_range(text: &str, labels: &HashMap<String, usize>) -> Result<RangeInclusive<u16>, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Ok(0..=u16::MAX);
+    }
+    match text.split_once('-') {
+        Some((start, end)) => {
+            Ok(parse_address(start.trim(), labels)?..=parse_address(end.trim(), labels)?)
+        }
+        None => {
+            let address = parse_address(text, labels)?;
+            Ok(address..=address)
+        }
+    }
+}
+
+impl Filter {
+    /// Which accesses to show, oldest first
+    fn apply<'a>(
+        &self,
+        accesses: impl Iterator<Item = &'a MemoryAccess>,
+        labels: &HashMap<String, usize>,
+    ) -> Result<Vec<MemoryAccess>, String> {
+        let addresses = parse_range(&self.address, labels)?;
+        let pcs = parse_range(&self.pc, labels)?;
+        let to = if self.to == 0 { u64::MAX } else { self.to };
+        Ok(accesses
+            .filter(|access| match access.kind {
+                AccessKind::Read => self.reads,
+                AccessKind::Write => self.writes,
+            })
+            .filter(|access| addresses.contains(&access.address) && pcs.contains(&access.pc))
+            .filter(|access| (self.from..=to).contains(&access.instruction))
+            .copied()
+            .collect())
+    }
+}
+
+/// Every memory read and write, to find out what touched an address and when
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimelinePane {
+    filter: Filter,
+    sort_by: SortBy,
+    descending: bool,
+    /// Address whose value gets plotted
+    plotted: Option<u16>,
+    signed: bool,
+    /// Filtered and sorted accesses
+    #[serde(skip)]
+    rows: Vec<MemoryAccess>,
+    /// Why the filter doesn't make sense
+    #[serde(skip)]
+    filter_error: Option<String>,
+    /// What `rows` was made from: the filter, sort and how many accesses the log had seen
+    #[serde(skip)]
+    rows_for: Option<(Filter, SortBy, bool, u64, usize)>,
+}
+
+impl Default for TimelinePane {
+    fn default() -> Self {
+        Self {
+            filter: Filter::default(),
+            sort_by: SortBy::Time,
+            descending: true,
+            plotted: None,
+            signed: false,
+            rows: Vec::new(),
+            filter_error: None,
+            rows_for: None,
+        }
+    }
+}
+
+impl TimelinePane {
+    fn refresh_rows(&mut self, emulator: &Emulator) {
+        let log = &emulator.access_log;
+        let key = (
+            self.filter.clone(),
+            self.sort_by,
+            self.descending,
+            log.pushed,
+            log.accesses.len(),
+        );
+        if self.rows_for.as_ref() == Some(&key) {
+            return;
+        }
+        self.rows_for = Some(key);
+        match self
+            .filter
+            .apply(log.accesses.iter(), &emulator.metadata.labels)
+        {
+            Ok(rows) => {
+                self.rows = rows;
+                self.filter_error = None;
+            }
+            Err(e) => {
+                self.rows.clear();
+                self.filter_error = Some(e);
+                return;
+            }
+        }
+        // stable sort so things at the same address/pc stay in time order
+        match self.sort_by {
+            SortBy::Time => {}
+            SortBy::Address => self.rows.sort_by_key(|access| access.address),
+            SortBy::Pc => self.rows.sort_by_key(|access| access.pc),
+        }
+        if self.descending {
+            self.rows.reverse();
+        }
+    }
+
+    fn controls(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator) {
+        ui.horizontal_wrapped(|ui| {
+            ui.checkbox(&mut emulator.access_log.enabled, "Record")
+                .on_hover_text("Turn off to run a bit faster");
+            ui.label(format!(
+                "{} accesses (keeps the last {MAX_ACCESSES})",
+                emulator.access_log.accesses.len()
+            ));
+            if ui.button("Clear").clicked() {
+                emulator.access_log.accesses.clear();
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("Address:");
+            ui.add(egui::TextEdit::singleline(&mut self.filter.address).desired_width(90.0))
+                .on_hover_text("An address (x4000), a label, or a range like BUFFER-BUFFER_END");
+            ui.label("PC:");
+            ui.add(egui::TextEdit::singleline(&mut self.filter.pc).desired_width(90.0))
+                .on_hover_text("Only accesses made by instructions at these addresses");
+            ui.checkbox(&mut self.filter.reads, "Reads");
+            ui.checkbox(&mut self.filter.writes, "Writes");
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.label("From #");
+            ui.add(egui::DragValue::new(&mut self.filter.from));
+            ui.label("to #");
+            ui.add(egui::DragValue::new(&mut self.filter.to))
+                .on_hover_text("0 means up to now");
+            ui.separator();
+            ui.label("Sort by:");
+            ui.radio_value(&mut self.sort_by, SortBy::Time, "Time");
+            ui.radio_value(&mut self.sort_by, SortBy::Address, "Address");
+            ui.radio_value(&mut self.sort_by, SortBy::Pc, "PC");
+            ui.checkbox(&mut self.descending, "Newest/highest first");
+        });
+    }
+
+    fn plot(&mut self, ui: &mut egui::Ui, emulator: &Emulator, theme: &ThemeSettings) {
+        let Some(address) = self.plotted else {
+            ui.label(RichText::new("Click an address to plot its value over time").weak());
+            return;
+        };
+        let labels = &emulator.metadata.addr_to_label;
+        let name = match labels.get(&(address as usize)) {
+            Some(label) => format!("x{address:04X} ({label})"),
+            None => format!("x{address:04X}"),
+        };
+        let (reads, writes) =
+            emulator
+                .access_log
+                .for_address(address)
+                .fold((0, 0), |(reads, writes), access| match access.kind {
+                    AccessKind::Read => (reads + 1, writes),
+                    AccessKind::Write => (reads, writes + 1),
+                });
+        ui.horizontal(|ui| {
+            ui.label(RichText::new(format!("Value of {name}")).strong());
+            ui.label(format!("{reads} reads, {writes} writes"));
+            ui.checkbox(&mut self.signed, "Signed");
+            if ui
+                .small_button("✖")
+                .on_hover_text("Stop plotting")
+                .clicked()
+            {
+                self.plotted = None;
+            }
+        });
+        let points = emulator.access_log.values_over_time(address);
+        let start = points.first().map_or(0, |&(at, _)| at);
+        step_plot(
+            ui,
+            &[Series {
+                name: &name,
+                color: theme.accent_color_positive,
+                points: &points,
+            }],
+            start,
+            emulator.instruction_count,
+            self.signed,
+            theme,
+        );
+    }
+
+    fn table(&mut self, ui: &mut egui::Ui, emulator: &Emulator, theme: &ThemeSettings) {
+        if let Some(e) = &self.filter_error {
+            ui.colored_label(theme.error_fg_color, e);
+            return;
+        }
+        let rows = &self.rows;
+        let labels = &emulator.metadata.addr_to_label;
+        let with_label = |address: u16| match labels.get(&(address as usize)) {
+            Some(label) => format!("x{address:04X} {label}"),
+            None => format!("x{address:04X}"),
+        };
+        let text_height = egui::TextStyle::Monospace.resolve(ui.style()).size * 1.5;
+
+        TableBuilder::new(ui)
+            .striped(true)
+            .resizable(true)
+            .cell_layout(egui::Layout::left_to_right(egui::Align::Center))
+            .column(Column::auto().at_least(50.0)) // instruction count
+            .column(Column::auto().at_least(80.0)) // PC
+            .column(Column::auto().at_least(30.0)) // kind
+            .column(Column::auto().at_least(80.0)) // address
+            .column(Column::auto().at_least(50.0)) // old
+            .column(Column::auto().at_least(50.0)) // new
+            .column(Column::remainder().at_least(40.0)) // cache
+            .header(20.0, |mut header| {
+                for title in ["#", "PC", "R/W", "Address", "Old", "New", "Cache"] {
+                    header.col(|ui| {
+                        ui.label(RichText::new(title).monospace().strong());
+                    });
+                }
+            })
+            .body(|body| {
+                body.rows(text_height, rows.len(), |mut row| {
+                    let access = rows[row.index()];
+                    row.col(|ui| {
+                        ui.label(RichText::new(access.instruction.to_string()).monospace());
+                    });
+                    row.col(|ui| {
+                        ui.label(RichText::new(with_label(access.pc)).monospace());
+                    });
+                    row.col(|ui| {
+                        let (text, color) = match access.kind {
+                            AccessKind::Read => ("R", theme.accent_color_positive),
+                            AccessKind::Write => ("W", theme.accent_color_negative),
+                        };
+                        ui.colored_label(color, RichText::new(text).monospace().strong());
+                    });
+                    row.col(|ui| {
+                        let selected = self.plotted == Some(access.address);
+                        if ui
+                            .selectable_label(
+                                selected,
+                                RichText::new(with_label(access.address)).monospace(),
+                            )
+                            .on_hover_text("Plot this address")
+                            .clicked()
+                        {
+                            self.plotted = Some(access.address);
+                        }
+                    });
+                    row.col(|ui| {
+                        if access.kind == AccessKind::Write {
+                            ui.label(RichText::new(format!("x{:04X}", access.old)).monospace());
+                        }
+                    });
+                    row.col(|ui| {
+                        ui.label(RichText::new(format!("x{:04X}", access.new)).monospace());
+                    });
+                    row.col(|ui| {
+                        let Some(result) = access.cache else {
+                            return;
+                        };
+                        let (text, color) = if result.hit {
+                            ("hit", theme.accent_color_positive)
+                        } else {
+                            ("miss", theme.accent_color_negative)
+                        };
+                        let label =
+                            ui.colored_label(color, RichText::new(text).monospace().strong());
+                        if let Some(evicted) = result.evicted {
+                            let written = if result.wrote_back {
+                                ", written back"
+                            } else {
+                                ""
+                            };
+                            label.on_hover_text(format!(
+                                "Set {}, evicted the block at {}{written}",
+                                result.set,
+                                with_label(evicted)
+                            ));
+                        }
+                    });
+                });
+            });
+    }
+}
+
+impl PaneDisplay for TimelinePane {
+    fn render(&mut self, ui: &mut egui::Ui, emulator: &mut Emulator, theme: &mut ThemeSettings) {
+        self.controls(ui, emulator);
+        ui.separator();
+        self.plot(ui, emulator, theme);
+        ui.separator();
+        self.refresh_rows(emulator);
+        self.table(ui, emulator, theme);
+    }
+
+    fn title(&self) -> String {
+        "Memory Timeline".to_string()
+    }
+
+    fn children() -> PaneTree {
+        PaneTree::Pane(
+            "Memory Timeline".to_string(),
+            Pane::new(RealPane::EmulatorPanes(Box::new(EmulatorPane::Timeline(
+                TimelinePane::default(),
+            )))),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(instruction: u64, pc: u16, address: u16, kind: AccessKind) -> MemoryAccess {
+        MemoryAccess {
+            instruction,
+            pc,
+            address,
+            old: 0,
+            new: 0,
+            kind,
+            cache: None,
+        }
+    }
+
+    #[test]
+    fn filters_by_label_range_and_kind() {
+        let labels = HashMap::from([
+            ("BUFFER".to_owned(), 0x4000),
+            ("BUFFER_END".to_owned(), 0x4003),
+        ]);
+        let log = [
+            access(1, 0x3000, 0x4000, AccessKind::Write),
+            access(2, 0x3001, 0x4004, AccessKind::Write),
+            access(3, 0x3002, 0x4003, AccessKind::Read),
+        ];
+        let filter = Filter {
+            address: "BUFFER-BUFFER_END".to_owned(),
+            ..Default::default()
+        };
+        let rows = filter.apply(log.iter(), &labels).unwrap();
+        assert_eq!(rows, vec![log[0], log[2]]);
+
+        let filter = Filter {
+            reads: false,
+            pc: "x3001".to_owned(),
+            ..Default::default()
+        };
+        assert_eq!(filter.apply(log.iter(), &labels).unwrap(), vec![log[1]]);
+
+        let filter = Filter {
+            address: "NOPE".to_owned(),
+            ..Default::default()
+        };
+        assert!(filter.apply(log.iter(), &labels).is_err());
+    }
+}
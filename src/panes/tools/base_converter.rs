@@ -11,6 +11,7 @@ use crate::{
 use super::ToolPanes;
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct BaseConverter {
     input: String,
     output_hist: Vec<String>,
@@ -10,6 +10,7 @@ use crate::{
 use super::ToolPanes;
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct ThemeEditorPane {
     // Holds a temporary copy of settings for editing, applied on change
     live_settings: ThemeSettings,
@@ -554,6 +555,17 @@ fn render_settings_editor(ui: &mut Ui, settings: &mut ThemeSettings) -> bool {
                 &mut settings.terminal_selection_bg_color,
             );
             changed |= render_color_setting(ui, "Link Color:", &mut settings.terminal_link_color);
+            const ANSI_NAMES: [&str; 8] = [
+                "Black", "Red", "Green", "Yellow", "Blue", "Magenta", "Cyan", "White",
+            ];
+            for (i, color) in settings.terminal_ansi_colors.iter_mut().enumerate() {
+                let bright = if i >= 8 { "Bright " } else { "" };
+                changed |= render_color_setting(
+                    ui,
+                    &format!("ANSI {bright}{}:", ANSI_NAMES[i % 8]),
+                    color,
+                );
+            }
         });
 
     egui::CollapsingHeader::new("Editor Colors")
@@ -109,6 +109,7 @@ pub struct ThemeSettings {
     pub terminal_cursor_color: Color32,
     pub terminal_selection_bg_color: Color32,
     pub terminal_link_color: Color32, // If terminal supports hyperlinks
+    pub terminal_ansi_colors: [Color32; 16], // ANSI black, red, green, yellow, blue, magenta, cyan, white then the bright versions
 
     // Editor Pane (Syntax Highlighting)
     pub editor_label_color: Color32,
@@ -259,6 +260,24 @@ impl ThemeSettings {
             terminal_cursor_color: Color32::from_rgb(0, 150, 255), // accent_color_primary
             terminal_selection_bg_color: Color32::from_rgb(0, 150, 255).linear_multiply(0.4),
             terminal_link_color: Color32::from_rgb(100, 170, 255), // hyperlink_color
+            terminal_ansi_colors: [
+                Color32::from_rgb(0, 0, 0),
+                Color32::from_rgb(205, 49, 49),
+                Color32::from_rgb(13, 188, 121),
+                Color32::from_rgb(229, 229, 16),
+                Color32::from_rgb(36, 114, 200),
+                Color32::from_rgb(188, 63, 188),
+                Color32::from_rgb(17, 168, 205),
+                Color32::from_rgb(229, 229, 229),
+                Color32::from_rgb(102, 102, 102),
+                Color32::from_rgb(241, 76, 76),
+                Color32::from_rgb(35, 209, 139),
+                Color32::from_rgb(245, 245, 67),
+                Color32::from_rgb(59, 142, 234),
+                Color32::from_rgb(214, 112, 214),
+                Color32::from_rgb(41, 184, 219),
+                Color32::from_rgb(255, 255, 255),
+            ],
 
             editor_label_color: Color32::from_rgb(220, 220, 150),
             editor_register_color: Color32::from_rgb(150, 190, 220),
@@ -407,6 +426,24 @@ impl ThemeSettings {
             terminal_cursor_color: Color32::from_rgb(0, 120, 220), // accent_color_primary
             terminal_selection_bg_color: Color32::from_rgb(0, 120, 220).linear_multiply(0.4),
             terminal_link_color: Color32::from_rgb(0, 100, 220), // hyperlink_color
+            terminal_ansi_colors: [
+                Color32::from_rgb(0, 0, 0),
+                Color32::from_rgb(205, 49, 49),
+                Color32::from_rgb(0, 138, 60),
+                Color32::from_rgb(148, 120, 0),
+                Color32::from_rgb(4, 81, 165),
+                Color32::from_rgb(188, 5, 188),
+                Color32::from_rgb(5, 128, 150),
+                Color32::from_rgb(85, 85, 85),
+                Color32::from_rgb(102, 102, 102),
+                Color32::from_rgb(230, 0, 0),
+                Color32::from_rgb(20, 160, 80),
+                Color32::from_rgb(181, 137, 0),
+                Color32::from_rgb(4, 81, 255),
+                Color32::from_rgb(220, 40, 220),
+                Color32::from_rgb(8, 160, 190),
+                Color32::from_rgb(165, 165, 165),
+            ],
 
             editor_label_color: Color32::from_rgb(150, 130, 0),
             editor_register_color: Color32::from_rgb(20, 70, 110),